@@ -0,0 +1,98 @@
+// 轴对齐包围盒（AABB），用于摄像机取景等需要知道物体范围的地方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    // 空包围盒：与任何包围盒合并都得到对方
+    pub const EMPTY: Self = Self {
+        min: glam::Vec3::INFINITY,
+        max: glam::Vec3::NEG_INFINITY,
+    };
+
+    pub fn new(min: glam::Vec3, max: glam::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = glam::Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| Self::new(aabb.min.min(p), aabb.max.max(p)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn center(&self) -> glam::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> glam::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [glam::Vec3; 8] {
+        std::array::from_fn(|i| {
+            glam::Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    // 变换后的包围盒：变换 8 个角点后重新求包围盒
+    pub fn transform(&self, matrix: &glam::Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points(self.corners().map(|c| matrix.transform_point3(c)))
+    }
+}
+
+// 视锥体：6 个平面 (normal, d)，满足 dot(normal, p) + d >= 0 的点在平面内侧
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    // 从观察投影矩阵的行向量中提取平面（Gribb-Hartmann 方法）。
+    // wgpu 的裁剪空间深度范围是 [0, w]，所以近平面直接取第三行。
+    // 对 reverse-Z 同样成立：约束仍然是 0 <= z <= w，只是近远两面交换了
+    pub fn from_view_projection(view_proj: &glam::Mat4) -> Self {
+        let row = |i| view_proj.row(i);
+        let planes = [
+            row(3) + row(0), // 左
+            row(3) - row(0), // 右
+            row(3) + row(1), // 下
+            row(3) - row(1), // 上
+            row(2),          // z >= 0
+            row(3) - row(2), // z <= w
+        ]
+        .map(|plane| {
+            // 无限远投影的远平面退化为法线为零的平面（总是通过测试），保持原样即可
+            let length = plane.truncate().length();
+            if length > f32::EPSILON { plane / length } else { plane }
+        });
+        Self { planes }
+    }
+
+    // 包围盒与视锥体相交（或在内部）时返回 true。
+    // 对每个平面只测试沿法线方向最远的那个角点，可能把少量视锥外的包围盒误判为可见，但不会漏判
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let farthest = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(farthest) + plane.w >= 0.0
+        })
+    }
+}
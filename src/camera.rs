@@ -1,23 +1,156 @@
-use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{Key, KeyCode, NamedKey, PhysicalKey}};
+use std::time::Duration;
+
+use crate::bounds::Aabb;
+
+use winit::{event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent}, keyboard::{Key, KeyCode, NamedKey, PhysicalKey}};
+
+// 投影方式，与观察矩阵（eye/target/up）相互独立。角度使用弧度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective {
+        fovy: f32,
+        znear: f32,
+        zfar: f32,
+    },
+    // height 是视口在世界空间中的高度，宽度由宽高比决定
+    Orthographic {
+        height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+    // 远平面在无穷远处，深度反转（近处为 1，远处趋近于 0），浮点深度的精度分布更均匀
+    InfinitePerspectiveReverseZ {
+        fovy: f32,
+        znear: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fovy: 45f32.to_radians(),
+            znear: 0.1,
+            zfar: Self::DEFAULT_ZFAR,
+        }
+    }
+}
+
+impl Projection {
+    pub const DEFAULT_ZFAR: f32 = 100.0;
+
+    pub fn matrix(&self, aspect: f32) -> glam::Mat4 {
+        match *self {
+            Projection::Perspective { fovy, znear, zfar } => glam::Mat4::perspective_rh(fovy, aspect, znear, zfar),
+            Projection::Orthographic { height, znear, zfar } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+                glam::Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, znear, zfar)
+            }
+            Projection::InfinitePerspectiveReverseZ { fovy, znear } => {
+                glam::Mat4::perspective_infinite_reverse_rh(fovy, aspect, znear)
+            }
+        }
+    }
+
+    // 近平面和远平面到摄像机的距离，无限远投影的远平面是 f32::INFINITY
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { znear, zfar, .. } | Projection::Orthographic { znear, zfar, .. } => (znear, zfar),
+            Projection::InfinitePerspectiveReverseZ { znear, .. } => (znear, f32::INFINITY),
+        }
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        matches!(self, Projection::InfinitePerspectiveReverseZ { .. })
+    }
+
+    // 观察空间深度为 depth 处视锥截面的半高
+    fn half_height_at(&self, depth: f32) -> f32 {
+        match *self {
+            Projection::Perspective { fovy, .. } | Projection::InfinitePerspectiveReverseZ { fovy, .. } => {
+                depth * (fovy * 0.5).tan()
+            }
+            Projection::Orthographic { height, .. } => height * 0.5,
+        }
+    }
+}
 
 pub struct Camera {
     pub eye: glam::Vec3,
     pub target: glam::Vec3,
     pub up: glam::Vec3,
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32
+    // 宽高比，State::resize 会自动更新
+    pub aspect: f32,
+    pub projection: Projection,
 }
 
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> glam::Mat4 {
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    pub fn build_view_matrix(&self) -> glam::Mat4 {
         // 旋转世界坐标到到摄像机所观察的位置，本质是摄像机变换的逆矩阵
-        let view = glam::Mat4::look_at_rh(self.eye, self.target, self.up);
+        glam::Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn build_projection_matrix(&self) -> glam::Mat4 {
         // 变换场景空间，产生景深效果
-        let proj = glam::Mat4::perspective_rh(self.fovy.to_radians(),self.aspect,self.znear,self.zfar);
+        self.projection.matrix(self.aspect)
+    }
 
-        proj * view
+    pub fn depth_range(&self) -> (f32, f32) {
+        self.projection.depth_range()
+    }
+
+    // 视锥体在观察空间深度 [near, far] 之间部分的 8 个角点（世界空间）
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [glam::Vec3; 8] {
+        let inv_view = self.build_view_matrix().inverse();
+        let mut corners = [glam::Vec3::ZERO; 8];
+        for (i, depth) in [near, far].into_iter().enumerate() {
+            let y = self.projection.half_height_at(depth);
+            let x = y * self.aspect;
+            corners[i * 4] = inv_view.transform_point3(glam::Vec3::new(-x, -y, -depth));
+            corners[i * 4 + 1] = inv_view.transform_point3(glam::Vec3::new(x, -y, -depth));
+            corners[i * 4 + 2] = inv_view.transform_point3(glam::Vec3::new(x, y, -depth));
+            corners[i * 4 + 3] = inv_view.transform_point3(glam::Vec3::new(-x, y, -depth));
+        }
+        corners
+    }
+
+    // 保持观察方向不变，移动摄像机使包围盒的外接球刚好完整出现在视野中，需要时扩展远平面
+    pub fn frame(&mut self, bounds: &Aabb) {
+        if bounds.is_empty() {
+            return;
+        }
+        let center = bounds.center();
+        let radius = bounds.half_extents().length().max(f32::EPSILON);
+        let (znear, _) = self.depth_range();
+        // 取水平和垂直视野中较小的一个
+        let fit_distance = |fovy: f32| {
+            let half_fovy = fovy * 0.5;
+            let half_fovx = (half_fovy.tan() * self.aspect).atan();
+            radius / half_fovy.min(half_fovx).sin()
+        };
+        // 远平面不够远时向外扩展，包围球的背面不会被裁掉
+        let distance = match &mut self.projection {
+            Projection::Perspective { fovy, zfar, .. } => {
+                let distance = fit_distance(*fovy);
+                *zfar = zfar.max(distance + radius);
+                distance
+            }
+            Projection::InfinitePerspectiveReverseZ { fovy, .. } => fit_distance(*fovy),
+            // 正交投影的大小与距离无关，调整视口高度，摄像机只需退到包围球之外
+            Projection::Orthographic { height, zfar, .. } => {
+                *height = 2.0 * radius * (1.0 / self.aspect).max(1.0);
+                *zfar = zfar.max(2.0 * radius + znear);
+                radius + znear
+            }
+        };
+
+        let direction = (self.eye - self.target).try_normalize().unwrap_or(glam::Vec3::Z);
+        self.target = center;
+        self.eye = center + direction * distance;
     }
 
     pub fn new(aspect:f32) -> Self {
@@ -26,101 +159,303 @@ impl Camera {
             // 看向原点
             target: (0.0,1.0,0.0).into(),
             up: glam::Vec3::Y,
-            aspect: aspect,
-            fovy: 45.0,
-            zfar: 100.0,
-            znear:0.1
+            aspect,
+            projection: Projection::default(),
         }
     }
 }
 
-pub struct CameraController {
+// 摄像机控制器的公共接口，State 通过它把输入交给当前使用的控制器
+pub trait CameraControl {
+    // 返回事件是否被控制器使用
+    fn process_events(&mut self, event: &WindowEvent) -> bool;
+    // 原始鼠标位移（DeviceEvent::MouseMotion）
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64);
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration);
+    // 需要捕获光标的控制器（如第一人称）才会返回 true
+    fn is_mouse_captured(&self) -> bool {
+        false
+    }
+    fn set_mouse_captured(&mut self, _captured: bool) {}
+}
+
+// 俯仰角略小于 90°，避免视线与 up 平行导致 look_at 退化
+const SAFE_FRAC_PI_2: f32 = std::f32::consts::FRAC_PI_2 - 0.0001;
+
+// 第一人称摄像机控制器：鼠标控制偏航角（yaw）和俯仰角（pitch），WASD 水平移动，
+// Space/Shift 升降，滚轮调整移动速度。移动量按经过的时间计算，与帧率无关。
+// 鼠标左键捕获光标，Tab 切换捕获状态；只有捕获光标时鼠标移动才会转动视角
+pub struct FpsCameraController {
+    // 每秒移动的距离
     speed: f32,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool
+    // 鼠标每移动一个像素转动的弧度
+    sensitivity: f32,
+    amount_forward: f32,
+    amount_backward: f32,
+    amount_left: f32,
+    amount_right: f32,
+    amount_up: f32,
+    amount_down: f32,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+    mouse_captured: bool,
 }
 
-impl CameraController {
-    pub fn new(speed: f32)-> Self{
+impl FpsCameraController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
             speed,
-            is_backward_pressed: false,
-            is_forward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false
+            sensitivity,
+            amount_forward: 0.0,
+            amount_backward: 0.0,
+            amount_left: 0.0,
+            amount_right: 0.0,
+            amount_up: 0.0,
+            amount_down: 0.0,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            mouse_captured: false,
         }
     }
 
-    pub fn process_events(&mut self,event: &WindowEvent) -> bool {
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn is_mouse_captured(&self) -> bool {
+        self.mouse_captured
+    }
+
+    pub fn set_mouse_captured(&mut self, captured: bool) {
+        self.mouse_captured = captured;
+        // 释放光标时丢弃还没处理的转动
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+    }
+
+    // 返回按键是否被控制器使用
+    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+        let amount = if state == ElementState::Pressed { 1.0 } else { 0.0 };
+        match key {
+            KeyCode::KeyW | KeyCode::ArrowUp => self.amount_forward = amount,
+            KeyCode::KeyS | KeyCode::ArrowDown => self.amount_backward = amount,
+            KeyCode::KeyA | KeyCode::ArrowLeft => self.amount_left = amount,
+            KeyCode::KeyD | KeyCode::ArrowRight => self.amount_right = amount,
+            KeyCode::Space => self.amount_up = amount,
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => self.amount_down = amount,
+            KeyCode::Tab => {
+                if state == ElementState::Pressed {
+                    self.set_mouse_captured(!self.mouse_captured);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // 鼠标移动来自 DeviceEvent::MouseMotion，是未经加速的原始位移
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.mouse_captured {
+            self.rotate_horizontal += dx as f32;
+            self.rotate_vertical += dy as f32;
+        }
+    }
+
+    // 滚轮每滚动一格速度变为 1.2 倍
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            // 触控板按像素滚动，大约 20 像素算一格
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+        };
+        self.speed = (self.speed * 1.2f32.powf(lines)).clamp(0.1, 1000.0);
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
-            WindowEvent::KeyboardInput {  event: 
-                KeyEvent{
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
                     state,
-                    physical_key,
+                    physical_key: PhysicalKey::Code(key),
                     ..
-                }, 
-                .. 
+                },
+                ..
+            } => self.process_keyboard(*key, *state),
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.process_scroll(delta);
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
             } => {
-                let is_pressed = *state == ElementState::Pressed;
-               
-                match physical_key {
-                    PhysicalKey::Code(KeyCode::KeyW) | PhysicalKey::Code(KeyCode::ArrowUp) => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyA) | PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyS) | PhysicalKey::Code(KeyCode::ArrowDown) => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyD) | PhysicalKey::Code(KeyCode::ArrowRight) => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
+                self.set_mouse_captured(true);
+                true
             }
-
-            _ => false
+            _ => false,
         }
     }
 
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
 
-    pub fn update_camera(&self, camera: &mut Camera) {
-        let forward = camera.target - camera.eye;
-        // 归一化,方便后面计算步长
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.length();
+        // 从当前视线方向求出偏航角和俯仰角，这样外部直接修改摄像机也不会冲突
+        let offset = camera.target - camera.eye;
+        let distance = offset.length().max(f32::EPSILON);
+        let direction = offset / distance;
+        let mut yaw = direction.z.atan2(direction.x);
+        let mut pitch = direction.y.clamp(-1.0, 1.0).asin();
 
-        // 防止摄像机离场景中心太近时出现问题
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+        yaw += self.rotate_horizontal * self.sensitivity;
+        pitch = (pitch - self.rotate_vertical * self.sensitivity).clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+
+        // 前后左右只在水平面内移动，升降沿世界的 Y 轴
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let forward = glam::Vec3::new(yaw_cos, 0.0, yaw_sin);
+        let right = glam::Vec3::new(-yaw_sin, 0.0, yaw_cos);
+        camera.eye += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
+        camera.eye += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        camera.eye += glam::Vec3::Y * (self.amount_up - self.amount_down) * self.speed * dt;
+
+        let (pitch_sin, pitch_cos) = pitch.sin_cos();
+        let direction = glam::Vec3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin);
+        camera.target = camera.eye + direction * distance;
+    }
+}
+
+impl CameraControl for FpsCameraController {
+    fn process_events(&mut self, event: &WindowEvent) -> bool {
+        FpsCameraController::process_events(self, event)
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        FpsCameraController::process_mouse_motion(self, dx, dy)
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        FpsCameraController::update_camera(self, camera, dt)
+    }
+
+    fn is_mouse_captured(&self) -> bool {
+        FpsCameraController::is_mouse_captured(self)
+    }
+
+    fn set_mouse_captured(&mut self, captured: bool) {
+        FpsCameraController::set_mouse_captured(self, captured)
+    }
+}
+
+// 轨道（arcball）控制器：摄像机始终看向 target，在以 target 为球心的球面上移动。
+// 左键拖动旋转，中键拖动平移 target，滚轮推拉距离。
+// 角度直接由 eye 相对 target 的球坐标计算，不会像旧控制器那样漂移，俯仰角被限制在 ±90° 以内
+pub struct OrbitCameraController {
+    // 鼠标每移动一个像素转动的弧度
+    sensitivity: f32,
+    min_distance: f32,
+    max_distance: f32,
+    is_rotating: bool,
+    is_panning: bool,
+    rotate: glam::Vec2,
+    pan: glam::Vec2,
+    scroll: f32,
+}
+
+impl OrbitCameraController {
+    pub fn new(sensitivity: f32) -> Self {
+        Self {
+            sensitivity,
+            min_distance: 0.1,
+            max_distance: 500.0,
+            is_rotating: false,
+            is_panning: false,
+            rotate: glam::Vec2::ZERO,
+            pan: glam::Vec2::ZERO,
+            scroll: 0.0,
         }
+    }
+
+    pub fn set_distance_limits(&mut self, min_distance: f32, max_distance: f32) {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+    }
+
+    pub fn set_rotating(&mut self, rotating: bool) {
+        self.is_rotating = rotating;
+    }
+
+    pub fn set_panning(&mut self, panning: bool) {
+        self.is_panning = panning;
+    }
+
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+        };
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera) {
+        let offset = camera.eye - camera.target;
+        // 每滚动一格距离缩短 10%
+        let distance = (offset.length() * 0.9f32.powf(self.scroll))
+            .clamp(self.min_distance, self.max_distance);
+        let direction = offset.try_normalize().unwrap_or(glam::Vec3::Z);
 
-        // 获取法向量
-        let right = forward_norm.cross(camera.up);
+        // 平移量与距离成正比，这样远近不同时拖动的手感一致
+        let forward = -direction;
+        let right = forward.cross(glam::Vec3::Y).try_normalize().unwrap_or(glam::Vec3::X);
+        let up = right.cross(forward);
+        camera.target += (up * self.pan.y - right * self.pan.x) * self.sensitivity * distance;
 
-        // 在按下前进或后退键时重做半径计算
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.length();
+        let mut yaw = direction.x.atan2(direction.z);
+        let mut pitch = direction.y.clamp(-1.0, 1.0).asin();
+        yaw -= self.rotate.x * self.sensitivity;
+        pitch = (pitch + self.rotate.y * self.sensitivity).clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
 
-        if self.is_right_pressed {
-            // 重新调整目标和眼睛之间的距离，以便其不发生变化。
-            // 因此，眼睛仍然位于目标和眼睛形成的圆圈上。
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = pitch.sin_cos();
+        let direction = glam::Vec3::new(pitch_cos * yaw_sin, pitch_sin, pitch_cos * yaw_cos);
+        camera.eye = camera.target + direction * distance;
+        camera.up = glam::Vec3::Y;
+
+        self.rotate = glam::Vec2::ZERO;
+        self.pan = glam::Vec2::ZERO;
+        self.scroll = 0.0;
+    }
+}
+
+impl CameraControl for OrbitCameraController {
+    fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.is_rotating = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Middle, .. } => {
+                self.is_panning = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.process_scroll(delta);
+                true
+            }
+            _ => false,
         }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        let delta = glam::Vec2::new(dx as f32, dy as f32);
+        if self.is_rotating {
+            self.rotate += delta;
+        } else if self.is_panning {
+            self.pan += delta;
         }
+    }
 
-         
+    fn update_camera(&mut self, camera: &mut Camera, _dt: Duration) {
+        OrbitCameraController::update_camera(self, camera)
     }
-}
\ No newline at end of file
+}
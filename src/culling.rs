@@ -0,0 +1,557 @@
+// 视锥剔除：每帧在 CPU 上测试每个实例的每个网格，把可见的实例数据紧凑地写入一个实例缓冲区。
+// 可见实例按 (网格, 材质) 分成若干批次，每批在缓冲区中连续存放，绘制时作为实例范围传给 draw_indexed。
+// 实例可以覆盖网格的材质，而一次绘制只能绑定一个材质，所以同一个网格会按材质拆成多次绘制
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    hot_reload::catch_validation,
+    instance::{InstanceManager, InstanceRaw},
+    model::Model,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub total_instances: u32,
+    // 至少有一个网格可见的实例
+    pub visible_instances: u32,
+    // 网格 × 实例的绘制数量
+    pub total_draws: u32,
+    pub visible_draws: u32,
+}
+
+impl CullingStats {
+    pub fn culled_instances(&self) -> u32 {
+        self.total_instances - self.visible_instances
+    }
+
+    pub fn culled_draws(&self) -> u32 {
+        self.total_draws - self.visible_draws
+    }
+}
+
+// 用同一个材质绘制同一个网格的一组实例
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawBatch {
+    pub mesh: usize,
+    pub material: usize,
+    // 实例在可见实例缓冲区中的范围
+    pub instances: Range<u32>,
+}
+
+// 实例的材质覆盖有效时使用覆盖的材质，否则使用网格自己的材质
+pub fn effective_material(model: &Model, mesh: usize, instance: &InstanceRaw) -> usize {
+    let material = instance.material() as usize;
+    if material < model.materials.len() {
+        material
+    } else {
+        model.meshes[mesh].material
+    }
+}
+
+pub struct InstanceCuller {
+    enabled: bool,
+    buffer: wgpu::Buffer,
+    // 缓冲区能容纳的实例数量
+    capacity: usize,
+    batches: Vec<DrawBatch>,
+    stats: CullingStats,
+}
+
+impl InstanceCuller {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = 64;
+        Self {
+            enabled: true,
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            batches: Vec::new(),
+            stats: CullingStats::default(),
+        }
+    }
+
+    // 关闭后所有实例都被视为可见，便于对比剔除的效果
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn batches(&self) -> &[DrawBatch] {
+        &self.batches
+    }
+
+    pub fn stats(&self) -> CullingStats {
+        self.stats
+    }
+
+    // instances 需要已经 update 过，这里直接使用它的 raw 数据
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        model: &Model,
+        instances: &InstanceManager,
+    ) {
+        let matrices = instances.instances().iter().map(|i| i.model_matrix()).collect::<Vec<_>>();
+        let raw = instances.raw();
+        let mut instance_visible = vec![false; raw.len()];
+        let mut visible = Vec::new();
+        self.batches.clear();
+
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            // 按材质分组，BTreeMap 保证批次顺序稳定
+            let mut groups = BTreeMap::<usize, Vec<usize>>::new();
+            for (i, matrix) in matrices.iter().enumerate() {
+                if !self.enabled || frustum.intersects_aabb(&mesh.bounds.transform(matrix)) {
+                    groups.entry(effective_material(model, mesh_index, &raw[i])).or_default().push(i);
+                    instance_visible[i] = true;
+                }
+            }
+            for (material, indices) in groups {
+                let start = visible.len() as u32;
+                visible.extend(indices.iter().map(|&i| raw[i]));
+                self.batches.push(DrawBatch {
+                    mesh: mesh_index,
+                    material,
+                    instances: start..visible.len() as u32,
+                });
+            }
+        }
+
+        self.stats = CullingStats {
+            total_instances: raw.len() as u32,
+            visible_instances: instance_visible.iter().filter(|&&v| v).count() as u32,
+            total_draws: (raw.len() * model.meshes.len()) as u32,
+            visible_draws: visible.len() as u32,
+        };
+
+        // 容量不足时按 2 的幂扩容
+        if visible.len() > self.capacity {
+            self.capacity = visible.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if !visible.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&visible));
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible Instance Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullConfigUniform {
+    planes: [[f32; 4]; 6],
+    instance_count: u32,
+    slot_count: u32,
+    material_count: u32,
+    _padding: u32,
+}
+
+// 一个 (网格, 材质) 组合：网格的包围盒，网格自己的材质，这一组使用的材质和它在输出缓冲区中分段的起点
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawSlotRaw {
+    min: [f32; 3],
+    mesh_material: u32,
+    max: [f32; 3],
+    material: u32,
+    offset: u32,
+    _padding: [u32; 3],
+}
+
+// 决定输出缓冲区布局的状态，任何一项变化都要重建缓冲区和绑定组
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GpuCullLayout {
+    instance_generation: u64,
+    instance_count: u32,
+    // 材质覆盖和使用它的实例数量，决定每个批次分段的大小
+    material_overrides: Vec<(u32, usize)>,
+}
+
+// GPU 驱动的视锥剔除：实例数据常驻 GPU，每帧由计算着色器测试每个实例，
+// 把可见实例写入输出缓冲区并填写每个批次的 DrawIndexedIndirect 参数，CPU 不需要遍历实例。
+// 批次是 (网格, 材质) 组合：每个网格的自身材质，加上实例使用的每个材质覆盖。
+// 输出缓冲区按批次分段，每段的大小是可能使用该材质的实例数量。每个实例对每个网格只属于一个批次，
+// 所以总共占用 网格数 × 实例数 × size_of::<InstanceRaw>() 字节。
+// 超出设备的存储缓冲区限制时 update 返回 false，由调用者改用 CPU 剔除
+pub struct GpuInstanceCuller {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    config_buffer: wgpu::Buffer,
+    slot_buffer: wgpu::Buffer,
+    visible_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    // 实例数量为 0 的间接绘制参数，每次剔除前复制到 indirect_buffer
+    args_template_buffer: wgpu::Buffer,
+    // 每个网格的包围盒、索引数量和材质
+    meshes: Vec<(Aabb, u32, usize)>,
+    material_count: usize,
+    // 批次的 instances 是它在输出缓冲区中的分段
+    batches: Vec<DrawBatch>,
+    layout: Option<GpuCullLayout>,
+    // 当前布局的缓冲区是否在设备限制之内
+    supported: bool,
+    instance_count: u32,
+}
+
+impl GpuInstanceCuller {
+    pub const ENTRY_POINTS: &'static [&'static str] = &["cs_main"];
+
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("instance_cull_bind_group_layout"),
+            entries: &[
+                // 视锥平面和数量
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // 每个批次的包围盒和材质
+                storage(1, true),
+                // 全部实例
+                storage(2, true),
+                // 可见实例
+                storage(3, false),
+                // 间接绘制参数
+                storage(4, false),
+            ],
+        });
+
+        let pipeline = Self::create_pipeline(device, &bind_group_layout, shader);
+
+        let config_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_cull_config_buffer"),
+            size: std::mem::size_of::<CullConfigUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group: None,
+            config_buffer,
+            slot_buffer: Self::create_slot_buffer(device, 0),
+            visible_buffer: Self::create_visible_buffer(device, 0),
+            indirect_buffer: Self::create_indirect_buffer(device, 0),
+            args_template_buffer: Self::create_args_template_buffer(device, 0),
+            meshes: Vec::new(),
+            material_count: 0,
+            batches: Vec::new(),
+            layout: None,
+            supported: false,
+            instance_count: 0,
+        }
+    }
+
+    // 热重载时替换着色器，绑定组布局不变，已有的绑定组继续可用
+    pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> anyhow::Result<()> {
+        self.pipeline = catch_validation(device, || Self::create_pipeline(device, &self.bind_group_layout, shader))?;
+        Ok(())
+    }
+
+    fn create_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, shader: &wgpu::ShaderModule) -> wgpu::ComputePipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instance Cull Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Instance Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: shader,
+            entry_point: "cs_main",
+            compilation_options: Default::default(),
+        })
+    }
+
+    // 剔除后的实例，作为绘制时的实例顶点缓冲区
+    pub fn visible_buffer(&self) -> &wgpu::Buffer {
+        &self.visible_buffer
+    }
+
+    // 每个批次一组 DrawIndexedIndirectArgs
+    pub fn indirect_buffer(&self) -> &wgpu::Buffer {
+        &self.indirect_buffer
+    }
+
+    pub fn batches(&self) -> &[DrawBatch] {
+        &self.batches
+    }
+
+    // 第 i 个批次的可见实例分段。
+    // 间接绘制参数的 first_instance 必须为 0（除非开启 INDIRECT_FIRST_INSTANCE），所以通过顶点缓冲区的偏移选择分段
+    pub fn visible_slice(&self, batch: usize) -> wgpu::BufferSlice<'_> {
+        let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        let range = &self.batches[batch].instances;
+        // 没有实例时分段长度为 0，不能创建空切片，退回到整个缓冲区（实例数量反正是 0）
+        if range.is_empty() {
+            self.visible_buffer.slice(..)
+        } else {
+            self.visible_buffer.slice(range.start as u64 * stride..range.end as u64 * stride)
+        }
+    }
+
+    pub fn indirect_offset(&self, batch: usize) -> wgpu::BufferAddress {
+        (batch * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>()) as wgpu::BufferAddress
+    }
+
+    // 模型改变时记录新的网格信息，下一次 update 重建批次
+    pub fn set_model(&mut self, model: &Model) {
+        self.meshes = model.meshes.iter().map(|mesh| (mesh.bounds, mesh.num_elements, mesh.material)).collect();
+        self.material_count = model.materials.len();
+        self.layout = None;
+    }
+
+    // 每帧上传视锥体，实例或材质覆盖变化时先重建批次。
+    // 缓冲区超出设备限制时返回 false，这一帧不能使用 GPU 剔除
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, frustum: &Frustum, instances: &InstanceManager) -> bool {
+        let layout = GpuCullLayout {
+            instance_generation: instances.generation(),
+            instance_count: instances.len() as u32,
+            material_overrides: instances.material_override_counts().collect(),
+        };
+        if self.layout.as_ref() != Some(&layout) {
+            self.supported = self.rebuild(device, queue, instances.buffer(), &layout);
+            self.layout = Some(layout);
+        }
+        if !self.supported {
+            return false;
+        }
+
+        let config = CullConfigUniform {
+            planes: frustum.planes.map(|plane| plane.to_array()),
+            instance_count: self.instance_count,
+            slot_count: self.batches.len() as u32,
+            material_count: self.material_count as u32,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.config_buffer, 0, bytemuck::cast_slice(&[config]));
+        true
+    }
+
+    // x 方向每个线程一个实例，y 方向每个批次一行。
+    // 计算着色器累加实例数量，所以在同一个编码器中先把参数重置为模板，没有调用 update 也能重复绘制
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        if self.batches.is_empty() {
+            return;
+        }
+        encoder.copy_buffer_to_buffer(&self.args_template_buffer, 0, &self.indirect_buffer, 0, self.indirect_buffer.size());
+        if self.instance_count == 0 {
+            return;
+        }
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Instance Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.instance_count.div_ceil(64), self.batches.len() as u32, 1);
+    }
+
+    /// 回读上一次剔除后每个批次的可见实例数量，会阻塞等待 GPU，只用于调试和测试
+    pub fn read_visible_counts(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        let size = self.indirect_buffer.size();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("indirect_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Indirect Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.indirect_buffer, 0, &readback, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        let counts = {
+            let data = slice.get_mapped_range();
+            let words: &[u32] = bytemuck::cast_slice(&data);
+            // 每组参数 5 个 u32，第二个是实例数量
+            words.chunks_exact(5).take(self.batches.len()).map(|args| args[1]).collect()
+        };
+        readback.unmap();
+        Ok(counts)
+    }
+
+    // 重建批次和缓冲区，超出设备限制时不创建缓冲区并返回 false
+    fn rebuild(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instance_buffer: &wgpu::Buffer, layout: &GpuCullLayout) -> bool {
+        self.instance_count = layout.instance_count;
+        self.batches.clear();
+        self.bind_group = None;
+        // 有效的材质覆盖使用它自己的批次，其余实例使用网格自己的材质
+        let overrides = layout
+            .material_overrides
+            .iter()
+            .filter(|&&(material, _)| (material as usize) < self.material_count)
+            .map(|&(material, count)| (material as usize, count as u32))
+            .collect::<Vec<_>>();
+        let overridden = overrides.iter().map(|&(_, count)| count).sum::<u32>();
+        let mut slots = Vec::new();
+        let mut capacity = 0;
+        for (mesh_index, &(bounds, _, mesh_material)) in self.meshes.iter().enumerate() {
+            let mut materials = vec![mesh_material];
+            materials.extend(overrides.iter().map(|&(material, _)| material));
+            materials.sort_unstable();
+            materials.dedup();
+            for material in materials {
+                let mut count = overrides.iter().find(|&&(m, _)| m == material).map_or(0, |&(_, count)| count);
+                if material == mesh_material {
+                    count += self.instance_count - overridden;
+                }
+                self.batches.push(DrawBatch {
+                    mesh: mesh_index,
+                    material,
+                    instances: capacity..capacity + count,
+                });
+                slots.push(DrawSlotRaw {
+                    min: bounds.min.into(),
+                    mesh_material: mesh_material as u32,
+                    max: bounds.max.into(),
+                    material: material as u32,
+                    offset: capacity,
+                    _padding: [0; 3],
+                });
+                capacity += count;
+            }
+        }
+
+        // 实例缓冲区和输出缓冲区都作为存储缓冲区绑定
+        let limits = device.limits();
+        let max_binding = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let visible_size = capacity as u64 * std::mem::size_of::<InstanceRaw>() as u64;
+        if visible_size > max_binding || instance_buffer.size() > max_binding {
+            log::warn!(
+                "GPU culling needs {} byte storage buffers but the device allows {}, falling back to CPU culling",
+                visible_size.max(instance_buffer.size()),
+                max_binding
+            );
+            self.batches.clear();
+            return false;
+        }
+
+        self.slot_buffer = Self::create_slot_buffer(device, slots.len());
+        if !slots.is_empty() {
+            queue.write_buffer(&self.slot_buffer, 0, bytemuck::cast_slice(&slots));
+        }
+        self.indirect_buffer = Self::create_indirect_buffer(device, slots.len());
+        self.args_template_buffer = Self::create_args_template_buffer(device, slots.len());
+        let args = self
+            .batches
+            .iter()
+            .flat_map(|batch| {
+                wgpu::util::DrawIndexedIndirectArgs {
+                    index_count: self.meshes[batch.mesh].1,
+                    instance_count: 0,
+                    first_index: 0,
+                    base_vertex: 0,
+                    first_instance: 0,
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect::<Vec<_>>();
+        if !args.is_empty() {
+            queue.write_buffer(&self.args_template_buffer, 0, &args);
+        }
+        self.visible_buffer = Self::create_visible_buffer(device, capacity as usize);
+
+        let buffers = [&self.config_buffer, &self.slot_buffer, instance_buffer, &self.visible_buffer, &self.indirect_buffer];
+        let entries = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>();
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("instance_cull_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        }));
+        true
+    }
+
+    // 空缓冲区不能绑定，所有缓冲区至少保留一个元素
+    fn create_slot_buffer(device: &wgpu::Device, count: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_cull_slot_buffer"),
+            size: (count.max(1) * std::mem::size_of::<DrawSlotRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_visible_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_cull_visible_buffer"),
+            size: (capacity.max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_indirect_buffer(device: &wgpu::Device, count: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_cull_indirect_buffer"),
+            size: (count.max(1) * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_args_template_buffer(device: &wgpu::Device, count: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_cull_args_template_buffer"),
+            size: (count.max(1) * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
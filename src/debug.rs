@@ -0,0 +1,551 @@
+// 调试视图：线框、法线、纹理坐标、切线、线性深度，以及按网格或实例着色。
+// 除深度视图外都替换主渲染管线直接绘制几何体；深度视图先正常渲染，再用全屏通道把深度纹理显示出来
+
+use std::{collections::HashMap, ops::Range};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    hot_reload::catch_validation,
+    instance::InstanceRaw,
+    model::{Mesh, ModelVertex, Vertex},
+    texture::Texture,
+};
+
+// 无限远投影的深度视图显示到这个距离为止
+const INFINITE_DEPTH_VIEW_DISTANCE: f32 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    // 正常的光照渲染
+    #[default]
+    Shaded,
+    Wireframe,
+    Normals,
+    TexCoords,
+    Tangents,
+    Depth,
+    // 每个网格（每次绘制）一种颜色
+    MeshId,
+    // 每个实例一种颜色
+    InstanceId,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 8] = [
+        DebugView::Shaded,
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::TexCoords,
+        DebugView::Tangents,
+        DebugView::Depth,
+        DebugView::MeshId,
+        DebugView::InstanceId,
+    ];
+
+    // 按 ALL 的顺序循环切换
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&view| view == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // 是否用调试管线代替主渲染管线绘制几何体
+    pub fn replaces_shading(self) -> bool {
+        !matches!(self, DebugView::Shaded | DebugView::Depth)
+    }
+
+    fn fragment_entry(self) -> &'static str {
+        match self {
+            DebugView::Wireframe => "fs_wireframe",
+            DebugView::Normals => "fs_normals",
+            DebugView::TexCoords => "fs_tex_coords",
+            DebugView::Tangents => "fs_tangents",
+            DebugView::MeshId => "fs_mesh_id",
+            DebugView::InstanceId => "fs_instance_id",
+            DebugView::Shaded | DebugView::Depth => unreachable!("{:?} does not use a geometry pipeline", self),
+        }
+    }
+}
+
+// 一次绘制的实例来源：CPU 剔除后的实例范围，或者 GPU 剔除写入的间接绘制参数
+pub enum DebugInstances<'a> {
+    Direct(wgpu::BufferSlice<'a>, Range<u32>),
+    Indirect(wgpu::BufferSlice<'a>, &'a wgpu::Buffer, wgpu::BufferAddress),
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthViewUniform {
+    inv_proj: [[f32; 4]; 4],
+    near: f32,
+    far: f32,
+    _padding: [f32; 2],
+}
+
+// 渲染管线需要与主渲染通道的附件一致
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugTarget {
+    pub color_format: wgpu::TextureFormat,
+    pub reverse_z: bool,
+    pub sample_count: u32,
+}
+
+pub struct DebugRenderer {
+    view: DebugView,
+    target: DebugTarget,
+    // 设备开启了 POLYGON_MODE_LINE
+    line_mode: bool,
+    // 不支持或者手动关闭 PolygonMode::Line 时用重心坐标画线框
+    barycentric_wireframe: bool,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    wire_pipeline_layout: wgpu::PipelineLayout,
+    wire_bind_group_layout: wgpu::BindGroupLayout,
+    // 当前视图的几何体管线，Shaded 和 Depth 时为 None
+    pipeline: Option<wgpu::RenderPipeline>,
+    // 网格 ID，每个网格占一个对齐后的槽位
+    mesh_bind_group_layout: wgpu::BindGroupLayout,
+    mesh_buffer: wgpu::Buffer,
+    mesh_bind_group: wgpu::BindGroup,
+    mesh_stride: u32,
+    mesh_capacity: u32,
+    // 重心坐标线框每个网格一个绑定组，在 prepare 中按绘制顺序创建
+    wire_bind_groups: Vec<wgpu::BindGroup>,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_pipeline: wgpu::RenderPipeline,
+    depth_buffer: wgpu::Buffer,
+    depth_bind_group: Option<wgpu::BindGroup>,
+}
+
+impl DebugRenderer {
+    // 着色器必须提供的入口点
+    pub const ENTRY_POINTS: &'static [&'static str] = &[
+        "vs_main",
+        "fs_wireframe",
+        "fs_normals",
+        "fs_tex_coords",
+        "fs_tangents",
+        "fs_mesh_id",
+        "fs_instance_id",
+        "vs_wireframe_barycentric",
+        "fs_wireframe_barycentric",
+        "vs_fullscreen",
+        "fs_depth",
+    ];
+
+    // shader 是预处理并编译好的 debug.wgsl（见 preprocessor.rs）
+    pub fn new(device: &wgpu::Device, shader: wgpu::ShaderModule, camera_bind_group_layout: &wgpu::BindGroupLayout, target: DebugTarget) -> Self {
+        let mesh_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_mesh_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let wire_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_wire_bind_group_layout"),
+            entries: &[storage_entry(0), storage_entry(1)],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &mesh_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let wire_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_wire_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &mesh_bind_group_layout, &wire_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let mesh_stride = device.limits().min_uniform_buffer_offset_alignment;
+        let (mesh_buffer, mesh_bind_group) = Self::create_mesh_ids(device, &mesh_bind_group_layout, mesh_stride, 1);
+
+        let depth_bind_group_layout = Self::create_depth_bind_group_layout(device);
+        let depth_pipeline = Self::create_depth_pipeline(device, &shader, &depth_bind_group_layout, target.color_format);
+        let depth_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_depth_view"),
+            size: std::mem::size_of::<DepthViewUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let line_mode = device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
+        Self {
+            view: DebugView::Shaded,
+            target,
+            line_mode,
+            barycentric_wireframe: !line_mode,
+            shader,
+            pipeline_layout,
+            wire_pipeline_layout,
+            wire_bind_group_layout,
+            pipeline: None,
+            mesh_bind_group_layout,
+            mesh_buffer,
+            mesh_bind_group,
+            mesh_stride,
+            mesh_capacity: 1,
+            wire_bind_groups: Vec::new(),
+            depth_bind_group_layout,
+            depth_pipeline,
+            depth_buffer,
+            depth_bind_group: None,
+        }
+    }
+
+    pub fn view(&self) -> DebugView {
+        self.view
+    }
+
+    // 线框视图是否使用 PolygonMode::Line
+    pub fn is_line_mode(&self) -> bool {
+        !self.barycentric_wireframe
+    }
+
+    pub fn set_view(&mut self, device: &wgpu::Device, view: DebugView) {
+        self.view = view;
+        self.rebuild(device);
+    }
+
+    // 替换着色器（热重载）。先用新的着色器创建所有视图的管线，任何一个失败都保留原来的着色器和管线，
+    // 之后切换视图时就不会因为着色器出错而失败
+    pub fn set_shader(&mut self, device: &wgpu::Device, shader: wgpu::ShaderModule) -> anyhow::Result<()> {
+        let previous = std::mem::replace(&mut self.shader, shader);
+        let result = catch_validation(device, || {
+            for view in DebugView::ALL.into_iter().filter(|view| view.replaces_shading()) {
+                self.create_pipeline(device, view);
+            }
+            Self::create_depth_pipeline(device, &self.shader, &self.depth_bind_group_layout, self.target.color_format)
+        });
+        match result {
+            Ok(depth_pipeline) => {
+                self.depth_pipeline = depth_pipeline;
+                self.rebuild(device);
+                Ok(())
+            }
+            Err(e) => {
+                self.shader = previous;
+                Err(e)
+            }
+        }
+    }
+
+    // 主渲染通道的格式、深度模式或采样数变化后调用
+    pub fn set_target(&mut self, device: &wgpu::Device, target: DebugTarget) {
+        if target == self.target {
+            return;
+        }
+        if target.color_format != self.target.color_format {
+            self.depth_pipeline = Self::create_depth_pipeline(device, &self.shader, &self.depth_bind_group_layout, target.color_format);
+        }
+        self.target = target;
+        self.rebuild(device);
+    }
+
+    // 设备不支持 POLYGON_MODE_LINE 时总是使用重心坐标
+    pub fn set_barycentric_wireframe(&mut self, device: &wgpu::Device, enabled: bool) {
+        self.barycentric_wireframe = enabled || !self.line_mode;
+        self.rebuild(device);
+    }
+
+    fn rebuild(&mut self, device: &wgpu::Device) {
+        self.pipeline = self.view.replaces_shading().then(|| self.create_pipeline(device, self.view));
+        if !self.uses_barycentric() {
+            self.wire_bind_groups.clear();
+        }
+        if self.view != DebugView::Depth {
+            self.depth_bind_group = None;
+        }
+    }
+
+    fn uses_barycentric(&self) -> bool {
+        self.view == DebugView::Wireframe && self.barycentric_wireframe
+    }
+
+    // 每帧绘制前调用。meshes 是按绘制顺序排列的全部网格，它们的下标就是网格 ID
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, meshes: &[&Mesh], depth_texture: &Texture, camera: &Camera) {
+        match self.view {
+            DebugView::Shaded => {}
+            DebugView::Depth => {
+                let (near, far) = camera.depth_range();
+                let uniform = DepthViewUniform {
+                    inv_proj: camera.build_projection_matrix().inverse().to_cols_array_2d(),
+                    near,
+                    far: if far.is_finite() { far } else { INFINITE_DEPTH_VIEW_DISTANCE },
+                    _padding: [0.0; 2],
+                };
+                queue.write_buffer(&self.depth_buffer, 0, bytemuck::cast_slice(&[uniform]));
+                // 深度纹理在调整大小或切换深度模式时会重新创建，每帧重新绑定
+                self.depth_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("debug_depth_bind_group"),
+                    layout: &self.depth_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 1, resource: self.depth_buffer.as_entire_binding() },
+                        wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&depth_texture.view) },
+                    ],
+                }));
+            }
+            _ => {
+                let count = (meshes.len() as u32).max(1);
+                if count > self.mesh_capacity {
+                    let capacity = count.next_power_of_two();
+                    (self.mesh_buffer, self.mesh_bind_group) =
+                        Self::create_mesh_ids(device, &self.mesh_bind_group_layout, self.mesh_stride, capacity);
+                    self.mesh_capacity = capacity;
+                }
+                if self.uses_barycentric() {
+                    self.wire_bind_groups = meshes
+                        .iter()
+                        .map(|mesh| {
+                            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                                label: Some("debug_wire_bind_group"),
+                                layout: &self.wire_bind_group_layout,
+                                entries: &[
+                                    wgpu::BindGroupEntry { binding: 0, resource: mesh.index_buffer.as_entire_binding() },
+                                    wgpu::BindGroupEntry { binding: 1, resource: mesh.vertex_buffer.as_entire_binding() },
+                                ],
+                            })
+                        })
+                        .collect();
+                }
+            }
+        }
+    }
+
+    // 设置调试管线和摄像机，之后用 draw_mesh 绘制各个网格
+    pub fn begin<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        let pipeline = self.pipeline.as_ref().expect("debug view does not draw geometry");
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+    }
+
+    pub fn draw_mesh<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, mesh: &'a Mesh, mesh_id: u32, instances: DebugInstances<'a>) {
+        render_pass.set_bind_group(1, &self.mesh_bind_group, &[mesh_id * self.mesh_stride]);
+        if self.uses_barycentric() {
+            // 顶点由着色器从存储缓冲区读取，每个索引展开成一个顶点，实例数据放在插槽 0
+            render_pass.set_bind_group(2, &self.wire_bind_groups[mesh_id as usize], &[]);
+            match instances {
+                DebugInstances::Direct(buffer, range) => {
+                    render_pass.set_vertex_buffer(0, buffer);
+                    render_pass.draw(0..mesh.num_elements, range);
+                }
+                // GPU 剔除写入的 DrawIndexedIndirectArgs 中 first_index 和 base_vertex 都是 0，
+                // 前四个字段可以直接当作 DrawIndirectArgs 使用
+                DebugInstances::Indirect(buffer, indirect_buffer, offset) => {
+                    render_pass.set_vertex_buffer(0, buffer);
+                    render_pass.draw_indirect(indirect_buffer, offset);
+                }
+            }
+        } else {
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            match instances {
+                DebugInstances::Direct(buffer, range) => {
+                    render_pass.set_vertex_buffer(1, buffer);
+                    render_pass.draw_indexed(0..mesh.num_elements, 0, range);
+                }
+                DebugInstances::Indirect(buffer, indirect_buffer, offset) => {
+                    render_pass.set_vertex_buffer(1, buffer);
+                    render_pass.draw_indexed_indirect(indirect_buffer, offset);
+                }
+            }
+        }
+    }
+
+    // 深度视图：在主渲染通道之后把深度纹理转换成灰度画到输出纹理上
+    pub fn draw_depth(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(bind_group) = &self.depth_bind_group else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_depth_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        render_pass.set_pipeline(&self.depth_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, view: DebugView) -> wgpu::RenderPipeline {
+        let barycentric = view == DebugView::Wireframe && self.barycentric_wireframe;
+        let (layout, vertex_entry, fragment_entry) = if barycentric {
+            (&self.wire_pipeline_layout, "vs_wireframe_barycentric", "fs_wireframe_barycentric")
+        } else {
+            (&self.pipeline_layout, "vs_main", view.fragment_entry())
+        };
+        let vertex_buffers = if barycentric {
+            vec![InstanceRaw::desc()]
+        } else {
+            vec![ModelVertex::desc(), InstanceRaw::desc()]
+        };
+        let polygon_mode = if view == DebugView::Wireframe && !barycentric {
+            wgpu::PolygonMode::Line
+        } else {
+            wgpu::PolygonMode::Fill
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: vertex_entry,
+                compilation_options: Default::default(),
+                buffers: &vertex_buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: fragment_entry,
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &srgb_constants(self.target.color_format),
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.target.color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: Texture::depth_compare(self.target.reverse_z),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.target.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+
+    // 第 i 个槽位的内容就是 i
+    fn create_mesh_ids(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, stride: u32, capacity: u32) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let words = (stride / 4) as usize;
+        let mut contents = vec![0u32; words * capacity as usize];
+        for (i, slot) in contents.chunks_mut(words).enumerate() {
+            slot[0] = i as u32;
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug_mesh_ids"),
+            contents: bytemuck::cast_slice(&contents),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug_mesh_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    // uniform 中的结构体按 16 字节对齐
+                    size: wgpu::BufferSize::new(16),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    fn create_depth_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_depth_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn create_depth_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_depth_pipeline_layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_depth_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_fullscreen",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_depth",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &srgb_constants(color_format),
+                    ..Default::default()
+                },
+                targets: &[Some(color_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+}
+
+// 着色器中的 SRGB_TARGET 常量
+fn srgb_constants(color_format: wgpu::TextureFormat) -> HashMap<String, f64> {
+    HashMap::from([("SRGB_TARGET".to_string(), if color_format.is_srgb() { 1.0 } else { 0.0 })])
+}
@@ -0,0 +1,174 @@
+// 调试视图：线框、法线、纹理坐标、切线、网格/实例 ID 和线性深度
+#include "include/camera.wgsl"
+#include "include/instance.wgsl"
+#include "include/vertex.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// 每个网格一份，通过动态偏移选择
+struct MeshUniform {
+    mesh_id: u32,
+}
+@group(1) @binding(0)
+var<uniform> mesh: MeshUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) world_normal: vec3f,
+    @location(2) world_tangent: vec3f,
+    @location(3) @interpolate(flat) instance_id: u32,
+    // 只有没有 PolygonMode::Line 时的线框回退方案使用
+    @location(4) barycentric: vec3f,
+}
+
+const WIRE_COLOR: vec3f = vec3f(0.9, 0.9, 0.9);
+
+// 输出格式是 sRGB 时写入前会被编码，先转换到线性空间，保证保存下来的数值就是要显示的数据
+override SRGB_TARGET: bool = false;
+
+fn output(color: vec3f) -> vec4f {
+    if !SRGB_TARGET {
+        return vec4f(color, 1.0);
+    }
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return vec4f(select(high, low, color <= vec3f(0.04045)), 1.0);
+}
+
+// 整数哈希（PCG），把相邻的 ID 映射到差别很大的颜色
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn id_color(id: u32) -> vec3f {
+    let h = hash(id);
+    let rgb = vec3f(f32(h & 0xffu), f32((h >> 8u) & 0xffu), f32((h >> 16u) & 0xffu)) / 255.0;
+    // 避免太暗的颜色和背景混在一起
+    return 0.25 + rgb * 0.75;
+}
+
+fn transform(position: vec3f, tex_coords: vec2f, normal: vec3f, tangent: vec3f, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+    let normal_matrix = instance_normal_matrix(instance);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4f(position, 1.0);
+    out.tex_coords = tex_coords;
+    out.world_normal = normal_matrix * normal;
+    out.world_tangent = instance_tangent_matrix(instance) * tangent;
+    // 剔除和按材质分批会改变实例的顺序，用实例的位置作为 ID，颜色在各帧之间保持稳定
+    let translation = bitcast<vec3u>(instance.model_matrix_3.xyz);
+    out.instance_id = hash(translation.x ^ hash(translation.y ^ hash(translation.z)));
+    out.barycentric = vec3f(1.0);
+    return out;
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    return transform(model.position, model.tex_coords, model.normal, model.tangent, instance);
+}
+
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4f {
+    return output(WIRE_COLOR);
+}
+
+// 方向映射到 0-1 的颜色
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4f {
+    return output(normalize(in.world_normal) * 0.5 + 0.5);
+}
+
+@fragment
+fn fs_tangents(in: VertexOutput) -> @location(0) vec4f {
+    return output(normalize(in.world_tangent) * 0.5 + 0.5);
+}
+
+// 重复的纹理坐标只显示小数部分
+@fragment
+fn fs_tex_coords(in: VertexOutput) -> @location(0) vec4f {
+    return output(vec3f(fract(in.tex_coords), 0.0));
+}
+
+@fragment
+fn fs_mesh_id(in: VertexOutput) -> @location(0) vec4f {
+    return output(id_color(mesh.mesh_id));
+}
+
+@fragment
+fn fs_instance_id(in: VertexOutput) -> @location(0) vec4f {
+    return output(id_color(in.instance_id));
+}
+
+// 线框的回退方案：从存储缓冲区中按索引读取顶点（顶点拉取），
+// 每个三角形的三个顶点分别得到 (1,0,0)、(0,1,0)、(0,0,1)，片元中靠近 0 的分量说明靠近对边
+@group(2) @binding(0)
+var<storage, read> wire_indices: array<u32>;
+// ModelVertex 按 f32 展开，每个顶点 14 个
+@group(2) @binding(1)
+var<storage, read> wire_vertices: array<f32>;
+
+const VERTEX_STRIDE: u32 = 14u;
+
+@vertex
+fn vs_wireframe_barycentric(@builtin(vertex_index) vertex_index: u32, instance: InstanceInput) -> VertexOutput {
+    let base = wire_indices[vertex_index] * VERTEX_STRIDE;
+    let position = vec3f(wire_vertices[base], wire_vertices[base + 1u], wire_vertices[base + 2u]);
+    var out = transform(position, vec2f(0.0), vec3f(0.0, 1.0, 0.0), vec3f(1.0, 0.0, 0.0), instance);
+    let corner = vertex_index % 3u;
+    out.barycentric = vec3f(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    return out;
+}
+
+// 线宽约 1 像素
+@fragment
+fn fs_wireframe_barycentric(in: VertexOutput) -> @location(0) vec4f {
+    let width = fwidth(in.barycentric);
+    let edge = min(in.barycentric.x / width.x, min(in.barycentric.y / width.y, in.barycentric.z / width.z));
+    if edge > 1.0 {
+        discard;
+    }
+    return output(WIRE_COLOR);
+}
+
+// 深度视图：全屏三角形读取深度纹理，用投影矩阵的逆还原观察空间的距离
+struct DepthView {
+    inv_proj: mat4x4f,
+    // 显示范围，近处为白色，远处为黑色
+    near: f32,
+    far: f32,
+}
+// 与摄像机共用第 0 组的编号，深度视图的管线布局中没有摄像机。
+// GLSL 后端不支持对深度纹理使用 textureLoad，按不可过滤的浮点纹理读取
+@group(0) @binding(1)
+var<uniform> depth_view: DepthView;
+@group(0) @binding(2)
+var t_depth: texture_2d<f32>;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn depth_color(depth: f32) -> vec4f {
+    // 只需要 z/w，与屏幕位置无关
+    let view = depth_view.inv_proj * vec4f(0.0, 0.0, depth, 1.0);
+    // 无限远投影清除值处的 w 为 0，视为最远
+    var distance = depth_view.far;
+    if abs(view.w) > 1e-6 {
+        distance = -view.z / view.w;
+    }
+    // 按对数映射到灰度，近处的细节不会挤在一起
+    let t = clamp(log(distance / depth_view.near) / log(depth_view.far / depth_view.near), 0.0, 1.0);
+    return output(vec3f(1.0 - t));
+}
+
+@fragment
+fn fs_depth(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    return depth_color(textureLoad(t_depth, vec2i(position.xy), 0).r);
+}
@@ -0,0 +1,231 @@
+// glTF 2.0 加载器，支持 .gltf（外部或 data URI 缓冲）和 .glb（内嵌二进制块）。
+// 节点层级在加载时被展开：每个节点的世界变换直接烘焙进顶点，
+// 每个图元（primitive）生成一个 Mesh，与 OBJ 加载器得到的 Model 结构完全一致
+
+use std::path::Path;
+
+use anyhow::Context;
+use wgpu::util::DeviceExt;
+
+use crate::{bounds::Aabb, model, resources, texture};
+
+pub async fn load_gltf(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queue,layout: &wgpu::BindGroupLayout,mipmaps: &texture::MipmapGenerator) -> anyhow::Result<model::Model> {
+    let data = resources::load_binary(file_name).await?;
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(&data)
+        .with_context(|| format!("failed to parse {}", file_name))?;
+
+    // 外部资源相对于 glTF 文件所在目录解析
+    let path = resources::res_path(file_name);
+    let base = path.parent().unwrap_or(Path::new("."));
+    let buffers = gltf::import_buffers(&document, Some(base), blob)
+        .with_context(|| format!("failed to load buffers of {}", file_name))?;
+
+    let mut materials = Vec::new();
+    for m in document.materials() {
+        let name = m.name().unwrap_or("gltf_material");
+        let pbr = m.pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+
+        let diffuse_texture = match pbr.base_color_texture() {
+            Some(info) => load_image(info.texture(), base, &buffers, texture::TextureOptions::color(), device, queue, mipmaps)?,
+            None => texture::Texture::from_color(device, queue, [255, 255, 255, 255], "white", &texture::TextureOptions::color())?,
+        };
+        let normal_texture = match m.normal_texture() {
+            Some(info) => load_image(info.texture(), base, &buffers, texture::TextureOptions::data(), device, queue, mipmaps)?,
+            None => texture::Texture::from_color(device, queue, [128, 128, 255, 255], "flat_normal", &texture::TextureOptions::data())?,
+        };
+
+        // 渲染器只绘制不透明物体，没有混合，基础色的 alpha（base_color[3]）和 alphaMode 都被忽略
+        if m.alpha_mode() != gltf::material::AlphaMode::Opaque {
+            log::warn!("{}: alpha mode {:?} is not supported, rendering as opaque", name, m.alpha_mode());
+        }
+        let uniform = model::MaterialUniform::from_pbr(
+            [base_color[0], base_color[1], base_color[2]],
+            pbr.metallic_factor(),
+            pbr.roughness_factor(),
+        );
+        materials.push(model::Material::new(device, name, diffuse_texture, normal_texture, uniform, layout));
+    }
+
+    // 没有指定材质的图元使用规范中的默认材质：白色、金属度 1、粗糙度 1
+    let default_material = materials.len();
+    materials.push(model::Material::new(
+        device,
+        "gltf_default",
+        texture::Texture::from_color(device, queue, [255, 255, 255, 255], "white", &texture::TextureOptions::color())?,
+        texture::Texture::from_color(device, queue, [128, 128, 255, 255], "flat_normal", &texture::TextureOptions::data())?,
+        model::MaterialUniform::from_pbr([1.0; 3], 1.0, 1.0),
+        layout,
+    ));
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .with_context(|| format!("{} contains no scene", file_name))?;
+
+    let mut meshes = Vec::new();
+    for node in scene.nodes() {
+        load_node(&node, glam::Mat4::IDENTITY, &buffers, default_material, file_name, device, &mut meshes)?;
+    }
+
+    Ok(model::Model { meshes, materials })
+}
+
+// glTF 采样器的寻址和过滤方式映射到 TextureOptions
+fn sampler_options(sampler: gltf::texture::Sampler, options: texture::TextureOptions) -> texture::TextureOptions {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let mut options = options;
+    options.address_mode_u = address_mode(sampler.wrap_s());
+    options.address_mode_v = address_mode(sampler.wrap_t());
+    if let Some(MagFilter::Nearest) = sampler.mag_filter() {
+        options.mag_filter = wgpu::FilterMode::Nearest;
+    }
+    match sampler.min_filter() {
+        Some(MinFilter::Nearest) => options = options.min_filter(wgpu::FilterMode::Nearest).mipmaps(false),
+        Some(MinFilter::Linear) => options = options.mipmaps(false),
+        Some(MinFilter::NearestMipmapNearest) => options = options.min_filter(wgpu::FilterMode::Nearest).mipmap_filter(wgpu::FilterMode::Nearest),
+        Some(MinFilter::LinearMipmapNearest) => options = options.mipmap_filter(wgpu::FilterMode::Nearest),
+        Some(MinFilter::NearestMipmapLinear) => options = options.min_filter(wgpu::FilterMode::Nearest),
+        Some(MinFilter::LinearMipmapLinear) | None => {}
+    }
+    options
+}
+
+// 纹理可能来自缓冲视图（.glb 内嵌）、data URI 或外部文件，统一解码成 Texture
+fn load_image(texture: gltf::Texture,base: &Path,buffers: &[gltf::buffer::Data],options: texture::TextureOptions,device: &wgpu::Device,queue: &wgpu::Queue,mipmaps: &texture::MipmapGenerator) -> anyhow::Result<texture::Texture> {
+    let options = sampler_options(texture.sampler(), options);
+    match texture.source().source() {
+        gltf::image::Source::View { view, .. } => {
+            // 文件损坏时缓冲或范围可能越界，返回错误而不是 panic
+            let bytes = buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .with_context(|| format!("image {} references bytes outside of buffer {}", texture.source().index(), view.buffer().index()))?;
+            texture::Texture::from_bytes(device, queue, bytes, "gltf_image", &options, mipmaps)
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            // 读取 URI 的逻辑与缓冲相同，借用 gltf 的实现同时处理 data URI 和相对路径
+            let bytes = gltf::buffer::Data::from_source(gltf::buffer::Source::Uri(uri), Some(base))
+                .with_context(|| format!("failed to load image {}", uri))?;
+            texture::Texture::from_bytes(device, queue, &bytes, uri, &options, mipmaps)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_node(node: &gltf::Node,parent: glam::Mat4,buffers: &[gltf::buffer::Data],default_material: usize,file_name: &str,device: &wgpu::Device,meshes: &mut Vec<model::Mesh>) -> anyhow::Result<()> {
+    let world = parent * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let name = mesh.name().unwrap_or(file_name);
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("{}: skipping primitive with mode {:?}", name, primitive.mode());
+                continue;
+            }
+            let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+
+            let positions = reader
+                .read_positions()
+                .with_context(|| format!("{}: primitive has no positions", name))?;
+            let mut vertices = positions
+                .map(|position| model::ModelVertex {
+                    position,
+                    tex_coords: [0.0; 2],
+                    normal: [0.0, 0.0, 1.0],
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                for (v, uv) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                    v.tex_coords = uv;
+                }
+            }
+
+            let mut indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            // 文件损坏时索引可能越界或者不是完整的三角形，返回错误而不是在生成法线和切线时 panic
+            anyhow::ensure!(
+                indices.len().is_multiple_of(3),
+                "{}: primitive {}: index count {} is not a multiple of 3", name, primitive.index(), indices.len()
+            );
+            if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+                anyhow::bail!("{}: primitive {}: index {} out of bounds for {} vertices", name, primitive.index(), i, vertices.len());
+            }
+
+            match reader.read_normals() {
+                Some(normals) => {
+                    for (v, n) in vertices.iter_mut().zip(normals) {
+                        v.normal = n;
+                    }
+                }
+                None => (vertices, indices) = resources::generate_normals(&vertices, &indices),
+            }
+
+            // 文件自带切线时直接使用，w 分量表示副切线的方向
+            match reader.read_tangents() {
+                Some(tangents) => {
+                    for (v, t) in vertices.iter_mut().zip(tangents) {
+                        let tangent = glam::Vec3::new(t[0], t[1], t[2]);
+                        v.tangent = tangent.into();
+                        v.bitangent = (glam::Vec3::from(v.normal).cross(tangent) * t[3]).into();
+                    }
+                }
+                None => resources::compute_tangents(&mut vertices, &indices),
+            }
+
+            // 把节点的世界变换烘焙到顶点中，法线使用逆转置矩阵
+            let normal_matrix = glam::Mat3::from_mat4(world).inverse().transpose();
+            let tangent_matrix = glam::Mat3::from_mat4(world);
+            for v in vertices.iter_mut() {
+                v.position = world.transform_point3(v.position.into()).into();
+                v.normal = (normal_matrix * glam::Vec3::from(v.normal)).normalize_or_zero().into();
+                v.tangent = (tangent_matrix * glam::Vec3::from(v.tangent)).normalize_or_zero().into();
+                v.bitangent = (tangent_matrix * glam::Vec3::from(v.bitangent)).normalize_or_zero().into();
+            }
+            // 镜像变换会翻转三角形的环绕方向，需要交换顶点顺序
+            if world.determinant() < 0.0 {
+                for c in indices.chunks_exact_mut(3) {
+                    c.swap(1, 2);
+                }
+            }
+
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+                label: Some(&format!("{:?} Vertex Buffer",name)),
+                contents: bytemuck::cast_slice(vertices.as_slice()),
+                // 线框的回退方案在着色器中把顶点和索引当作存储缓冲区读取
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            });
+
+            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+                label: Some(&format!("{:?} Index Buffer",name)),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+            });
+
+            meshes.push(model::Mesh{
+                name: name.to_string(),
+                vertex_buffer,
+                index_buffer,
+                num_elements: indices.len() as u32,
+                bounds: Aabb::from_points(vertices.iter().map(|v| glam::Vec3::from(v.position))),
+                material: primitive.material().index().unwrap_or(default_material),
+            });
+        }
+    }
+
+    for child in node.children() {
+        load_node(&child, world, buffers, default_material, file_name, device, meshes)?;
+    }
+    Ok(())
+}
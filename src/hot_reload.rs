@@ -0,0 +1,87 @@
+// 着色器热重载：开发时从磁盘读取 WGSL，文件（包括 #include 的文件）改变后重新编译并重建渲染管线。
+// 编译或创建管线失败时保留上一次成功的结果，错误信息带有文件名和行号。读取和预处理见 preprocessor.rs
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::anyhow;
+use wgpu::naga;
+
+// 轮询文件的修改时间。不依赖系统的文件通知，每帧检查几个文件的开销可以忽略
+pub struct ShaderWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified_time(&path);
+                (path, modified)
+            })
+            .collect();
+        Self { files }
+    }
+
+    // 返回上次检查之后修改过的文件。文件暂时不存在（编辑器保存时先删除再写入）时不算修改
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, last_modified) in &mut self.files {
+            let modified = modified_time(path);
+            if modified.is_some() && modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// 用 naga 解析并验证 WGSL，确认需要的入口点都存在。
+/// 错误信息的格式与编译器相同（`path:line:column`），并附带出错的源码行
+pub fn validate_wgsl(source: &str, path: &Path, entry_points: &[&str]) -> anyhow::Result<naga::Module> {
+    validate_source(source, &path.display().to_string(), entry_points, |_, _| None)
+}
+
+// locate 把出错的行号和列号映射到原始文件中的位置（预处理过的源码），得到的位置放在错误信息最前面
+pub(crate) fn validate_source(
+    source: &str,
+    path: &str,
+    entry_points: &[&str],
+    locate: impl Fn(u32, u32) -> Option<String>,
+) -> anyhow::Result<naga::Module> {
+    let error = |location: Option<naga::SourceLocation>, message: String| {
+        match location.and_then(|location| locate(location.line_number, location.line_position)) {
+            Some(origin) => anyhow!("{}: {}", origin, message),
+            None => anyhow!(message),
+        }
+    };
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| error(e.location(source), e.emit_to_string_with_path(source, path)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| error(e.location(source), e.emit_to_string_with_path(source, path)))?;
+    for name in entry_points {
+        if !module.entry_points.iter().any(|entry_point| entry_point.name == *name) {
+            anyhow::bail!("{}: missing entry point `{}`", path, name);
+        }
+    }
+    Ok(module)
+}
+
+/// 捕获 f 中产生的验证错误并作为 Err 返回，而不是交给默认的错误处理（直接 panic）。
+/// 用于创建可能失败的管线，例如着色器的绑定与管线布局不一致
+pub fn catch_validation<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> anyhow::Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(anyhow!("{}", error)),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,5 @@
+// 摄像机，与 lib.rs 中的 CameraUniform 对应。绑定的组号由各个着色器自己决定
+struct CameraUniform {
+    view_pos: vec4f,
+    view_proj: mat4x4f,
+}
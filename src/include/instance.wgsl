@@ -0,0 +1,37 @@
+// 实例数据，与 instance.rs 中的 InstanceRaw::desc 对应
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4f,
+    @location(6) model_matrix_1: vec4f,
+    @location(7) model_matrix_2: vec4f,
+    @location(8) model_matrix_3: vec4f,
+    @location(9) normal_matrix_0: vec3f,
+    @location(10) normal_matrix_1: vec3f,
+    @location(11) normal_matrix_2: vec3f,
+    @location(12) tint: vec4f,
+}
+
+fn instance_model_matrix(instance: InstanceInput) -> mat4x4f {
+    return mat4x4f(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3
+    );
+}
+
+// 切线和副切线是表面上的方向，跟随模型矩阵左上 3x3 变换；只有法线使用逆转置的法线矩阵
+fn instance_tangent_matrix(instance: InstanceInput) -> mat3x3f {
+    return mat3x3f(
+        instance.model_matrix_0.xyz,
+        instance.model_matrix_1.xyz,
+        instance.model_matrix_2.xyz
+    );
+}
+
+fn instance_normal_matrix(instance: InstanceInput) -> mat3x3f {
+    return mat3x3f(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2
+    );
+}
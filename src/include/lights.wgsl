@@ -0,0 +1,39 @@
+// 光源和簇，与 lights.rs 中的 LightRaw、LightConfigUniform 和常量对应
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+const TILE_SIZE: u32 = 32u;
+const CLUSTER_SLICES: u32 = 16u;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 63u;
+
+struct Light {
+    position: vec3f,
+    kind: u32,
+    direction: vec3f,
+    range: f32,
+    color: vec3f,
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+}
+
+struct LightConfig {
+    view: mat4x4f,
+    inv_proj: mat4x4f,
+    screen_size: vec2u,
+    tile_count: vec2u,
+    light_count: u32,
+    // 平行光排在光源缓冲区的前面
+    directional_count: u32,
+    depth_near: f32,
+    depth_far: f32,
+    logarithmic: u32,
+}
+
+// 光源剔除的结果：每个簇可见的点光源和聚光灯索引。
+// count 是没有截断的光源数量，超过 MAX_LIGHTS_PER_CLUSTER 时只记录前面的光源
+struct ClusterLights {
+    count: u32,
+    indices: array<u32, MAX_LIGHTS_PER_CLUSTER>,
+}
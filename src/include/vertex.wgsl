@@ -0,0 +1,8 @@
+// 网格顶点，与 model.rs 中的 ModelVertex::desc 对应
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+    @location(2) normal: vec3f,
+    @location(3) tangent: vec3f,
+    @location(4) bitangent: vec3f,
+}
@@ -1,13 +1,42 @@
+use std::collections::BTreeMap;
+
+// 没有材质覆盖时写入 InstanceRaw 的值
+pub const NO_MATERIAL_OVERRIDE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Instance {
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+    // 与材质的漫反射颜色相乘，alpha 与纹理的 alpha 相乘
+    pub tint: glam::Vec4,
+    // 替换网格自带的材质，值是模型材质列表中的下标，越界时忽略
+    pub material: Option<usize>,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+            scale: glam::Vec3::ONE,
+            tint: glam::Vec4::ONE,
+            material: None,
+        }
+    }
 }
 
 impl Instance {
+    pub fn new(position: glam::Vec3, rotation: glam::Quat) -> Self {
+        Self { position, rotation, ..Default::default() }
+    }
+
+    pub fn model_matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (glam::Mat4::from_translation(self.position) * glam::Mat4::from_quat(self.rotation)).to_cols_array_2d()
-        }
+        InstanceRaw::new(self.model_matrix(), self.tint, self.material)
     }
 }
 
@@ -16,14 +45,47 @@ impl Instance {
 // 四元数的矩阵形式
 pub struct InstanceRaw{
     model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3],
+    tint: [f32; 4],
+    material: u32,
 }
 
+// 行列式低于这个值时模型矩阵被视为不可逆（例如某个方向缩放为 0）
+const MIN_DETERMINANT: f32 = 1e-12;
+
 impl InstanceRaw {
+    // 任意模型矩阵（例如场景图中带切变的世界矩阵）也可以直接作为实例
+    pub fn new(model: glam::Mat4, tint: glam::Vec4, material: Option<usize>) -> Self {
+        // 法线矩阵：模型矩阵左上 3x3 的逆转置，非等比缩放时法线才不会被拉歪。
+        // 矩阵不可逆时求逆会得到 inf/NaN，退回单位矩阵，保持原来的法线方向
+        let linear = glam::Mat3::from_mat4(model);
+        let normal = if linear.determinant().abs() > MIN_DETERMINANT {
+            linear.inverse().transpose()
+        } else {
+            glam::Mat3::IDENTITY
+        };
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: normal.to_cols_array_2d(),
+            tint: tint.to_array(),
+            material: material.map_or(NO_MATERIAL_OVERRIDE, |m| m as u32),
+        }
+    }
+
+    pub fn normal_matrix(&self) -> glam::Mat3 {
+        glam::Mat3::from_cols_array_2d(&self.normal)
+    }
+
+    // 材质覆盖的下标，没有覆盖时是 NO_MATERIAL_OVERRIDE
+    pub fn material(&self) -> u32 {
+        self.material
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
-        wgpu::VertexBufferLayout { 
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress, 
-            step_mode: wgpu::VertexStepMode::Instance, 
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
             attributes: &[
                 // mat4 从技术的角度来看是由 4 个 vec4 构成，占用 4 个插槽。
                 // 我们需要为每个 vec4 定义一个插槽，然后在着色器中重新组装出 mat4。
@@ -47,9 +109,210 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
-            ] 
+                // 法线矩阵 mat3 由 3 个 vec3 构成
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32;16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32;19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32;22]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                // 颜色。最后的材质覆盖只在分批次和 GPU 剔除时使用，着色器不读取，所以不作为顶点属性
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32;25]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
         }
     }
 }
 
+// 管理运行时可以增删改的实例，以及它们在 GPU 上的实例缓冲区。
+// 修改只记录脏的下标，update 时把连续的脏区间分别上传；容量不足时按 2 的幂扩容并整体上传
+pub struct InstanceManager {
+    instances: Vec<Instance>,
+    // 与 GPU 缓冲区内容一致的副本
+    raw: Vec<InstanceRaw>,
+    dirty: Vec<usize>,
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    // 每次重建缓冲区加一，使用该缓冲区创建绑定组的地方据此判断是否需要重建
+    generation: u64,
+    // 每个材质覆盖被多少个实例使用
+    material_overrides: BTreeMap<u32, usize>,
+    uploaded_bytes: u64,
+}
 
+impl InstanceManager {
+    pub fn new(device: &wgpu::Device, instances: Vec<Instance>) -> Self {
+        let capacity = instances.len().max(1).next_power_of_two();
+        let mut manager = Self {
+            instances: Vec::new(),
+            raw: Vec::new(),
+            dirty: Vec::new(),
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            generation: 0,
+            material_overrides: BTreeMap::new(),
+            uploaded_bytes: 0,
+        };
+        manager.set(instances);
+        manager
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Instance> {
+        self.instances.get(index)
+    }
+
+    // 返回可修改的实例，并把它标记为需要上传
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Instance> {
+        let instance = self.instances.get_mut(index)?;
+        self.dirty.push(index);
+        Some(instance)
+    }
+
+    // 返回新实例的下标
+    pub fn add(&mut self, instance: Instance) -> usize {
+        self.instances.push(instance);
+        self.dirty.push(self.instances.len() - 1);
+        self.instances.len() - 1
+    }
+
+    // 用最后一个实例填补空位，所以最后一个实例的下标会变成 index
+    pub fn remove(&mut self, index: usize) -> Instance {
+        let removed = self.instances.swap_remove(index);
+        if index < self.instances.len() {
+            self.dirty.push(index);
+        }
+        removed
+    }
+
+    // 替换全部实例
+    pub fn set(&mut self, instances: Vec<Instance>) {
+        self.dirty.extend(0..instances.len());
+        self.instances = instances;
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    // 上一次 update 之后的实例数据，与 instances() 一一对应
+    pub fn raw(&self) -> &[InstanceRaw] {
+        &self.raw
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // 当前被使用的材质覆盖
+    pub fn material_overrides(&self) -> impl Iterator<Item = u32> + '_ {
+        self.material_overrides.keys().copied()
+    }
+
+    // 每个材质覆盖和使用它的实例数量
+    pub fn material_override_counts(&self) -> impl Iterator<Item = (u32, usize)> + '_ {
+        self.material_overrides.iter().map(|(&material, &count)| (material, count))
+    }
+
+    // 上一次 update 上传的字节数
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.uploaded_bytes = 0;
+
+        // 删除的实例从末尾移除
+        while self.raw.len() > self.instances.len() {
+            let raw = self.raw.pop().unwrap();
+            self.release_material(raw.material);
+        }
+        self.dirty.retain(|&i| i < self.instances.len());
+        self.dirty.sort_unstable();
+        self.dirty.dedup();
+        for k in 0..self.dirty.len() {
+            let i = self.dirty[k];
+            let raw = self.instances[i].to_raw();
+            if raw.material != NO_MATERIAL_OVERRIDE {
+                *self.material_overrides.entry(raw.material).or_default() += 1;
+            }
+            if i < self.raw.len() {
+                let old = std::mem::replace(&mut self.raw[i], raw);
+                self.release_material(old.material);
+            } else {
+                self.raw.push(raw);
+            }
+        }
+
+        if self.raw.len() > self.capacity {
+            self.capacity = self.raw.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+            self.generation += 1;
+            self.write(queue, 0..self.raw.len());
+        } else {
+            // 合并相邻的脏下标，每个连续区间上传一次
+            let mut start = 0;
+            while start < self.dirty.len() {
+                let mut end = start + 1;
+                while end < self.dirty.len() && self.dirty[end] == self.dirty[end - 1] + 1 {
+                    end += 1;
+                }
+                self.write(queue, self.dirty[start]..self.dirty[end - 1] + 1);
+                start = end;
+            }
+        }
+        self.dirty.clear();
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue, range: std::ops::Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let offset = (range.start * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+        let bytes: &[u8] = bytemuck::cast_slice(&self.raw[range]);
+        queue.write_buffer(&self.buffer, offset, bytes);
+        self.uploaded_bytes += bytes.len() as u64;
+    }
+
+    fn release_material(&mut self, material: u32) {
+        if let Some(count) = self.material_overrides.get_mut(&material) {
+            *count -= 1;
+            if *count == 0 {
+                self.material_overrides.remove(&material);
+            }
+        }
+    }
+
+    // 实例缓冲区同时作为顶点缓冲区（直接绘制、阴影）和存储缓冲区（GPU 剔除的输入）
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
@@ -0,0 +1,91 @@
+// GPU 实例剔除
+// 每个线程测试一个 (实例, 批次) 组合。批次是 (网格, 材质) 组合，实例只属于和它实际使用的材质相同的批次。
+// 可见时把网格的包围盒变换到世界空间，与视锥体的 6 个平面求交，
+// 可见的实例被复制到输出缓冲区中该批次的分段里，同时原子地累加该批次间接绘制参数中的实例数量
+
+// InstanceRaw 共 30 个 32 位字（mat4 + mat3 + vec4 颜色 + u32 材质），
+// 和 WGSL 的结构体对齐规则不一致，按 u32 数组读写，需要时再 bitcast 成 f32
+const INSTANCE_WORDS: u32 = 30u;
+const MATERIAL_WORD: u32 = 29u;
+
+struct CullConfig {
+    planes: array<vec4f, 6>,
+    instance_count: u32,
+    slot_count: u32,
+    material_count: u32,
+}
+
+struct DrawSlot {
+    min: vec3f,
+    mesh_material: u32,
+    max: vec3f,
+    material: u32,
+    // 这个批次在输出缓冲区中的分段起点
+    offset: u32,
+}
+
+// 与 wgpu::util::DrawIndexedIndirectArgs 的布局一致
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> config: CullConfig;
+@group(0) @binding(1)
+var<storage, read> slots: array<DrawSlot>;
+@group(0) @binding(2)
+var<storage, read> instances: array<u32>;
+@group(0) @binding(3)
+var<storage, read_write> visible: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> draws: array<DrawArgs>;
+
+fn load_column(base: u32) -> vec4f {
+    return bitcast<vec4f>(vec4u(instances[base], instances[base + 1u], instances[base + 2u], instances[base + 3u]));
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    let instance = id.x;
+    let slot_index = id.y;
+    if instance >= config.instance_count || slot_index >= config.slot_count {
+        return;
+    }
+    let slot = slots[slot_index];
+    let base = instance * INSTANCE_WORDS;
+
+    // 材质覆盖越界（包括没有覆盖）时使用网格自己的材质
+    var material = instances[base + MATERIAL_WORD];
+    if material >= config.material_count {
+        material = slot.mesh_material;
+    }
+    if material != slot.material {
+        return;
+    }
+
+    let model = mat4x4f(load_column(base), load_column(base + 4u), load_column(base + 8u), load_column(base + 12u));
+
+    // 变换后的包围盒：中心直接变换，半边长乘以矩阵各元素的绝对值
+    let center = (model * vec4f((slot.min + slot.max) * 0.5, 1.0)).xyz;
+    let half_extents = (slot.max - slot.min) * 0.5;
+    let extent = mat3x3f(abs(model[0].xyz), abs(model[1].xyz), abs(model[2].xyz)) * half_extents;
+
+    for (var i = 0u; i < 6u; i++) {
+        let plane = config.planes[i];
+        let radius = dot(extent, abs(plane.xyz));
+        if dot(plane.xyz, center) + plane.w + radius < 0.0 {
+            return;
+        }
+    }
+
+    // 分段的大小是使用这个材质的实例数量，不会越界。绘制时通过顶点缓冲区偏移选择分段
+    let slot_offset = atomicAdd(&draws[slot_index].instance_count, 1u);
+    let dst = (slot.offset + slot_offset) * INSTANCE_WORDS;
+    for (var i = 0u; i < INSTANCE_WORDS; i++) {
+        visible[dst + i] = instances[base + i];
+    }
+}
@@ -0,0 +1,145 @@
+// 一个模型和它的全部实例，以及两种视锥剔除器。
+// 场景文件中的每个模型对应一个 InstancedModel，各自维护实例缓冲区和可见实例缓冲区
+
+use std::ops::Range;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    culling::{CullingStats, GpuInstanceCuller, InstanceCuller},
+    debug::{DebugInstances, DebugRenderer},
+    instance::{Instance, InstanceManager},
+    model::{DrawModel, Model},
+};
+
+pub struct InstancedModel {
+    model: Model,
+    instances: InstanceManager,
+    culler: InstanceCuller,
+    // 开启 GPU 剔除后改用计算着色器剔除并间接绘制，CPU 剔除器不再更新
+    gpu_culler: GpuInstanceCuller,
+    // GPU 剔除的缓冲区超出设备限制时退回 CPU 剔除
+    gpu_fallback: bool,
+}
+
+impl InstancedModel {
+    pub fn new(device: &wgpu::Device, cull_shader: &wgpu::ShaderModule, model: Model, instances: Vec<Instance>) -> Self {
+        let mut gpu_culler = GpuInstanceCuller::new(device, cull_shader);
+        gpu_culler.set_model(&model);
+        Self {
+            model,
+            instances: InstanceManager::new(device, instances),
+            culler: InstanceCuller::new(device),
+            gpu_culler,
+            gpu_fallback: false,
+        }
+    }
+
+    // 替换模型，实例保持不变
+    pub fn set_model(&mut self, model: Model) {
+        self.gpu_culler.set_model(&model);
+        self.model = model;
+    }
+
+    pub fn set_cull_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> anyhow::Result<()> {
+        self.gpu_culler.set_shader(device, shader)
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    pub fn instances(&self) -> &InstanceManager {
+        &self.instances
+    }
+
+    pub fn instances_mut(&mut self) -> &mut InstanceManager {
+        &mut self.instances
+    }
+
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.culler.set_enabled(enabled);
+    }
+
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culler.stats()
+    }
+
+    pub fn gpu_visible_counts(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        self.gpu_culler.read_visible_counts(device, queue)
+    }
+
+    // 上传修改过的实例，然后按照当前的剔除方式准备这一帧的绘制
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, frustum: &Frustum, gpu_culling: bool) {
+        self.instances.update(device, queue);
+        self.gpu_fallback = gpu_culling && !self.gpu_culler.update(device, queue, frustum, &self.instances);
+        if !self.uses_gpu_culling(gpu_culling) {
+            self.culler.update(device, queue, frustum, &self.model, &self.instances);
+        }
+    }
+
+    fn uses_gpu_culling(&self, gpu_culling: bool) -> bool {
+        gpu_culling && !self.gpu_fallback
+    }
+
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.gpu_fallback {
+            self.gpu_culler.cull(encoder);
+        }
+    }
+
+    // 阴影通道不做剔除，绘制全部实例
+    pub fn shadow_caster(&self) -> (&Model, &wgpu::Buffer, Range<u32>) {
+        (&self.model, self.instances.buffer(), 0..self.instances.raw().len() as u32)
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        gpu_culling: bool,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.uses_gpu_culling(gpu_culling) {
+            render_pass.draw_model_indirect(&self.model, &self.gpu_culler, camera_bind_group, light_bind_group);
+        } else {
+            render_pass.set_vertex_buffer(1, self.culler.buffer().slice(..));
+            render_pass.draw_model_batches(&self.model, self.culler.batches(), camera_bind_group, light_bind_group);
+        }
+    }
+
+    // 用调试管线绘制，第 i 个网格的 ID 为 first_mesh_id + i
+    pub fn draw_debug<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        gpu_culling: bool,
+        debug: &'a DebugRenderer,
+        first_mesh_id: u32,
+    ) {
+        if self.uses_gpu_culling(gpu_culling) {
+            for (i, batch) in self.gpu_culler.batches().iter().enumerate() {
+                let instances = DebugInstances::Indirect(
+                    self.gpu_culler.visible_slice(i),
+                    self.gpu_culler.indirect_buffer(),
+                    self.gpu_culler.indirect_offset(i),
+                );
+                debug.draw_mesh(render_pass, &self.model.meshes[batch.mesh], first_mesh_id + batch.mesh as u32, instances);
+            }
+        } else {
+            for batch in self.culler.batches() {
+                if batch.instances.is_empty() {
+                    continue;
+                }
+                let instances = DebugInstances::Direct(self.culler.buffer().slice(..), batch.instances.clone());
+                debug.draw_mesh(render_pass, &self.model.meshes[batch.mesh], first_mesh_id + batch.mesh as u32, instances);
+            }
+        }
+    }
+
+    // 所有实例的包围盒（世界空间）
+    pub fn bounds(&self) -> Aabb {
+        let bounds = self.model.bounds();
+        self.instances.instances().iter().fold(Aabb::EMPTY, |aabb, instance| {
+            aabb.union(&bounds.transform(&instance.model_matrix()))
+        })
+    }
+}
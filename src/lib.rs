@@ -1,22 +1,49 @@
-use std::{f32::consts, sync::Arc};
-use camera::{Camera, CameraController};
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
+use anyhow::Context;
+use bounds::Aabb;
+use bounds::Frustum;
+use culling::{CullingStats, GpuInstanceCuller};
+use debug::{DebugInstances, DebugRenderer, DebugTarget, DebugView};
+use hot_reload::ShaderWatcher;
+use camera::{Camera, CameraControl, FpsCameraController, OrbitCameraController, Projection};
 use image::GenericImageView;
-use instance::{Instance, InstanceRaw};
-use texture::Texture;
+use instance::{Instance, InstanceManager, InstanceRaw};
+use instanced_model::InstancedModel;
+use lights::LightManager;
+use preprocessor::{ShaderCache, ShaderDefines, ShaderLibrary};
+use reflection::ShaderReflection;
+use scene::{ModelId, NodeId, Scene};
+use scene_file::SceneFile;
+use shadow::{ShadowConfig, ShadowMap};
+use texture::{MipmapGenerator, Texture};
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{ElementState, Event, KeyEvent, StartCause, WindowEvent}, 
+    event::{DeviceEvent, ElementState, Event, KeyEvent, StartCause, WindowEvent}, 
     event_loop::{EventLoop, EventLoopWindowTarget}, 
-    keyboard::{Key, NamedKey}, 
-    window::{Window, WindowBuilder}
+    keyboard::{Key, KeyCode, NamedKey, PhysicalKey}, 
+    window::{CursorGrabMode, Window, WindowBuilder}
 };
 use model::Vertex;
+pub use options::ViewerOptions;
 
-mod texture;
-mod camera;
-mod instance;
-mod model;
+pub mod texture;
+mod gltf_loader;
+pub mod bounds;
+pub mod camera;
+pub mod culling;
+pub mod debug;
+pub mod hot_reload;
+pub mod instance;
+mod instanced_model;
+pub mod lights;
+pub mod model;
+pub mod options;
+pub mod preprocessor;
+pub mod reflection;
 mod resources;
+pub mod scene;
+pub mod scene_file;
+pub mod shadow;
 
 
 // const VERTICES: &[Vertex] = &[
@@ -65,16 +92,12 @@ mod resources;
 //     Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // E
 // ];
 
-const INDICES: &[u16] = &[
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
-];
-
 #[repr(C)]
 #[derive(Debug,Clone, Copy,bytemuck::Pod,bytemuck::Zeroable)]
 // 视图投影矩阵
 struct CameraUniform {
+    // 摄像机的世界坐标，计算高光时需要，使用 vec4 是为了满足 uniform 的 16 字节对齐
+    view_position: [f32;4],
     view_proj: [[f32;4];4]
 }
 
@@ -82,54 +105,118 @@ struct CameraUniform {
 
 impl CameraUniform {
     fn new()->Self {
-        Self { view_proj: glam::Mat4::IDENTITY.to_cols_array_2d() }
+        Self {
+            view_position: [0.0;4],
+            view_proj: glam::Mat4::IDENTITY.to_cols_array_2d()
+        }
     }
 
     fn update_view_proj(&mut self,camera: &Camera) {
+        self.view_position = camera.eye.extend(1.0).into();
         self.view_proj = camera.build_view_projection_matrix().to_cols_array_2d();
     }
 }
 
-struct State {
-    surface: wgpu::Surface<'static>,
+
+pub struct State {
+    surface: Option<wgpu::Surface<'static>>,
+    offscreen_target: Option<Texture>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     clear_color: wgpu::Color,
-    render_pipeline: wgpu::RenderPipeline,
-    // vertex_buffer: wgpu::Buffer,
-    // num_vertices: u32,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: Texture,
+    // 主渲染管线的各个变体，按着色器宏、深度模式和采样数缓存
+    render_pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    // 着色器源码（编译进程序或者热重载的目录），主着色器当前使用的宏和已编译的变体
+    shader_library: ShaderLibrary,
+    shader_defines: ShaderDefines,
+    shaders: ShaderCache,
+    reverse_z: bool,
+    // 与当前深度模式一致的投影，切换深度模式失败时恢复
+    depth_projection: Projection,
+    // 开启 reverse-Z 前的远平面，关闭时恢复
+    perspective_zfar: f32,
     camera: Camera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
-    camera_controller: CameraController,
-    instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
+    camera_controller: Box<dyn CameraControl>,
+    orbit_camera: bool,
+    lights: LightManager,
+    shadow_map: ShadowMap,
+    // 场景文件中的每个模型及其实例，至少有一个。load_model、set_instances 等操作第一个
+    models: Vec<InstancedModel>,
+    // 场景图中的节点和 models 的实例一起绘制
+    scene: Scene,
+    frustum_culling: bool,
+    gpu_culling: bool,
+    // 当前场景文件，reload_scene 重新加载它
+    scene_path: PathBuf,
     depth_texture: Texture,
-    obj_model: model::Model,
+    // MSAA 采样数，大于 1 时先渲染到 msaa_target 再解析到输出纹理
+    sample_count: u32,
+    supported_sample_counts: Vec<u32>,
+    msaa_target: Option<Texture>,
+    debug: DebugRenderer,
+    vertex_storage: bool,
+    // 新模型的 GPU 实例剔除器使用的着色器，热重载时一起替换
+    instance_cull_shader: wgpu::ShaderModule,
+    // 主着色器以外的每个着色器用到的文件，热重载时监视
+    shader_files: HashMap<&'static str, Vec<PathBuf>>,
+    // 开启着色器热重载时监视着色器目录中的文件
+    shader_watcher: Option<ShaderWatcher>,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    // 加载纹理时在 GPU 上生成 mipmap，各个模型共用
+    mipmaps: MipmapGenerator,
+}
+
+// 各个着色器的文件名，#include 的文件相对于它们所在的目录
+const SHADER_FILE: &str = "shader.wgsl";
+const DEBUG_SHADER_FILE: &str = "debug.wgsl";
+const SHADOW_SHADER_FILE: &str = "shadow.wgsl";
+const LIGHT_CULL_SHADER_FILE: &str = "light_cull.wgsl";
+const INSTANCE_CULL_SHADER_FILE: &str = "instance_cull.wgsl";
+// 热重载的目录中必须有的着色器
+const SHADER_FILES: &[&str] = &[SHADER_FILE, DEBUG_SHADER_FILE, SHADOW_SHADER_FILE, LIGHT_CULL_SHADER_FILE, INSTANCE_CULL_SHADER_FILE];
+const SHADER_ENTRY_POINTS: &[&str] = &["vs_main", "fs_main"];
+
+// 主着色器中纹理和摄像机的绑定组编号
+const TEXTURE_GROUP: u32 = 0;
+const CAMERA_GROUP: u32 = 1;
+
+/// 主着色器的功能宏：法线贴图
+pub const NORMAL_MAP: &str = "NORMAL_MAP";
+/// 主着色器的功能宏：阴影
+pub const SHADOWS: &str = "SHADOWS";
+
+// 主渲染管线的变体，相同的组合只创建一次
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    defines: ShaderDefines,
+    reverse_z: bool,
+    sample_count: u32,
 }
 
-const NUM_INSTANCES_PER_ROW: u32 = 10;
-const INSTANCE_DISPLACEMENT: glam::Vec3 = glam::Vec3::new(NUM_INSTANCES_PER_ROW as f32 * 0.5,0.0,NUM_INSTANCES_PER_ROW as f32 * 0.5);
+// 适配器支持时才开启的功能
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES.union(wgpu::Features::POLYGON_MODE_LINE);
+
+// 启动时加载的场景文件，位于 res 目录下
+const DEFAULT_SCENE: &str = "scenes/default.ron";
 
 impl State {
-    async fn new(window: Arc<Window>) -> Self {
+    async fn new(window: Arc<Window>, options: &ViewerOptions) -> anyhow::Result<Self> {
         let size = window.inner_size();
 
         // 获取GPU适配器（指向WebGPU API实现的实例）
         // Backends::all() : Vulkan, Metal, DX12, WebGL等后端
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor{
-            backends: wgpu::Backends::all(),
+            backends: options.backends,
             ..Default::default()
         });
 
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance.create_surface(window.clone())?;
         /*
          * 关于RequestAdapterOptions:
          * power_preference: LowPower(高续航:集成显卡) 和 HighPerformance(高功耗:独立显卡)
@@ -138,25 +225,35 @@ impl State {
          *
          */
         let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions{
+            power_preference: options.power_preference,
             compatible_surface: Some(&surface),
-            ..Default::default()
-        }).await.unwrap();
+            force_fallback_adapter: options.force_fallback_adapter,
+        }).await.with_context(|| format!("no suitable adapter for backends {:?}", options.backends))?;
+        log::info!("using adapter {:?}", adapter.get_info());
         
         let (device,queue) = adapter.request_device(
             &wgpu::DeviceDescriptor{
                 // 允许我们指定想要的扩展功能，但需要设备支持
-                required_features: wgpu::Features::empty(),
+                // 适配器支持时开启格式的扩展能力（否则 MSAA 只能使用 4 倍采样）和线框模式
+                required_features: adapter.features() & OPTIONAL_FEATURES,
                 // 该字段用于某些资源限制
                 required_limits: wgpu::Limits::default(),
                 label: None,
             },
             // 追踪API调用路径 
             None
-        ).await.unwrap();
+        ).await?;
 
         
 
         let caps = surface.get_capabilities(&adapter);
+        // Fifo 所有平台都支持
+        let present_mode = if caps.present_modes.contains(&options.present_mode) {
+            options.present_mode
+        } else {
+            log::warn!("present mode {:?} is not supported, using Fifo (supported: {:?})", options.present_mode, caps.present_modes);
+            wgpu::PresentMode::Fifo
+        };
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             // surface 如何在GPU上存储
@@ -165,7 +262,7 @@ impl State {
             height: size.height,
             // 展示平面和显示设备的同步
             // Fifo 指定了显示设备的刷新率做为渲染的帧速率，这本质上就是垂直同步
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode,
             alpha_mode: caps.alpha_modes[0],
             view_formats: vec![],
             // 延迟帧数？？？
@@ -173,102 +270,90 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        // 加载图像
-        let diffuse_bytes = include_bytes!("../happy-tree.png");
-        // let diffuse_image = image::load_from_memory(diffuse_bytes).unwrap();
-        // let diffuse_rgba = diffuse_image.to_rgba8();
-        // let dimensions = diffuse_image.dimensions(); 
-        let diffuse_texture = Texture::from_bytes(&device,&queue,diffuse_bytes,"happy-tree").unwrap();
-
-        // 创建纹理
-        // let texture_size = wgpu::Extent3d {
-        //     width: dimensions.0,
-        //     height: dimensions.1,
-        //     depth_or_array_layers: 1,
-        // };
-        // let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor{
-        //     size:texture_size,
-        //     mip_level_count: 1,
-        //     sample_count: 1,
-        //     dimension: wgpu::TextureDimension::D2,
-        //     // 大多数图像都是使用 sRGB 来存储的，我们需要在这里指定
-        //     format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        //     // TEXTURE_BINDING 表示我们要在着色器中使用这个纹理。
-        //     // COPY_DST 表示我们能将数据复制到这个纹理上。
-        //     usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        //     label: Some("diffuse_texture"),
-        //     view_formats: &[],
-        // });
-
-        // 使用命令队列来填充纹理数据
-        // queue.write_texture(
-        //     // 告诉 wgpu 从何处复制像素数据
-        //     wgpu::ImageCopyTexture{
-        //     texture: &diffuse_texture,
-        //     mip_level: 0,
-        //     origin: wgpu::Origin3d::ZERO,
-        //     aspect: wgpu::TextureAspect::All,
-        // }, &diffuse_rgba, 
-        //  // 纹理的内存布局
-        // wgpu::ImageDataLayout{
-        //     offset:0,
-        //     bytes_per_row: Some(4 * dimensions.0),
-        //     rows_per_image: Some(dimensions.1),
-        // }, texture_size);
-
-        // 创建纹理视图和采样器
-        // let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        // let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor{
-        //     address_mode_u: wgpu::AddressMode::ClampToEdge, // 边缘拉伸
-        //     address_mode_v: wgpu::AddressMode::ClampToEdge,
-        //     address_mode_w: wgpu::AddressMode::ClampToEdge,
-        //     mag_filter: wgpu::FilterMode::Linear, // 线性插值
-        //     min_filter: wgpu::FilterMode::Nearest, // 近处会有像素感
-        //     mipmap_filter: wgpu::FilterMode::Nearest,
-        //     ..Default::default()
-        // });
-
-        // 创建绑定组
-        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor{
-            entries: &[
-                // 绑定到 0 资源槽的纹理
-                wgpu::BindGroupLayoutEntry {
-                    binding:0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                // 绑定到 1 资源槽的采样器
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("texture_bind_group")
+        let scene_path = options.scene.clone().unwrap_or_else(|| resources::res_path(DEFAULT_SCENE));
+        let mut state = Self::build(&adapter, device, queue, config, Some(surface), scene_path).await?;
+        state.set_sample_count(options.sample_count)?;
+        if let Some(dir) = &options.shader_dir {
+            state.enable_shader_hot_reload(dir)?;
+        }
+        if let Some(model) = &options.model {
+            state.load_model(model).await.with_context(|| format!("failed to load model {}", model))?;
+        }
+        Ok(state)
+    }
+
+    /// 无窗口（离屏）渲染：不创建 surface，而是渲染到一张可以回读的颜色纹理上，
+    /// 用于 CI 或没有显示器的机器。使用 fallback 适配器（软渲染），保证在没有 GPU 的 Linux 上也能运行。
+    pub async fn new_headless(width: u32, height: u32, format: wgpu::TextureFormat) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor{
+            backends: wgpu::Backends::all(),
+            ..Default::default()
         });
 
+        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions{
+            compatible_surface: None,
+            force_fallback_adapter: true,
+            ..Default::default()
+        }).await.context("no fallback adapter available")?;
+
+        let (device,queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor{
+                required_features: adapter.features() & OPTIONAL_FEATURES,
+                required_limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None
+        ).await?;
+
+        // 没有 surface，这里的配置只用来记录渲染目标的尺寸和格式
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2
+        };
+
+        Self::build(&adapter, device, queue, config, None, resources::res_path(DEFAULT_SCENE)).await
+    }
+
+    async fn build(
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface<'static>>,
+        scene_path: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        // 没有 surface 时渲染到离屏纹理
+        let offscreen_target = match surface {
+            Some(_) => None,
+            None => Some(Texture::create_render_target(&device, &config, "offscreen_target")),
+        };
+
+        // 着色器，默认开启所有功能
+        let shader_library = ShaderLibrary::embedded();
+        let shader_defines = ShaderDefines::from_iter([NORMAL_MAP, SHADOWS]);
+        // 纹理和摄像机的绑定组布局从开启所有功能的变体中反射得到，各个变体共用；
+        // 顶点缓冲区布局是手写的，启动时与着色器的输入对照
+        let reflection = ShaderReflection::load(&shader_library, SHADER_FILE, &shader_defines)?;
+        reflection.validate_vertex_buffers("vs_main", &[model::ModelVertex::desc(), InstanceRaw::desc()])?;
+
+        // 材质的绑定组布局：漫反射纹理和采样器、法线贴图和采样器、材质的光照参数
+        let texture_bind_group_layout = reflection.create_bind_group_layout(&device, TEXTURE_GROUP, "texture_bind_group_layout")?;
+
+         // 模型、实例、摄像机、光源和清屏颜色都来自场景文件
+         let scene_file = SceneFile::load(&scene_path)?;
+         let mipmaps = MipmapGenerator::new(&device)?;
+         let instance_cull_shader = shader_library.compile(&device, INSTANCE_CULL_SHADER_FILE, &ShaderDefines::new(), GpuInstanceCuller::ENTRY_POINTS)?;
+         let models = Self::load_scene_models(&scene_file, &device, &queue, &texture_bind_group_layout, &instance_cull_shader.module, &mipmaps).await?;
+
          // 定义摄像机
-         let camera = Camera::new(config.width as f32 / config.height as f32);
+         let camera = scene_file.camera.to_camera(config.width as f32 / config.height as f32);
 
          let mut camera_uniform = CameraUniform::new();
          camera_uniform.update_view_proj(&camera);
@@ -277,21 +362,8 @@ impl State {
              contents: bytemuck::cast_slice(&[camera_uniform]),
              usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
          });
-         let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
-             label: Some("camera_bind_group_layout"), 
-             entries: &[
-                 wgpu::BindGroupLayoutEntry {
-                     binding: 0,
-                     visibility: wgpu::ShaderStages::VERTEX,
-                     ty: wgpu::BindingType::Buffer {
-                         ty: wgpu::BufferBindingType::Uniform,
-                         has_dynamic_offset: false,
-                         min_binding_size: None
-                     },
-                     count: None
-                 }
-             ]
-         });
+         // 片元着色器需要摄像机位置计算高光，可见性包括片元阶段
+         let camera_bind_group_layout = reflection.create_bind_group_layout(&device, CAMERA_GROUP, "camera_bind_group_layout")?;
          let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor{
              label:Some("camera_bind_group"),
              layout: &camera_bind_group_layout,
@@ -303,158 +375,145 @@ impl State {
              ]
          });
 
-         const SPACE_BETWEEN: f32 = 3.0;
-         // 创建实例缓冲区  10行10列
-         let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z|{
-            (0..NUM_INSTANCES_PER_ROW).map(move |x|{
-                let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                let z = SPACE_BETWEEN* (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-
-                // let position = glam::Vec3{x: x as f32, y: 0.0, z: z as f32} - INSTANCE_DISPLACEMENT;
-                let position = glam::Vec3{x,y:0.0,z};
-                let rotation = if position.length().abs() <= std::f32::EPSILON {
-                    glam::Quat::from_axis_angle(glam::Vec3::Z, 0.0)
-                } else {
-                    glam::Quat::from_axis_angle(position.normalize(), consts::FRAC_PI_4)
-                };
-
-                Instance { position, rotation }
-            }) 
-         }).collect::<Vec<_>>();
-         let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-            label: Some("instance_buffer"),
-            contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX
-         });
+         // 光源
+         let light_cull_shader = shader_library.compile(&device, LIGHT_CULL_SHADER_FILE, &ShaderDefines::new(), LightManager::ENTRY_POINTS)?;
+         let mut lights = LightManager::new(&device, &light_cull_shader.module, config.width, config.height);
+         for light in &scene_file.lights {
+             lights.add(light.to_light());
+         }
+         lights.update(&device, &queue, &camera);
+
+         // 平行光的阴影
+         let shadow_shader = shader_library.compile(&device, SHADOW_SHADER_FILE, &ShaderDefines::new(), ShadowMap::ENTRY_POINTS)?;
+         let mut shadow_map = ShadowMap::new(&device, shadow_shader.module, ShadowConfig::default());
+         shadow_map.update(&queue, &camera, lights.shadow_light());
 
          // 深度纹理
-         let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+         let depth_texture = texture::Texture::create_depth_texture(&device, &config, camera.projection.is_reverse_z(), 1, "depth_texture");
 
-        let clear_color = wgpu::Color::BLACK;
+        let clear_color = scene_file.clear_color();
 
-        // 着色器
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor{
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        let mut shaders = ShaderCache::new(SHADER_FILE, SHADER_ENTRY_POINTS);
+        let shader = shaders.get(&device, &shader_library, &shader_defines)?;
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor{
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: &[
                 &texture_bind_group_layout,
-                &camera_bind_group_layout
+                &camera_bind_group_layout,
+                lights.bind_group_layout(),
+                shadow_map.bind_group_layout()
             ],
             push_constant_ranges: &[],
         });
         // 渲染管线
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                compilation_options: Default::default(),
-                entry_point: "vs_main", // 指定函数的入口点
-                buffers: &[model::ModelVertex::desc(),InstanceRaw::desc()], // 定义传入什么类型的数据到顶点着色器
-            },
-            fragment: Some(wgpu::FragmentState{
-                module: &shader,
-                compilation_options: Default::default(),
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState{
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE), // 混合模式新像素替换旧像素
-                    write_mask: wgpu::ColorWrites::ALL, // 允许写入所有颜色通道
-                })],
-            }),
-            // 图元解释如何将顶点数据组织成三角形
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 每3个顶点组成一个三角形
-                strip_index_format: None,
-                // 确定三角形的朝向（上 左下 右下）
-                front_face: wgpu::FrontFace::Ccw, // Ccw指定顶点的帧缓冲区坐标（framebuffer coordinates）按逆时针顺序给出的三角形为朝前（面向屏幕外）
-                // 如何剔除三角形
-                cull_mode: Some(wgpu::Face::Back), // Back指定朝后（面向屏幕内）的三角形会被剔除（不被渲染）
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            // 启用深度测试
-            depth_stencil: Some(wgpu::DepthStencilState{
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                // 使用 LESS 意味着像素将被从后往前绘制，大于当前位置的深度值的像素将被丢弃
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1, // 多采样
-                mask: !0, 
-                alpha_to_coverage_enabled: false, // 抗锯齿
-            },
-            multiview: None
-        });
-
-        // 顶点缓存区数据
-        // let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //     label: Some("Vertex Buffer"),
-        //     // bytemuck::cast_slice() 将数据转换未&[u8]
-        //     contents: bytemuck::cast_slice(VERTICES),
-        //     usage: wgpu::BufferUsages::VERTEX,
-        // });
-
-        // let num_vertices = VERTICES.len() as u32;
-
-        // 索引缓存区数据
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let num_indices = INDICES.len() as u32;
+        let reverse_z = camera.projection.is_reverse_z();
+        let render_pipeline = create_render_pipeline(&device, &render_pipeline_layout, shader, config.format, reverse_z, 1);
+        let render_pipelines = HashMap::from([(PipelineKey { defines: shader_defines.clone(), reverse_z, sample_count: 1 }, render_pipeline)]);
 
-        let camera_controller = CameraController::new(0.2);
+        // 调试视图
+        let debug_shader = shader_library.compile(&device, DEBUG_SHADER_FILE, &ShaderDefines::new(), DebugRenderer::ENTRY_POINTS)?;
+        let debug = DebugRenderer::new(&device, debug_shader.module, &camera_bind_group_layout, DebugTarget { color_format: config.format, reverse_z, sample_count: 1 });
+        // 没有 PolygonMode::Line 时线框要在顶点着色器中读取存储缓冲区
+        let vertex_storage = adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::VERTEX_STORAGE);
+        let supported_sample_counts = supported_sample_counts(adapter, &device, config.format);
 
-        // 加载模型
-        let obj_model = resources::load_model("cube.obj", &device, &queue, &texture_bind_group_layout).await.unwrap();
+        // 每秒移动 4 个单位，鼠标每像素转动约 0.17°
+        let camera_controller: Box<dyn CameraControl> = Box::new(FpsCameraController::new(4.0, 0.003));
 
-        Self {
+        Ok(Self {
             surface,
+            offscreen_target,
             device,
             queue,
             config,
             size,
             clear_color,
-            render_pipeline,
-            // vertex_buffer,
-            // num_vertices,
-            index_buffer,
-            num_indices,
-            diffuse_bind_group,
-            diffuse_texture,
+            render_pipelines,
+            render_pipeline_layout,
+            shader_library,
+            shader_defines,
+            shaders,
+            reverse_z,
+            depth_projection: camera.projection,
+            perspective_zfar: match camera.projection {
+                Projection::Perspective { zfar, .. } => zfar,
+                _ => Projection::DEFAULT_ZFAR,
+            },
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
             camera_controller,
-            instances,
-            instance_buffer,
+            orbit_camera: false,
+            lights,
+            shadow_map,
+            models,
+            scene: Scene::new(),
+            frustum_culling: true,
+            gpu_culling: false,
+            scene_path,
             depth_texture,
-            obj_model
+            sample_count: 1,
+            supported_sample_counts,
+            msaa_target: None,
+            debug,
+            vertex_storage,
+            instance_cull_shader: instance_cull_shader.module,
+            shader_files: HashMap::from([
+                (DEBUG_SHADER_FILE, debug_shader.files),
+                (SHADOW_SHADER_FILE, shadow_shader.files),
+                (LIGHT_CULL_SHADER_FILE, light_cull_shader.files),
+                (INSTANCE_CULL_SHADER_FILE, instance_cull_shader.files),
+            ]),
+            shader_watcher: None,
+            texture_bind_group_layout,
+            mipmaps,
+        })
+    }
+
+    // 加载场景文件中的所有模型并生成实例，任何一个模型出错都返回错误
+    async fn load_scene_models(
+        scene_file: &SceneFile,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        cull_shader: &wgpu::ShaderModule,
+        mipmaps: &MipmapGenerator,
+    ) -> anyhow::Result<Vec<InstancedModel>> {
+        let mut models = Vec::with_capacity(scene_file.models.len());
+        for (i, desc) in scene_file.models.iter().enumerate() {
+            let path = format!("models[{}]", i);
+            let model = resources::load_model(&desc.file, device, queue, layout, mipmaps).await
+                .with_context(|| format!("{}.file: failed to load {:?}", path, desc.file))?;
+            desc.validate_materials(&path, model.materials.len())?;
+            let mut instanced = InstancedModel::new(device, cull_shader, model, desc.instances());
+            // 先上传一次，之后的 update 只上传修改过的实例
+            instanced.instances_mut().update(device, queue);
+            models.push(instanced);
         }
+        Ok(models)
     }
 
    
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
+            // 宽高比跟随窗口，否则画面会被拉伸
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
              // 需要在每次窗口改变时重新配置surface
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if self.offscreen_target.is_some() {
+                self.offscreen_target = Some(texture::Texture::create_render_target(&self.device, &self.config, "offscreen_target"));
+            }
             // 确保更新了 config 之后一定要更新 depth_texture，否则程序就会崩溃，
             // 因为此时 depth_texture 与surface 纹理的宽高已经不一致了
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth texture");
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, self.reverse_z, self.sample_count, "depth texture");
+            self.msaa_target = create_msaa_target(&self.device, &self.config, self.sample_count);
+            self.lights.resize(&self.device, new_size.width, new_size.height);
         }
     }
 
@@ -479,24 +538,630 @@ impl State {
 
         //     _ => false
         // }
-        self.camera_controller.process_events(event)
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                    ..
+                },
+                ..
+            } => {
+                self.frame_model();
+                true
+            }
+            // C 在第一人称和轨道控制器之间切换
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyC),
+                    ..
+                },
+                ..
+            } => {
+                self.orbit_camera = !self.orbit_camera;
+                if self.orbit_camera {
+                    self.set_camera_controller(OrbitCameraController::new(0.005));
+                } else {
+                    self.set_camera_controller(FpsCameraController::new(4.0, 0.003));
+                }
+                true
+            }
+            // G 在 CPU 和 GPU 视锥剔除之间切换
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyG),
+                    ..
+                },
+                ..
+            } => {
+                self.set_gpu_culling(!self.gpu_culling);
+                log::info!("gpu culling: {}", self.gpu_culling);
+                true
+            }
+            // L 检查上一帧是否有簇因为光源太多而丢弃了光源
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                    ..
+                },
+                ..
+            } => {
+                match self.light_cluster_overflow() {
+                    Ok(0) => log::info!("no light clusters overflowed"),
+                    Ok(overflowed) => log::warn!(
+                        "{} light clusters have more than {} lights, extra lights are dropped",
+                        overflowed,
+                        lights::MAX_LIGHTS_PER_CLUSTER
+                    ),
+                    Err(e) => log::error!("{:#}", e),
+                }
+                true
+            }
+            // M 在支持的 MSAA 采样数之间循环切换
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyM),
+                    ..
+                },
+                ..
+            } => {
+                let counts = &self.supported_sample_counts;
+                let next = counts.iter().position(|&c| c == self.sample_count).map_or(0, |i| (i + 1) % counts.len());
+                let sample_count = counts[next];
+                match self.set_sample_count(sample_count) {
+                    Ok(()) => log::info!("msaa: {}x", sample_count),
+                    Err(e) => log::error!("{:#}", e),
+                }
+                true
+            }
+            // V 在调试视图之间循环切换，跳过当前无法显示的视图（Shaded 总是可用）
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                    ..
+                },
+                ..
+            } => {
+                let mut view = self.debug.view().next();
+                while let Err(e) = self.set_debug_view(view) {
+                    log::warn!("skipping {:?}: {:#}", view, e);
+                    view = view.next();
+                }
+                log::info!("debug view: {:?}", view);
+                true
+            }
+            // N 开关法线贴图，切换着色器变体
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                    ..
+                },
+                ..
+            } => {
+                let enabled = !self.shader_defines.contains(NORMAL_MAP);
+                match self.set_shader_define(NORMAL_MAP, enabled) {
+                    Ok(()) => log::info!("normal map: {}", enabled),
+                    Err(e) => log::error!("{:#}", e),
+                }
+                true
+            }
+            // R 重新加载场景文件，出错时保留当前场景
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                    ..
+                },
+                ..
+            } => {
+                match pollster::block_on(self.reload_scene()) {
+                    Ok(()) => log::info!("reloaded {}", self.scene_path.display()),
+                    Err(e) => log::error!("{:#}", e),
+                }
+                true
+            }
+            _ => self.camera_controller.process_events(event),
+        }
+    }
+
+    pub fn set_camera_controller(&mut self, controller: impl CameraControl + 'static) {
+        self.camera_controller = Box::new(controller);
+    }
+
+    // 所有实例和场景节点的包围盒（世界空间）
+    pub fn scene_bounds(&self) -> Aabb {
+        self.models.iter().fold(self.scene.bounds(), |aabb, model| aabb.union(&model.bounds()))
+    }
+
+    // 让摄像机取景到整个模型
+    pub fn frame_model(&mut self) {
+        self.scene.update_world_transforms();
+        let bounds = self.scene_bounds();
+        self.camera.frame(&bounds);
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn lights_mut(&mut self) -> &mut LightManager {
+        &mut self.lights
+    }
+
+    pub fn set_shadow_config(&mut self, config: ShadowConfig) {
+        self.shadow_map.set_config(&self.device, config);
+    }
+
+    // 替换第一个模型，实例保持不变
+    pub async fn load_model(&mut self, file_name: &str) -> anyhow::Result<()> {
+        let model = resources::load_model(file_name, &self.device, &self.queue, &self.texture_bind_group_layout, &self.mipmaps).await?;
+        self.models[0].set_model(model);
+        Ok(())
+    }
+
+    // 替换第一个模型的全部实例，下一次 update 时上传
+    pub fn set_instances(&mut self, instances: Vec<Instance>) {
+        self.models[0].instances_mut().set(instances);
+    }
+
+    /// 加载场景文件，替换全部模型、实例、摄像机、光源和清屏颜色。
+    /// 文件有错误或者模型加载失败时返回错误，当前场景保持不变
+    pub async fn load_scene_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let scene_file = SceneFile::load(path)?;
+        let mut models = Self::load_scene_models(&scene_file, &self.device, &self.queue, &self.texture_bind_group_layout, &self.instance_cull_shader, &self.mipmaps).await
+            .with_context(|| format!("invalid scene file {}", path.display()))?;
+
+        for model in &mut models {
+            model.set_frustum_culling(self.frustum_culling);
+        }
+        self.models = models;
+        self.camera = scene_file.camera.to_camera(self.camera.aspect);
+        self.lights.clear();
+        for light in &scene_file.lights {
+            self.lights.add(light.to_light());
+        }
+        self.clear_color = scene_file.clear_color();
+        self.scene_path = path.to_path_buf();
+        Ok(())
     }
 
-    fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
+    // 重新加载当前的场景文件
+    pub async fn reload_scene(&mut self) -> anyhow::Result<()> {
+        let path = self.scene_path.clone();
+        self.load_scene_file(path).await
+    }
+
+    pub fn scene_path(&self) -> &Path {
+        &self.scene_path
+    }
+
+    pub fn mipmaps(&self) -> &MipmapGenerator {
+        &self.mipmaps
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    // 修改在下一次 update 时生效
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    // 加载模型并加入场景，返回的 ModelId 用于挂载到节点上
+    pub async fn load_scene_model(&mut self, file_name: &str) -> anyhow::Result<ModelId> {
+        let model = resources::load_model(file_name, &self.device, &self.queue, &self.texture_bind_group_layout, &self.mipmaps).await?;
+        Ok(self.scene.add_model(model))
+    }
+
+    // 使用场景中摄像机节点的视角，节点没有摄像机时返回 false
+    pub fn use_scene_camera(&mut self, node: NodeId) -> bool {
+        self.scene.update_world_transforms();
+        match self.scene.camera(node, self.camera.aspect) {
+            Some(camera) => {
+                self.camera = camera;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 增删改第一个模型的实例，修改在下一次 update 时上传
+    pub fn instances_mut(&mut self) -> &mut InstanceManager {
+        self.models[0].instances_mut()
+    }
+
+    pub fn instances(&self) -> &InstanceManager {
+        self.models[0].instances()
+    }
+
+    // 第 index 个模型的实例，顺序与场景文件中的 models 相同
+    pub fn model_instances(&self, index: usize) -> Option<&InstanceManager> {
+        self.models.get(index).map(|model| model.instances())
+    }
+
+    pub fn model_instances_mut(&mut self, index: usize) -> Option<&mut InstanceManager> {
+        self.models.get_mut(index).map(|model| model.instances_mut())
+    }
+
+    // 上一次 update 时所有模型视锥剔除的统计之和
+    pub fn culling_stats(&self) -> CullingStats {
+        self.models.iter().map(|model| model.culling_stats()).fold(CullingStats::default(), |sum, stats| CullingStats {
+            total_instances: sum.total_instances + stats.total_instances,
+            visible_instances: sum.visible_instances + stats.visible_instances,
+            total_draws: sum.total_draws + stats.total_draws,
+            visible_draws: sum.visible_draws + stats.visible_draws,
+        })
+    }
+
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+        for model in &mut self.models {
+            model.set_frustum_culling(enabled);
+        }
+    }
+
+    pub fn set_gpu_culling(&mut self, enabled: bool) {
+        self.gpu_culling = enabled;
+    }
+
+    pub fn is_gpu_culling(&self) -> bool {
+        self.gpu_culling
+    }
+
+    /// 回读最近一帧 GPU 剔除后每个网格的可见实例数量（会等待 GPU 完成），多个模型的结果依次排列
+    pub fn gpu_visible_counts(&self) -> anyhow::Result<Vec<u32>> {
+        let mut counts = Vec::new();
+        for model in &self.models {
+            counts.extend(model.gpu_visible_counts(&self.device, &self.queue)?);
+        }
+        Ok(counts)
+    }
+
+    // 上一帧中光源数量超过容量的簇的数量，会阻塞等待 GPU
+    pub fn light_cluster_overflow(&self) -> anyhow::Result<u32> {
+        self.lights.read_overflowed_clusters(&self.device, &self.queue)
+    }
+
+    pub fn mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.camera_controller.process_mouse_motion(dx, dy);
+    }
+
+    pub fn is_mouse_captured(&self) -> bool {
+        self.camera_controller.is_mouse_captured()
+    }
+
+    pub fn set_mouse_captured(&mut self, captured: bool) {
+        self.camera_controller.set_mouse_captured(captured);
+    }
+
+    /// 开启或关闭 reverse-Z：在普通透视和无限远 reverse-Z 透视之间切换，保留视野和近平面，关闭时恢复原来的远平面。
+    /// 正交投影不支持 reverse-Z，开启时返回错误；管线创建失败时投影保持不变
+    pub fn set_reverse_z(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.camera.projection = match (self.camera.projection, enabled) {
+            (Projection::Perspective { fovy, znear, zfar }, true) => {
+                self.perspective_zfar = zfar;
+                Projection::InfinitePerspectiveReverseZ { fovy, znear }
+            }
+            (Projection::InfinitePerspectiveReverseZ { fovy, znear }, false) => Projection::Perspective { fovy, znear, zfar: self.perspective_zfar },
+            (projection @ Projection::Orthographic { .. }, true) => {
+                anyhow::bail!("reverse-Z needs a perspective projection, the camera uses {:?}", projection)
+            }
+            (projection, _) => projection,
+        };
+        self.sync_depth_mode()
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // 深度缓冲的比较方向和清除值必须与摄像机的投影一致，投影改变后重建管线和深度纹理。
+    // 管线创建失败时把摄像机恢复到上一次与深度模式一致的投影
+    fn sync_depth_mode(&mut self) -> anyhow::Result<()> {
+        let reverse_z = self.camera.projection.is_reverse_z();
+        if reverse_z != self.reverse_z {
+            let key = PipelineKey { reverse_z, ..self.pipeline_key() };
+            if let Err(e) = self.ensure_render_pipeline(&key) {
+                self.camera.projection = self.depth_projection;
+                return Err(e);
+            }
+            self.reverse_z = reverse_z;
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, reverse_z, self.sample_count, "depth texture");
+            self.debug.set_target(&self.device, self.debug_target());
+        }
+        self.depth_projection = self.camera.projection;
+        Ok(())
+    }
+
+    /// 设置 MSAA 采样数（1 表示关闭），会重新创建渲染管线和多重采样的颜色、深度纹理。
+    /// 适配器不支持该采样数时返回错误，设置保持不变
+    pub fn set_sample_count(&mut self, sample_count: u32) -> anyhow::Result<()> {
+        if !self.supported_sample_counts.contains(&sample_count) {
+            anyhow::bail!(
+                "{}x MSAA is not supported for {:?} (supported: {:?})",
+                sample_count, self.config.format, self.supported_sample_counts
+            );
+        }
+        if sample_count == self.sample_count {
+            return Ok(());
+        }
+        if sample_count > 1 && self.debug.view() == DebugView::Depth {
+            anyhow::bail!("MSAA cannot be enabled while the depth view is active");
+        }
+        self.ensure_render_pipeline(&PipelineKey { sample_count, ..self.pipeline_key() })?;
+        self.sample_count = sample_count;
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, self.reverse_z, sample_count, "depth texture");
+        self.msaa_target = create_msaa_target(&self.device, &self.config, sample_count);
+        self.debug.set_target(&self.device, self.debug_target());
+        Ok(())
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // 颜色格式和深度格式都支持的采样数，从小到大排列，总是包含 1
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &self.supported_sample_counts
+    }
+
+    fn debug_target(&self) -> DebugTarget {
+        DebugTarget { color_format: self.config.format, reverse_z: self.reverse_z, sample_count: self.sample_count }
+    }
+
+    /// 切换调试视图。没有 PolygonMode::Line 又不支持在顶点着色器中读取存储缓冲区时无法显示线框，
+    /// 开启 MSAA 时无法显示深度（多重采样的深度纹理只作为附件，见 create_depth_texture），这两种情况返回错误
+    pub fn set_debug_view(&mut self, view: DebugView) -> anyhow::Result<()> {
+        if view == DebugView::Wireframe && !self.debug.is_line_mode() && !self.vertex_storage {
+            anyhow::bail!("wireframe view needs POLYGON_MODE_LINE or vertex shader storage buffers");
+        }
+        if view == DebugView::Depth && self.sample_count > 1 {
+            anyhow::bail!("depth view needs MSAA to be off: the multisampled depth texture cannot be sampled");
+        }
+        self.debug.set_view(&self.device, view);
+        Ok(())
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug.view()
+    }
+
+    /// 线框视图不使用 PolygonMode::Line，改用重心坐标在片元着色器中画线。设备不支持线框模式时总是如此
+    pub fn set_barycentric_wireframe(&mut self, enabled: bool) -> anyhow::Result<()> {
+        if enabled && !self.vertex_storage {
+            anyhow::bail!("barycentric wireframe needs vertex shader storage buffers");
+        }
+        self.debug.set_barycentric_wireframe(&self.device, enabled);
+        Ok(())
+    }
+
+    pub fn is_line_wireframe(&self) -> bool {
+        self.debug.is_line_mode()
+    }
+
+    /// 打开或关闭主着色器的功能宏（NORMAL_MAP、SHADOWS），切换到对应的着色器变体。
+    /// 每个变体只在第一次使用时编译，编译失败时返回错误，设置保持不变
+    pub fn set_shader_define(&mut self, name: &str, enabled: bool) -> anyhow::Result<()> {
+        let mut defines = self.shader_defines.clone();
+        defines.set(name, enabled);
+        self.ensure_render_pipeline(&PipelineKey { defines: defines.clone(), ..self.pipeline_key() })?;
+        self.shader_defines = defines;
+        Ok(())
+    }
+
+    pub fn shader_defines(&self) -> &ShaderDefines {
+        &self.shader_defines
+    }
+
+    // 已编译的主着色器变体和已创建的主渲染管线的数量
+    pub fn shader_variant_count(&self) -> usize {
+        self.shaders.len()
+    }
+
+    pub fn render_pipeline_count(&self) -> usize {
+        self.render_pipelines.len()
+    }
+
+    fn pipeline_key(&self) -> PipelineKey {
+        PipelineKey { defines: self.shader_defines.clone(), reverse_z: self.reverse_z, sample_count: self.sample_count }
+    }
+
+    // 缓存中没有 key 对应的管线时编译着色器变体并创建管线
+    fn ensure_render_pipeline(&mut self, key: &PipelineKey) -> anyhow::Result<()> {
+        if self.render_pipelines.contains_key(key) {
+            return Ok(());
+        }
+        self.shaders.get(&self.device, &self.shader_library, &key.defines)?;
+        let pipeline = self.create_main_pipeline(&self.shaders, key)?;
+        self.render_pipelines.insert(key.clone(), pipeline);
+        Ok(())
+    }
+
+    // 用 shaders 中已编译的变体创建主渲染管线，着色器与管线布局不一致时返回错误
+    fn create_main_pipeline(&self, shaders: &ShaderCache, key: &PipelineKey) -> anyhow::Result<wgpu::RenderPipeline> {
+        let shader = shaders.module(&key.defines).context("shader variant is not compiled")?;
+        hot_reload::catch_validation(&self.device, || {
+            create_render_pipeline(&self.device, &self.render_pipeline_layout, shader, self.config.format, key.reverse_z, key.sample_count)
+        })
+        .with_context(|| format!("{}: failed to create render pipeline", self.shader_library.path(SHADER_FILE).display()))
+    }
+
+    /// 开发模式：从 dir 读取全部着色器（主着色器、调试、阴影、光源剔除和实例剔除）和它们 #include 的文件代替编译进程序的版本，
+    /// 之后每帧检查文件是否修改并重新加载。
+    /// 文件不存在时返回错误；着色器有错误时只记录日志，继续使用当前的着色器，修正后会自动重新加载
+    pub fn enable_shader_hot_reload(&mut self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        for file in SHADER_FILES {
+            let path = dir.join(file);
+            anyhow::ensure!(path.is_file(), "shader hot reload: {} not found", path.display());
+        }
+        self.shader_library = ShaderLibrary::from_dir(dir);
+        if let Err(e) = self.reload_shaders() {
+            log::error!("{:#}", e);
+        }
+        Ok(())
+    }
+
+    /// 重新加载着色器目录中的全部着色器，之前编译的变体全部作废。
+    /// 出错的着色器保留上一次成功的版本，所有错误合并返回
+    pub fn reload_shaders(&mut self) -> anyhow::Result<()> {
+        let dir = self.shader_library.dir().context("shader hot reload is not enabled")?.to_path_buf();
+        let errors = [
+            self.reload_main_shader(),
+            self.reload_debug_shader(),
+            self.reload_shadow_shader(),
+            self.reload_light_cull_shader(),
+            self.reload_instance_cull_shader(),
+        ]
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|e| format!("{:#}", e))
+            .collect::<Vec<_>>();
+
+        // 监视着色器用到的所有文件，新 include 的文件也包括在内
+        let mut files = SHADER_FILES.iter().map(|file| dir.join(file)).collect::<Vec<_>>();
+        files.extend(self.shaders.files().cloned());
+        files.extend(self.shader_files.values().flatten().cloned());
+        files.retain(|path| path.starts_with(&dir));
+        files.sort();
+        files.dedup();
+        self.shader_watcher = Some(ShaderWatcher::new(files));
+
+        if !errors.is_empty() {
+            anyhow::bail!(errors.join("\n"));
+        }
+        Ok(())
+    }
+
+    // 用新的源码编译当前的变体，成功后替换全部缓存，其他变体在下次使用时重新编译
+    fn reload_main_shader(&mut self) -> anyhow::Result<()> {
+        let key = self.pipeline_key();
+        let mut shaders = ShaderCache::new(SHADER_FILE, SHADER_ENTRY_POINTS);
+        shaders.get(&self.device, &self.shader_library, &key.defines)?;
+        let pipeline = self.create_main_pipeline(&shaders, &key)?;
+        self.shaders = shaders;
+        self.render_pipelines = HashMap::from([(key, pipeline)]);
+        Ok(())
+    }
+
+    fn reload_debug_shader(&mut self) -> anyhow::Result<()> {
+        let shader = self.shader_library.compile(&self.device, DEBUG_SHADER_FILE, &ShaderDefines::new(), DebugRenderer::ENTRY_POINTS)?;
+        let path = self.shader_library.path(DEBUG_SHADER_FILE);
+        self.debug.set_shader(&self.device, shader.module).with_context(|| format!("{}: failed to create pipelines", path.display()))?;
+        self.shader_files.insert(DEBUG_SHADER_FILE, shader.files);
+        Ok(())
+    }
+
+    fn reload_shadow_shader(&mut self) -> anyhow::Result<()> {
+        let shader = self.shader_library.compile(&self.device, SHADOW_SHADER_FILE, &ShaderDefines::new(), ShadowMap::ENTRY_POINTS)?;
+        let path = self.shader_library.path(SHADOW_SHADER_FILE);
+        self.shadow_map.set_shader(&self.device, shader.module).with_context(|| format!("{}: failed to create pipeline", path.display()))?;
+        self.shader_files.insert(SHADOW_SHADER_FILE, shader.files);
+        Ok(())
+    }
+
+    fn reload_light_cull_shader(&mut self) -> anyhow::Result<()> {
+        let shader = self.shader_library.compile(&self.device, LIGHT_CULL_SHADER_FILE, &ShaderDefines::new(), LightManager::ENTRY_POINTS)?;
+        let path = self.shader_library.path(LIGHT_CULL_SHADER_FILE);
+        self.lights.set_cull_shader(&self.device, &shader.module).with_context(|| format!("{}: failed to create pipeline", path.display()))?;
+        self.shader_files.insert(LIGHT_CULL_SHADER_FILE, shader.files);
+        Ok(())
+    }
+
+    // 所有模型的剔除器使用同一个着色器，之后加载的模型也使用新的版本
+    fn reload_instance_cull_shader(&mut self) -> anyhow::Result<()> {
+        let shader = self.shader_library.compile(&self.device, INSTANCE_CULL_SHADER_FILE, &ShaderDefines::new(), GpuInstanceCuller::ENTRY_POINTS)?;
+        let path = self.shader_library.path(INSTANCE_CULL_SHADER_FILE);
+        for model in &mut self.models {
+            model.set_cull_shader(&self.device, &shader.module).with_context(|| format!("{}: failed to create pipeline", path.display()))?;
+        }
+        self.instance_cull_shader = shader.module;
+        self.shader_files.insert(INSTANCE_CULL_SHADER_FILE, shader.files);
+        Ok(())
+    }
+
+    // dt 是距离上一帧经过的时间
+    pub fn update(&mut self, dt: Duration) {
+        let changed = self.shader_watcher.as_mut().map(|watcher| watcher.poll()).unwrap_or_default();
+        if !changed.is_empty() {
+            // include 的文件可能被多个着色器使用，任何文件改变都重新加载全部着色器
+            match self.reload_shaders() {
+                Ok(()) => log::info!("reloaded shaders after changes to {:?}", changed),
+                Err(e) => log::error!("{:#}", e),
+            }
+        }
+        self.camera_controller.update_camera(&mut self.camera, dt);
+        if let Err(e) = self.sync_depth_mode() {
+            log::error!("{:#}", e);
+        }
         self.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        let frustum = Frustum::from_view_projection(&self.camera.build_view_projection_matrix());
+        for model in &mut self.models {
+            model.update(&self.device, &self.queue, &frustum, self.gpu_culling);
+        }
+        self.scene.prepare(&self.device, &self.queue);
+        self.lights.update(&self.device, &self.queue, &self.camera);
+        self.shadow_map.update(&self.queue, &self.camera, self.lights.shadow_light());
+        if self.debug.view() != DebugView::Shaded {
+            // 网格 ID 按绘制顺序分配：先是各个模型，然后是场景图
+            let mut meshes = self.models.iter().flat_map(|model| &model.model().meshes).collect::<Vec<_>>();
+            meshes.extend(self.scene.draws().flat_map(|(model, _, _)| &model.meshes));
+            self.debug.prepare(&self.device, &self.queue, &meshes, &self.depth_texture, &self.camera);
+        }
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let Some(surface) = &self.surface else {
+            // 离屏模式直接渲染到离屏纹理
+            if let Some(target) = &self.offscreen_target {
+                self.draw(&target.view);
+            }
+            return Ok(());
+        };
         // 等待surface提供一个SurfaceTexture
-        let out = self.surface.get_current_texture()?;
+        let out = surface.get_current_texture()?;
         // 创建一个默认的纹理视图，渲染代码使用纹理视图和纹理进行交互
         let view = out.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view);
+        out.present();
+
+        Ok(())
+    }
+
+    // 将场景绘制到给定的颜色附件上
+    fn draw(&self, view: &wgpu::TextureView) {
         // 创建一个命令编码器记录实际命令发送给GPU,(命令编码器会创建一个命令缓冲区)
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("Render Encoder"),
         });
+        // 先用计算着色器为每个簇剔除光源
+        self.lights.cull(&mut encoder);
+        if self.gpu_culling {
+            for model in &self.models {
+                model.cull(&mut encoder);
+            }
+        }
+        // 从光源方向渲染阴影贴图
+        let mut casters = self.models.iter().map(|model| model.shadow_caster()).collect::<Vec<_>>();
+        casters.extend(self.scene.draws());
+        self.shadow_map.render(&mut encoder, &casters);
         {
             // 创建渲染通道来编码所有实际绘制的命令
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
@@ -505,71 +1170,195 @@ impl State {
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment{
                     view: &self.depth_texture.view,
                     depth_ops: Some(wgpu::Operations{
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(Texture::depth_clear_value(self.reverse_z)),
                         store: wgpu::StoreOp::Store
                     }),
                     stencil_ops: None
                 }),
                 color_attachments: &[
                     // 这个时片元着色器中@location(0) 标记指向的颜色附件
-                    Some(wgpu::RenderPassColorAttachment{
-                    // 要渲染的纹理视图
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear( wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store
-                    }
-                })],
+                    // 开启 MSAA 时渲染到多重采样纹理，通道结束时解析到 view，多重采样的内容不需要保留
+                    Some(match &self.msaa_target {
+                        Some(msaa_target) => wgpu::RenderPassColorAttachment {
+                            view: &msaa_target.view,
+                            resolve_target: Some(view),
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(self.clear_color),
+                                store: wgpu::StoreOp::Discard
+                            }
+                        },
+                        None => wgpu::RenderPassColorAttachment{
+                            // 要渲染的纹理视图
+                            view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(self.clear_color),
+                                store: wgpu::StoreOp::Store
+                            }
+                        },
+                    })],
                 ..Default::default()
             });
-            // render_pass.set_pipeline(&self.render_pipeline);
-            // render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            // render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            // render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            // render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            // render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            // //告诉 wgpu 用 3 个顶点和 1 个实例（实例的索引就是 @builtin(vertex_index) 的由来）来进行绘制。
-            // // render_pass.draw(0..self.num_vertices,0..1);
-            // render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
-        
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_pipeline(&self.render_pipeline);
-           
+            if self.debug.view().replaces_shading() {
+                // 调试视图按 update 中分配网格 ID 的顺序绘制
+                self.debug.begin(&mut render_pass, &self.camera_bind_group);
+                let mut mesh_id = 0;
+                for model in &self.models {
+                    model.draw_debug(&mut render_pass, self.gpu_culling, &self.debug, mesh_id);
+                    mesh_id += model.model().meshes.len() as u32;
+                }
+                for (model, buffer, instances) in self.scene.draws() {
+                    for mesh in &model.meshes {
+                        self.debug.draw_mesh(&mut render_pass, mesh, mesh_id, DebugInstances::Direct(buffer.slice(..), instances.clone()));
+                        mesh_id += 1;
+                    }
+                }
+            } else {
+                render_pass.set_pipeline(&self.render_pipelines[&self.pipeline_key()]);
+                render_pass.set_bind_group(3, self.shadow_map.bind_group(), &[]);
 
-            use model::DrawModel;
-            // let mesh = &self.obj_model.meshes[0];
-            // let material = &self.obj_model.materials[mesh.material];
-            // render_pass.draw_mesh_instanced(mesh, 0..self.instances.len() as u32,material,&self.camera_bind_group);
-            render_pass.draw_model_instanced(&self.obj_model, 0..self.instances.len() as u32, &self.camera_bind_group)
+                use scene::DrawScene;
+                // let mesh = &self.obj_model.meshes[0];
+                // let material = &self.obj_model.materials[mesh.material];
+                // render_pass.draw_mesh_instanced(mesh, 0..self.instances.len() as u32,material,&self.camera_bind_group);
+                for model in &self.models {
+                    model.draw(&mut render_pass, self.gpu_culling, &self.camera_bind_group, self.lights.bind_group());
+                }
+                render_pass.draw_scene(&self.scene, &self.camera_bind_group, self.lights.bind_group());
+            }
+        }
+        if self.debug.view() == DebugView::Depth {
+            self.debug.draw_depth(&mut encoder, view);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        out.present();
+    }
+
+    /// 渲染一帧到离屏纹理并回读为 RGBA 图像
+    pub fn capture_frame(&mut self) -> anyhow::Result<image::RgbaImage> {
+        // 有窗口时 surface 纹理不能复制，另外绘制一遍到临时的离屏纹理上
+        let temporary_target;
+        let target = match &self.offscreen_target {
+            Some(target) => target,
+            None => {
+                temporary_target = Texture::create_render_target(&self.device, &self.config, "capture_target");
+                &temporary_target
+            }
+        };
+        self.draw(&target.view);
+
+        let (width, height) = (self.config.width, self.config.height);
+        let bytes_per_pixel = self.config.format.block_copy_size(None).context("unsupported capture format")?;
+        // 纹理复制到缓冲区时，每行字节数必须是 256 的倍数
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture_buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Capture Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            target.texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        // 映射缓冲区并等待 GPU 完成
+        let buffer_slice = output_buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        // 去掉每行末尾的填充字节
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = buffer_slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        output_buffer.unmap();
 
+        match self.config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {}
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                for pixel in pixels.chunks_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            format => anyhow::bail!("cannot capture frames in {:?}", format),
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels).context("captured frame has wrong size")
+    }
+
+    /// 渲染一帧并保存为 PNG
+    pub fn save_frame(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let frame = self.capture_frame()?;
+        frame.save_with_format(path, image::ImageFormat::Png)?;
         Ok(())
     }
 }
 
-pub async fn run() {
+pub async fn run(options: ViewerOptions) -> anyhow::Result<()> {
     env_logger::init();
-    let event_loop = EventLoop::new().unwrap();
-    let window = Arc::new(WindowBuilder::new().build(&event_loop).unwrap());
-    let mut state = State::new(window.clone()).await;
+    let event_loop = EventLoop::new()?;
+    let window = Arc::new(WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height))
+        .build(&event_loop)?);
+    let mut state = State::new(window.clone(), &options).await?;
+    let mut last_render_time = Instant::now();
+    let mut cursor_grabbed = false;
+    // 只指定截图时渲染一帧
+    let frame_limit = options.frames.or(options.screenshot.as_ref().map(|_| 1));
+    let mut frames_rendered = 0;
+    let mut result = Ok(());
     // 事件循环处理
-    let _ = EventLoop::run(event_loop, 
-      move |event: Event<()>,elwt:&EventLoopWindowTarget<()>| {
+    EventLoop::run(event_loop, 
+      |event: Event<()>,elwt:&EventLoopWindowTarget<()>| {
          if event == Event::NewEvents(StartCause::Init) {
             // 事件启动阶段
          }
 
+         // 原始的鼠标位移，不受光标位置和窗口边界的影响
+         if let Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } = event {
+            state.mouse_motion(delta.0, delta.1);
+         }
+
          if let Event::WindowEvent {event, ..} = event {
-            if !state.input(&event) {
+            // 窗口失去焦点时释放光标
+            if event == WindowEvent::Focused(false) {
+                state.set_mouse_captured(false);
+            }
+            let consumed = state.input(&event);
+            if cursor_grabbed != state.is_mouse_captured() {
+                cursor_grabbed = state.is_mouse_captured();
+                grab_cursor(&window, cursor_grabbed);
+            }
+            if !consumed {
                 match event {
                     WindowEvent::KeyboardInput {
                         event: KeyEvent {
@@ -585,12 +1374,24 @@ pub async fn run() {
                     }
 
                     WindowEvent::RedrawRequested => {
-                        state.update();
+                        let now = Instant::now();
+                        let dt = now - last_render_time;
+                        last_render_time = now;
+                        state.update(dt);
                         match state.render() {
-                            Ok(_) => {},
+                            Ok(_) => frames_rendered += 1,
                             // 展示平面丢失上下文，需要重新配置
                             Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
                             Err(e) => eprintln!("{:?}", e),
+                        }
+                        // 渲染完指定的帧数后截图并退出
+                        if frame_limit.is_some_and(|limit| frames_rendered >= limit) {
+                            if let Some(path) = &options.screenshot {
+                                result = state.save_frame(path)
+                                    .with_context(|| format!("failed to save screenshot {}", path.display()));
+                            }
+                            elwt.exit();
+                            return;
                         }
                          // 除非我们手动请求，RedrawRequested 将只会触发一次。
                         window.request_redraw();
@@ -600,6 +1401,106 @@ pub async fn run() {
             }
             
          }   
-      });
+      })?;
 
-}
\ No newline at end of file
+    result
+}
+// 捕获光标用于鼠标视角。部分平台不支持 Locked，退回到 Confined
+fn grab_cursor(window: &Window, grab: bool) {
+    let result = if grab {
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(e) = result {
+        log::warn!("failed to change cursor grab: {}", e);
+    }
+    window.set_cursor_visible(!grab);
+}
+
+// 采样数为 1 时不需要多重采样纹理
+fn create_msaa_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Option<Texture> {
+    (sample_count > 1).then(|| Texture::create_msaa_target(device, config, sample_count, "msaa_target"))
+}
+
+// 颜色格式和深度格式都支持、并且颜色格式可以解析的 MSAA 采样数。
+// 没有开启 TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES 时只能使用 WebGPU 保证的能力（1 和 4）
+fn supported_sample_counts(adapter: &wgpu::Adapter, device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Vec<u32> {
+    let flags = |format: wgpu::TextureFormat| {
+        if device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            adapter.get_texture_format_features(format).flags
+        } else {
+            format.guaranteed_format_features(device.features()).flags
+        }
+    };
+    let (color, depth) = (flags(color_format), flags(Texture::DEPTH_FORMAT));
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&count| {
+            color.sample_count_supported(count)
+                && depth.sample_count_supported(count)
+                && (count == 1 || color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE))
+        })
+        .collect()
+}
+
+// 主渲染管线。切换深度模式或 MSAA 采样数时需要重新创建
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    color_format: wgpu::TextureFormat,
+    reverse_z: bool,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            compilation_options: Default::default(),
+            entry_point: "vs_main", // 指定函数的入口点
+            buffers: &[model::ModelVertex::desc(),InstanceRaw::desc()], // 定义传入什么类型的数据到顶点着色器
+        },
+        fragment: Some(wgpu::FragmentState{
+            module: shader,
+            compilation_options: Default::default(),
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState{
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE), // 混合模式新像素替换旧像素
+                write_mask: wgpu::ColorWrites::ALL, // 允许写入所有颜色通道
+            })],
+        }),
+        // 图元解释如何将顶点数据组织成三角形
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 每3个顶点组成一个三角形
+            strip_index_format: None,
+            // 确定三角形的朝向（上 左下 右下）
+            front_face: wgpu::FrontFace::Ccw, // Ccw指定顶点的帧缓冲区坐标（framebuffer coordinates）按逆时针顺序给出的三角形为朝前（面向屏幕外）
+            // 如何剔除三角形
+            cull_mode: Some(wgpu::Face::Back), // Back指定朝后（面向屏幕内）的三角形会被剔除（不被渲染）
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // 启用深度测试
+        depth_stencil: Some(wgpu::DepthStencilState{
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            // 使用 LESS 意味着像素将被从后往前绘制，大于当前位置的深度值的像素将被丢弃；
+            // reverse-Z 时近处的深度更大，比较方向相反
+            depth_compare: Texture::depth_compare(reverse_z),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count, // 多采样
+            mask: !0, 
+            alpha_to_coverage_enabled: false, // 抗锯齿
+        },
+        multiview: None
+    })
+}
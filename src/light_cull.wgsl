@@ -0,0 +1,104 @@
+// 按簇剔除光源（Clustered Forward）
+// 每个线程处理一个簇：构造图块在观察空间中的子视锥体，再用簇的深度切片截断，
+// 用点光源和聚光灯的包围球（position + range）与之求交。
+// 平行光排在光源缓冲区的前面，影响所有像素，不参与剔除
+
+#include "include/lights.wgsl"
+
+@group(0) @binding(0)
+var<storage, read> lights: array<Light>;
+@group(0) @binding(1)
+var<uniform> config: LightConfig;
+@group(0) @binding(2)
+var<storage, read_write> clusters: array<ClusterLights>;
+
+// 屏幕像素坐标 + NDC 深度 -> 观察空间坐标
+fn unproject(pixel: vec2f, depth: f32) -> vec3f {
+    let ndc = vec2f(
+        pixel.x / f32(config.screen_size.x) * 2.0 - 1.0,
+        1.0 - pixel.y / f32(config.screen_size.y) * 2.0
+    );
+    let view = config.inv_proj * vec4f(ndc, depth, 1.0);
+    return view.xyz / view.w;
+}
+
+struct Plane {
+    normal: vec3f,
+    distance: f32,
+}
+
+// 通过图块的一条边构造侧面，法线朝向图块中心。
+// 使用两个不同深度上的点，透视和正交投影都适用
+fn edge_plane(a: vec2f, b: vec2f, center: vec3f) -> Plane {
+    let near_a = unproject(a, 0.25);
+    let far_a = unproject(a, 0.75);
+    let near_b = unproject(b, 0.25);
+    var normal = normalize(cross(far_a - near_a, near_b - near_a));
+    if dot(normal, center - near_a) < 0.0 {
+        normal = -normal;
+    }
+    return Plane(normal, dot(normal, near_a));
+}
+
+// 第 k 个深度切片的起始深度（观察空间中到摄像机的距离）
+fn slice_depth(k: u32) -> f32 {
+    let t = f32(k) / f32(CLUSTER_SLICES);
+    if config.logarithmic != 0u {
+        return config.depth_near * pow(config.depth_far / config.depth_near, t);
+    }
+    return mix(config.depth_near, config.depth_far, t);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    let cluster_index = id.x;
+    let tile_total = config.tile_count.x * config.tile_count.y;
+    if cluster_index >= tile_total * CLUSTER_SLICES {
+        return;
+    }
+
+    let tile_index = cluster_index % tile_total;
+    let slice = cluster_index / tile_total;
+    let tile = vec2u(tile_index % config.tile_count.x, tile_index / config.tile_count.x);
+    let min_px = vec2f(tile * TILE_SIZE);
+    let max_px = min(vec2f((tile + 1u) * TILE_SIZE), vec2f(config.screen_size));
+    let center = unproject((min_px + max_px) * 0.5, 0.5);
+
+    var planes: array<Plane, 4>;
+    planes[0] = edge_plane(min_px, vec2f(max_px.x, min_px.y), center);
+    planes[1] = edge_plane(vec2f(max_px.x, min_px.y), max_px, center);
+    planes[2] = edge_plane(max_px, vec2f(min_px.x, max_px.y), center);
+    planes[3] = edge_plane(vec2f(min_px.x, max_px.y), min_px, center);
+
+    // 超出深度范围的像素归入第一个或最后一个切片，所以这两个切片向外不设边界
+    var near = slice_depth(slice);
+    var far = slice_depth(slice + 1u);
+    if slice == 0u {
+        near = -3.4e38;
+    }
+    if slice == CLUSTER_SLICES - 1u {
+        far = 3.4e38;
+    }
+
+    var count = 0u;
+    for (var i = config.directional_count; i < config.light_count; i++) {
+        let light = lights[i];
+        // 摄像机朝向 -Z，观察空间深度是 -z
+        let position = (config.view * vec4f(light.position, 1.0)).xyz;
+        let depth = -position.z;
+        var visible = depth + light.range >= near && depth - light.range <= far;
+        for (var p = 0u; p < 4u; p++) {
+            if dot(planes[p].normal, position) - planes[p].distance < -light.range {
+                visible = false;
+            }
+        }
+
+        if visible {
+            if count < MAX_LIGHTS_PER_CLUSTER {
+                clusters[cluster_index].indices[count] = i;
+            }
+            count++;
+        }
+    }
+    clusters[cluster_index].count = count;
+}
@@ -0,0 +1,469 @@
+use crate::{
+    camera::{Camera, Projection},
+    hot_reload::catch_validation,
+};
+
+// 屏幕被划分为 TILE_SIZE x TILE_SIZE 像素的图块，每个图块在深度方向再分成 CLUSTER_SLICES 个切片（簇），
+// 每个簇记录影响它的点光源和聚光灯。必须与 include/lights.wgsl 中的常量保持一致
+pub const TILE_SIZE: u32 = 32;
+pub const CLUSTER_SLICES: u32 = 16;
+// 每个簇最多记录的光源数量，限制了每个像素的光照计算量。超出的光源被丢弃，可以用 read_overflowed_clusters 检查
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 63;
+
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+// 平行光（太阳光），没有位置和衰减
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+}
+
+// 点光源，强度随距离平方衰减，超过 range 后为 0
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+// 聚光灯，在点光源的基础上按照内外锥角做边缘衰减（角度为弧度）
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    pub position: glam::Vec3,
+    pub direction: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+impl Light {
+    pub fn to_raw(&self) -> LightRaw {
+        match *self {
+            Light::Directional(l) => LightRaw {
+                kind: LIGHT_DIRECTIONAL,
+                direction: l.direction.normalize().into(),
+                color: l.color.into(),
+                intensity: l.intensity,
+                ..Default::default()
+            },
+            Light::Point(l) => LightRaw {
+                position: l.position.into(),
+                kind: LIGHT_POINT,
+                range: l.range,
+                color: l.color.into(),
+                intensity: l.intensity,
+                ..Default::default()
+            },
+            Light::Spot(l) => LightRaw {
+                position: l.position.into(),
+                kind: LIGHT_SPOT,
+                direction: l.direction.normalize().into(),
+                range: l.range,
+                color: l.color.into(),
+                intensity: l.intensity,
+                inner_cos: l.inner_angle.cos(),
+                outer_cos: l.outer_angle.cos(),
+                _padding: [0.0; 2],
+            },
+        }
+    }
+}
+
+// 存储缓冲区中的光源，三种光源共用同一个结构体，通过 kind 区分
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+    _padding: [f32; 2],
+}
+
+// 光源数量以及光源剔除需要的摄像机和屏幕信息。
+// 光源缓冲区中平行光排在前面，它们影响所有像素，不参与剔除
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightConfigUniform {
+    view: [[f32; 4]; 4],
+    inv_proj: [[f32; 4]; 4],
+    screen_size: [u32; 2],
+    tile_count: [u32; 2],
+    light_count: u32,
+    directional_count: u32,
+    // 深度切片覆盖的观察空间深度范围，logarithmic 为 1 时按对数划分
+    depth_near: f32,
+    depth_far: f32,
+    logarithmic: u32,
+    _padding: [u32; 3],
+}
+
+// 管理场景中的所有光源，并在每帧用计算着色器按簇剔除光源（Clustered Forward）
+pub struct LightManager {
+    lights: Vec<Light>,
+    light_buffer: wgpu::Buffer,
+    light_capacity: usize,
+    config_buffer: wgpu::Buffer,
+    tile_buffer: wgpu::Buffer,
+    tile_count: [u32; 2],
+    screen_size: [u32; 2],
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    cull_bind_group_layout: wgpu::BindGroupLayout,
+    cull_bind_group: wgpu::BindGroup,
+    cull_pipeline: wgpu::ComputePipeline,
+}
+
+impl LightManager {
+    pub const ENTRY_POINTS: &'static [&'static str] = &["cs_main"];
+
+    pub fn new(device: &wgpu::Device, cull_shader: &wgpu::ShaderModule, width: u32, height: u32) -> Self {
+        let light_capacity = 16;
+        let light_buffer = Self::create_light_buffer(device, light_capacity);
+        let config_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_config_buffer"),
+            size: std::mem::size_of::<LightConfigUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let tile_count = Self::tile_count(width, height);
+        let tile_buffer = Self::create_tile_buffer(device, tile_count);
+
+        // 渲染时只读取剔除结果
+        let bind_group_layout = Self::create_bind_group_layout(
+            device,
+            "light_bind_group_layout",
+            wgpu::ShaderStages::FRAGMENT,
+            true,
+        );
+        // 计算着色器写入每个图块的光源列表
+        let cull_bind_group_layout = Self::create_bind_group_layout(
+            device,
+            "light_cull_bind_group_layout",
+            wgpu::ShaderStages::COMPUTE,
+            false,
+        );
+
+        let cull_pipeline = Self::create_cull_pipeline(device, &cull_bind_group_layout, cull_shader);
+
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &light_buffer, &config_buffer, &tile_buffer);
+        let cull_bind_group = Self::create_bind_group(device, &cull_bind_group_layout, &light_buffer, &config_buffer, &tile_buffer);
+
+        Self {
+            lights: Vec::new(),
+            light_buffer,
+            light_capacity,
+            config_buffer,
+            tile_buffer,
+            tile_count,
+            screen_size: [width, height],
+            bind_group_layout,
+            bind_group,
+            cull_bind_group_layout,
+            cull_bind_group,
+            cull_pipeline,
+        }
+    }
+
+    // 热重载时替换剔除着色器，出错时保留原来的管线
+    pub fn set_cull_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> anyhow::Result<()> {
+        self.cull_pipeline = catch_validation(device, || Self::create_cull_pipeline(device, &self.cull_bind_group_layout, shader))?;
+        Ok(())
+    }
+
+    fn create_cull_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, shader: &wgpu::ShaderModule) -> wgpu::ComputePipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Cull Pipeline Layout"),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Light Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: shader,
+            entry_point: "cs_main",
+            compilation_options: Default::default(),
+        })
+    }
+
+    pub fn add(&mut self, light: impl Into<Light>) -> usize {
+        self.lights.push(light.into());
+        self.lights.len() - 1
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        &mut self.lights
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    // 投射阴影的光源：第一个平行光。
+    // 平行光在光源缓冲区中排在最前面，所以它在缓冲区中的下标总是 0
+    pub fn shadow_light(&self) -> Option<(usize, DirectionalLight)> {
+        self.lights.iter().find_map(|light| match light {
+            Light::Directional(l) => Some((0, *l)),
+            _ => None,
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    // 屏幕尺寸变化时图块数量也会变化
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.screen_size = [width, height];
+        let tile_count = Self::tile_count(width, height);
+        if tile_count != self.tile_count {
+            self.tile_count = tile_count;
+            self.tile_buffer = Self::create_tile_buffer(device, tile_count);
+            self.recreate_bind_groups(device);
+        }
+    }
+
+    // 上传光源数据和摄像机信息，光源数量超过容量时扩容
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera) {
+        if self.lights.len() > self.light_capacity {
+            self.light_capacity = self.lights.len().next_power_of_two();
+            self.light_buffer = Self::create_light_buffer(device, self.light_capacity);
+            self.recreate_bind_groups(device);
+        }
+
+        // 平行光排在前面，其余光源保持原来的顺序
+        let is_directional = |light: &&Light| matches!(light, Light::Directional(_));
+        let (directional, local): (Vec<&Light>, Vec<&Light>) = self.lights.iter().partition(is_directional);
+        let raw = directional.iter().chain(&local).map(|light| light.to_raw()).collect::<Vec<_>>();
+        if !raw.is_empty() {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+
+        let view = camera.build_view_matrix();
+        let (depth_near, depth_far, logarithmic) = self.depth_range(camera, &view);
+        let config = LightConfigUniform {
+            view: view.to_cols_array_2d(),
+            inv_proj: camera.build_projection_matrix().inverse().to_cols_array_2d(),
+            screen_size: self.screen_size,
+            tile_count: self.tile_count,
+            light_count: self.lights.len() as u32,
+            directional_count: directional.len() as u32,
+            depth_near,
+            depth_far,
+            logarithmic: logarithmic as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.config_buffer, 0, bytemuck::cast_slice(&[config]));
+    }
+
+    // 深度切片的范围：透视投影按对数划分，近处的簇更薄；正交投影均匀划分。
+    // 无限远投影没有远平面，切片只需要覆盖到最远的光源能照到的深度
+    fn depth_range(&self, camera: &Camera, view: &glam::Mat4) -> (f32, f32, bool) {
+        match camera.projection {
+            Projection::Perspective { znear, zfar, .. } => (znear, zfar, true),
+            Projection::Orthographic { znear, zfar, .. } => (znear, zfar, false),
+            Projection::InfinitePerspectiveReverseZ { znear, .. } => {
+                let far = self
+                    .lights
+                    .iter()
+                    .filter_map(|light| match light {
+                        Light::Directional(_) => None,
+                        Light::Point(l) => Some((l.position, l.range)),
+                        Light::Spot(l) => Some((l.position, l.range)),
+                    })
+                    .map(|(position, range)| -view.transform_point3(position).z + range)
+                    .fold(znear * 2.0, f32::max);
+                (znear, far, true)
+            }
+        }
+    }
+
+    // 每个簇一个线程，测试所有点光源和聚光灯的包围球是否与簇相交
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.set_bind_group(0, &self.cull_bind_group, &[]);
+        compute_pass.dispatch_workgroups(Self::cluster_count(self.tile_count).div_ceil(64), 1, 1);
+    }
+
+    /// 回读上一次剔除后光源数量超过 MAX_LIGHTS_PER_CLUSTER 的簇的数量，这些簇丢弃了部分光源。
+    /// 会阻塞等待 GPU，只用于调试和测试
+    pub fn read_overflowed_clusters(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<u32> {
+        let size = self.tile_buffer.size();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_tile_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Light Tile Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.tile_buffer, 0, &readback, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+        let overflowed = {
+            let data = slice.get_mapped_range();
+            let words: &[u32] = bytemuck::cast_slice(&data);
+            // 每个簇的第一个 u32 是没有截断的光源数量
+            words
+                .chunks_exact(MAX_LIGHTS_PER_CLUSTER as usize + 1)
+                .filter(|cluster| cluster[0] > MAX_LIGHTS_PER_CLUSTER)
+                .count() as u32
+        };
+        readback.unmap();
+        Ok(overflowed)
+    }
+
+    fn tile_count(width: u32, height: u32) -> [u32; 2] {
+        [width.div_ceil(TILE_SIZE).max(1), height.div_ceil(TILE_SIZE).max(1)]
+    }
+
+    fn cluster_count(tile_count: [u32; 2]) -> u32 {
+        tile_count[0] * tile_count[1] * CLUSTER_SLICES
+    }
+
+    fn create_light_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_buffer"),
+            size: (capacity * std::mem::size_of::<LightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_tile_buffer(device: &wgpu::Device, tile_count: [u32; 2]) -> wgpu::Buffer {
+        // 每个簇: 光源数量 + MAX_LIGHTS_PER_CLUSTER 个光源索引
+        let cluster_size = (MAX_LIGHTS_PER_CLUSTER + 1) as usize * std::mem::size_of::<u32>();
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_tile_buffer"),
+            size: Self::cluster_count(tile_count) as wgpu::BufferAddress * cluster_size as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group_layout(
+        device: &wgpu::Device,
+        label: &str,
+        visibility: wgpu::ShaderStages,
+        tiles_read_only: bool,
+    ) -> wgpu::BindGroupLayout {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[
+                // 光源列表
+                storage(0, true),
+                // 光源数量和剔除参数
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // 每个簇的光源索引
+                storage(2, tiles_read_only),
+            ],
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        light_buffer: &wgpu::Buffer,
+        config_buffer: &wgpu::Buffer,
+        tile_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: config_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: tile_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn recreate_bind_groups(&mut self, device: &wgpu::Device) {
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.light_buffer, &self.config_buffer, &self.tile_buffer);
+        self.cull_bind_group = Self::create_bind_group(device, &self.cull_bind_group_layout, &self.light_buffer, &self.config_buffer, &self.tile_buffer);
+    }
+}
@@ -1,5 +1,177 @@
-use wgpu_test::run;
+use std::path::PathBuf;
 
-fn main() {
-    pollster::block_on(run());
+use clap::{Parser, ValueEnum};
+use wgpu_test::{run, ViewerOptions};
+
+/// wgpu 模型查看器
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// 场景文件（.ron 或 .json）
+    #[arg(long, conflicts_with = "model")]
+    scene: Option<PathBuf>,
+    /// res 目录下的模型文件（.obj、.gltf 或 .glb），替换默认场景中的模型
+    #[arg(long)]
+    model: Option<String>,
+    /// 窗口宽度（像素）
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    /// 窗口高度（像素）
+    #[arg(long, default_value_t = 600)]
+    height: u32,
+    #[arg(long, value_enum, default_value_t = PresentMode::Fifo)]
+    present_mode: PresentMode,
+    #[arg(long, value_enum, default_value_t = Backend::All)]
+    backend: Backend,
+    /// MSAA 采样数
+    #[arg(long, default_value_t = 1, value_parser = parse_sample_count)]
+    msaa: u32,
+    #[arg(long, value_enum, default_value_t = PowerPreference::None)]
+    power_preference: PowerPreference,
+    /// 强制使用 fallback 适配器（通常是软渲染）
+    #[arg(long)]
+    force_fallback_adapter: bool,
+    /// 渲染指定帧数后退出
+    #[arg(long)]
+    frames: Option<u32>,
+    /// 退出前把最后一帧保存为 PNG，没有指定 --frames 时只渲染一帧
+    #[arg(long)]
+    screenshot: Option<PathBuf>,
+    /// 开发模式：从目录读取全部着色器，修改后自动重新加载。不指定目录时使用源码中的 src
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = concat!(env!("CARGO_MANIFEST_DIR"), "/src"))]
+    shader_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PresentMode {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    All,
+    Primary,
+    Vulkan,
+    Gl,
+    Metal,
+    Dx12,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PowerPreference {
+    None,
+    LowPower,
+    HighPerformance,
+}
+
+impl From<Cli> for ViewerOptions {
+    fn from(cli: Cli) -> Self {
+        Self {
+            scene: cli.scene,
+            model: cli.model,
+            width: cli.width,
+            height: cli.height,
+            present_mode: match cli.present_mode {
+                PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+                PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+                PresentMode::Fifo => wgpu::PresentMode::Fifo,
+                PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+                PresentMode::Immediate => wgpu::PresentMode::Immediate,
+                PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            },
+            backends: match cli.backend {
+                Backend::All => wgpu::Backends::all(),
+                Backend::Primary => wgpu::Backends::PRIMARY,
+                Backend::Vulkan => wgpu::Backends::VULKAN,
+                Backend::Gl => wgpu::Backends::GL,
+                Backend::Metal => wgpu::Backends::METAL,
+                Backend::Dx12 => wgpu::Backends::DX12,
+            },
+            sample_count: cli.msaa,
+            power_preference: match cli.power_preference {
+                PowerPreference::None => wgpu::PowerPreference::None,
+                PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+                PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+            },
+            force_fallback_adapter: cli.force_fallback_adapter,
+            frames: cli.frames,
+            screenshot: cli.screenshot,
+            shader_dir: cli.shader_dir,
+        }
+    }
+}
+
+fn parse_sample_count(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err(format!("expected 1, 2, 4 or 8, got {}", value)),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    pollster::block_on(run(cli.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ViewerOptions, clap::Error> {
+        Cli::try_parse_from(std::iter::once("wgpu-test").chain(args.iter().copied())).map(ViewerOptions::from)
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse(&[]).unwrap();
+        assert_eq!((options.width, options.height), (800, 600));
+        assert_eq!(options.present_mode, wgpu::PresentMode::Fifo);
+        assert_eq!(options.backends, wgpu::Backends::all());
+        assert_eq!(options.sample_count, 1);
+        assert_eq!(options.shader_dir, None);
+    }
+
+    #[test]
+    fn sample_count_must_be_a_power_of_two() {
+        for count in [1, 2, 4, 8] {
+            assert_eq!(parse(&["--msaa", &count.to_string()]).unwrap().sample_count, count);
+        }
+        for value in ["0", "3", "16", "four"] {
+            let error = parse(&["--msaa", value]).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation, "{}", value);
+        }
+    }
+
+    #[test]
+    fn backend_and_present_mode() {
+        let options = parse(&["--backend", "gl", "--present-mode", "auto-no-vsync"]).unwrap();
+        assert_eq!(options.backends, wgpu::Backends::GL);
+        assert_eq!(options.present_mode, wgpu::PresentMode::AutoNoVsync);
+        assert_eq!(parse(&["--backend", "primary"]).unwrap().backends, wgpu::Backends::PRIMARY);
+        assert_eq!(parse(&["--present-mode", "mailbox"]).unwrap().present_mode, wgpu::PresentMode::Mailbox);
+
+        assert_eq!(parse(&["--backend", "webgpu"]).unwrap_err().kind(), clap::error::ErrorKind::InvalidValue);
+        assert_eq!(parse(&["--present-mode", "vsync"]).unwrap_err().kind(), clap::error::ErrorKind::InvalidValue);
+    }
+
+    // 只写 --shader-dir 时使用源码中的 src
+    #[test]
+    fn shader_dir_defaults_to_src() {
+        let options = parse(&["--shader-dir"]).unwrap();
+        assert_eq!(options.shader_dir, Some(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"))));
+        let options = parse(&["--shader-dir", "shaders"]).unwrap();
+        assert_eq!(options.shader_dir, Some(PathBuf::from("shaders")));
+    }
+
+    #[test]
+    fn scene_conflicts_with_model() {
+        let error = parse(&["--scene", "scene.ron", "--model", "cube.obj"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert_eq!(parse(&["--model", "cube.obj"]).unwrap().model.as_deref(), Some("cube.obj"));
+    }
 }
@@ -0,0 +1,27 @@
+// 生成 mipmap：用线性过滤对上一级采样，渲染到下一级。
+// 纹理视图是 sRGB 格式时，采样得到的是线性值，写入时再编码回 sRGB，因此平均是在线性空间进行的
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+// 不需要顶点缓冲，三个顶点组成一个覆盖整个屏幕的三角形
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(t_source, s_source, in.tex_coords);
+}
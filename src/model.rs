@@ -1,6 +1,8 @@
 use std::ops::Range;
 
-use crate::texture;
+use wgpu::util::DeviceExt;
+
+use crate::{bounds::Aabb, culling::{DrawBatch, GpuInstanceCuller}, texture};
 
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -12,6 +14,9 @@ pub struct ModelVertex{
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    // 切线和副切线，与法线一起构成切线空间，用于法线贴图
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
 }
 
 impl Vertex for ModelVertex {
@@ -38,6 +43,16 @@ impl Vertex for ModelVertex {
                     offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
                 }
             ]
         }
@@ -49,13 +64,125 @@ pub struct Model {
     pub materials: Vec<Material>,
 }
 
+impl Model {
+    // 所有网格的包围盒（模型空间）
+    pub fn bounds(&self) -> Aabb {
+        self.meshes.iter().fold(Aabb::EMPTY, |aabb, mesh| aabb.union(&mesh.bounds))
+    }
+}
+
+// 材质的光照参数，对应 MTL 中的 Ka/Kd/Ks/Ns
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub ambient: [f32; 3],
+    // uniform 中的 vec3 需要按 16 字节对齐
+    pub _padding0: u32,
+    pub diffuse: [f32; 3],
+    pub _padding1: u32,
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    // 纹理坐标变换 uv * uv_scale + uv_offset，对应 MTL 贴图的 -s/-o 选项
+    pub uv_scale: [f32; 2],
+    pub uv_offset: [f32; 2],
+}
+
+impl MaterialUniform {
+    pub fn new(ambient: [f32; 3], diffuse: [f32; 3], specular: [f32; 3], shininess: f32) -> Self {
+        Self {
+            ambient,
+            _padding0: 0,
+            diffuse,
+            _padding1: 0,
+            specular,
+            shininess,
+            uv_scale: [1.0; 2],
+            uv_offset: [0.0; 2],
+        }
+    }
+
+    pub fn with_uv_transform(mut self, scale: [f32; 2], offset: [f32; 2]) -> Self {
+        self.uv_scale = scale;
+        self.uv_offset = offset;
+        self
+    }
+
+    // 把 glTF 的金属度/粗糙度参数近似换算成 Blinn-Phong 参数：
+    // 金属没有漫反射，镜面颜色在 0.04 和基础色之间插值，粗糙度映射为高光指数。
+    // 只使用基础色的 RGB：渲染器不支持半透明，alpha 由调用者丢弃
+    pub fn from_pbr(base_color: [f32; 3], metallic: f32, roughness: f32) -> Self {
+        let base_color = glam::Vec3::from(base_color);
+        let metallic = metallic.clamp(0.0, 1.0);
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let diffuse = base_color * (1.0 - metallic);
+        let specular = glam::Vec3::splat(0.04).lerp(base_color, metallic);
+        let shininess = (2.0 / (alpha * alpha).max(1e-4) - 2.0).clamp(1.0, 256.0);
+        Self::new(base_color.into(), diffuse.into(), specular.into(), shininess)
+    }
+}
+
 // 材质
 pub struct Material {
     pub name: String,
     pub diffuse_texture: texture::Texture,
+    pub normal_texture: texture::Texture,
+    pub uniform: MaterialUniform,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        diffuse_texture: texture::Texture,
+        normal_texture: texture::Texture,
+        uniform: MaterialUniform,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+            label: Some(&format!("{:?} Material Buffer",name)),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor{
+            layout,
+            label: Some(name),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            normal_texture,
+            uniform,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+}
+
 // 网格
 pub struct Mesh {
     pub name: String,
@@ -63,35 +190,65 @@ pub struct Mesh {
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    // 加载时根据顶点位置计算的包围盒（模型空间）
+    pub bounds: Aabb,
 }
 
 pub trait DrawModel<'a> {
-    fn draw_mesh(&mut self,mesh:&'a Mesh,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup);
-    fn draw_mesh_instanced(&mut self,mesh:&'a Mesh,instances: Range<u32>,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup);
-    fn draw_model(&mut self, model: &'a Model, camera_bind_group: &'a wgpu::BindGroup);
+    fn draw_mesh(&mut self,mesh:&'a Mesh,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup,light_bind_group:&'a wgpu::BindGroup);
+    fn draw_mesh_instanced(&mut self,mesh:&'a Mesh,instances: Range<u32>,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup,light_bind_group:&'a wgpu::BindGroup);
+    fn draw_model(&mut self, model: &'a Model, camera_bind_group: &'a wgpu::BindGroup, light_bind_group: &'a wgpu::BindGroup);
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    // 按批次绘制，每个批次指定网格、材质和实例范围（剔除和材质覆盖之后的结果）
+    fn draw_model_batches(
+        &mut self,
+        model: &'a Model,
+        batches: &[DrawBatch],
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    // 实例数量由 GPU 写入间接绘制参数，实例数据需要事先绑定到插槽 1
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        indirect_buffer: &'a wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    // 使用 GPU 剔除的结果绘制模型，每个批次绑定自己的可见实例分段
+    fn draw_model_indirect(
+        &mut self,
+        model: &'a Model,
+        culler: &'a GpuInstanceCuller,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
     );
 }
 
 impl<'a,'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b:'a {
-    fn draw_mesh(&mut self,mesh:&'b Mesh,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup) {
-        self.draw_mesh_instanced(mesh,0..1,materal,camera_bind_group);
+    fn draw_mesh(&mut self,mesh:&'b Mesh,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup,light_bind_group:&'a wgpu::BindGroup) {
+        self.draw_mesh_instanced(mesh,0..1,materal,camera_bind_group,light_bind_group);
     }
 
-    fn draw_mesh_instanced(&mut self,mesh:&'b Mesh,instances: Range<u32>,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup) {
+    fn draw_mesh_instanced(&mut self,mesh:&'b Mesh,instances: Range<u32>,materal:&'a Material,camera_bind_group:&'a wgpu::BindGroup,light_bind_group:&'a wgpu::BindGroup) {
         self.set_vertex_buffer(0,mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, &materal.bind_group, &[]);
         self.set_bind_group(1, &camera_bind_group, &[]);
+        self.set_bind_group(2, light_bind_group, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
-    fn draw_model(&mut self, model: &'b Model, camera_bind_group: &'b wgpu::BindGroup) {
-        self.draw_model_instanced(model, 0..1, camera_bind_group);
+    fn draw_model(&mut self, model: &'b Model, camera_bind_group: &'b wgpu::BindGroup, light_bind_group: &'b wgpu::BindGroup) {
+        self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
     }
 
     fn draw_model_instanced(
@@ -99,10 +256,75 @@ impl<'a,'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b:'a {
         model: &'b Model,
         instances: Range<u32>,
         camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
     ) {
         for mesh in &model.meshes {
             let material = &model.materials[mesh.material];
-            self.draw_mesh_instanced(mesh,  instances.clone(), material,camera_bind_group);
+            self.draw_mesh_instanced(mesh,  instances.clone(), material,camera_bind_group,light_bind_group);
+        }
+    }
+
+    fn draw_model_batches(
+        &mut self,
+        model: &'b Model,
+        batches: &[DrawBatch],
+        camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for batch in batches {
+            if batch.instances.is_empty() {
+                continue;
+            }
+            let mesh = &model.meshes[batch.mesh];
+            let material = &model.materials[batch.material];
+            self.draw_mesh_instanced(mesh, batch.instances.clone(), material, camera_bind_group, light_bind_group);
+        }
+    }
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        indirect_buffer: &'b wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.draw_indexed_indirect(indirect_buffer, indirect_offset);
+    }
+
+    fn draw_model_indirect(
+        &mut self,
+        model: &'b Model,
+        culler: &'b GpuInstanceCuller,
+        camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for (i, batch) in culler.batches().iter().enumerate() {
+            let mesh = &model.meshes[batch.mesh];
+            let material = &model.materials[batch.material];
+            self.set_vertex_buffer(1, culler.visible_slice(i));
+            self.draw_mesh_indirect(mesh, material, culler.indirect_buffer(), culler.indirect_offset(i), camera_bind_group, light_bind_group);
+        }
+    }
+}
+
+// 只绘制几何体而不绑定材质，用于阴影贴图等只需要深度的通道
+pub trait DrawGeometry<'a> {
+    fn draw_model_geometry_instanced(&mut self, model: &'a Model, instances: Range<u32>);
+}
+
+impl<'a,'b> DrawGeometry<'b> for wgpu::RenderPass<'a> where 'b:'a {
+    fn draw_model_geometry_instanced(&mut self, model: &'b Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            self.set_vertex_buffer(0,mesh.vertex_buffer.slice(..));
+            self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            self.draw_indexed(0..mesh.num_elements, 0, instances.clone());
         }
     }
 }
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+// 启动窗口和选择适配器时使用的选项，对应命令行参数
+#[derive(Debug, Clone)]
+pub struct ViewerOptions {
+    // 场景文件（.ron 或 .json），None 时使用 res/scenes/default.ron
+    pub scene: Option<PathBuf>,
+    // res 目录下的模型文件，替换场景中的第一个模型
+    pub model: Option<String>,
+    // 窗口内部尺寸（物理像素）
+    pub width: u32,
+    pub height: u32,
+    // surface 不支持时退回到 Fifo
+    pub present_mode: wgpu::PresentMode,
+    pub backends: wgpu::Backends,
+    // MSAA 的采样数：1、2、4 或 8
+    pub sample_count: u32,
+    pub power_preference: wgpu::PowerPreference,
+    pub force_fallback_adapter: bool,
+    // 渲染指定帧数后退出
+    pub frames: Option<u32>,
+    // 退出前把最后一帧保存为 PNG
+    pub screenshot: Option<PathBuf>,
+    // 开发模式：从这个目录读取着色器，修改后自动重新加载
+    pub shader_dir: Option<PathBuf>,
+}
+
+impl Default for ViewerOptions {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            model: None,
+            width: 800,
+            height: 600,
+            present_mode: wgpu::PresentMode::Fifo,
+            backends: wgpu::Backends::all(),
+            sample_count: 1,
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            frames: None,
+            screenshot: None,
+            shader_dir: None,
+        }
+    }
+}
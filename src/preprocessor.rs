@@ -0,0 +1,328 @@
+// WGSL 预处理器：支持 #include 共享的模块，以及 #ifdef/#ifndef/#else/#endif/#define/#undef 生成不同的着色器变体。
+// 每种宏组合（变体）只编译一次，编译结果缓存在 ShaderCache 中
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use wgpu::naga;
+
+use crate::hot_reload::{catch_validation, validate_source};
+
+// 编译进程序的着色器，路径相对于 src 目录
+const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+    ("shader.wgsl", include_str!("shader.wgsl")),
+    ("debug.wgsl", include_str!("debug.wgsl")),
+    ("shadow.wgsl", include_str!("shadow.wgsl")),
+    ("light_cull.wgsl", include_str!("light_cull.wgsl")),
+    ("instance_cull.wgsl", include_str!("instance_cull.wgsl")),
+    ("mipmap.wgsl", include_str!("mipmap.wgsl")),
+    ("include/camera.wgsl", include_str!("include/camera.wgsl")),
+    ("include/instance.wgsl", include_str!("include/instance.wgsl")),
+    ("include/lights.wgsl", include_str!("include/lights.wgsl")),
+    ("include/vertex.wgsl", include_str!("include/vertex.wgsl")),
+];
+
+/// 预处理时定义的宏。有序集合，相同的宏组合总是得到相同的键
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeSet<String>);
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str) -> Self {
+        self.insert(name);
+        self
+    }
+
+    pub fn insert(&mut self, name: &str) {
+        self.0.insert(name.to_string());
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    // 设置宏是否定义
+    pub fn set(&mut self, name: &str, defined: bool) {
+        if defined {
+            self.insert(name);
+        } else {
+            self.remove(name);
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+impl<'a> FromIterator<&'a str> for ShaderDefines {
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        Self(iter.into_iter().map(str::to_string).collect())
+    }
+}
+
+/// 着色器源码的来源：编译进程序的版本，或者磁盘上的目录（热重载）。
+/// 文件名使用 `/` 分隔的相对路径，例如 `include/camera.wgsl`
+#[derive(Debug, Clone, Default)]
+pub struct ShaderLibrary {
+    dir: Option<PathBuf>,
+}
+
+impl ShaderLibrary {
+    pub fn embedded() -> Self {
+        Self { dir: None }
+    }
+
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: Some(dir.into()) }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    // 错误信息中显示的路径
+    pub fn path(&self, name: &str) -> PathBuf {
+        match &self.dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        }
+    }
+
+    fn read(&self, name: &str) -> anyhow::Result<String> {
+        match &self.dir {
+            Some(dir) => {
+                let path = dir.join(name);
+                std::fs::read_to_string(&path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))
+            }
+            None => EMBEDDED_SHADERS
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| anyhow!("unknown shader {}", name)),
+        }
+    }
+
+    /// 展开 name 中的 #include 和条件编译指令。每个文件只包含一次，
+    /// 文件中的 #define 对之后的内容（包括其他文件）都有效
+    pub fn preprocess(&self, name: &str, defines: &ShaderDefines) -> anyhow::Result<PreprocessedShader> {
+        let mut shader = PreprocessedShader { source: String::new(), lines: Vec::new(), files: Vec::new() };
+        let mut defines = defines.0.clone();
+        self.process_file(name, &mut defines, &mut shader, &mut HashSet::new())?;
+        Ok(shader)
+    }
+
+    fn process_file(&self, name: &str, defines: &mut BTreeSet<String>, out: &mut PreprocessedShader, included: &mut HashSet<String>) -> anyhow::Result<()> {
+        if !included.insert(name.to_string()) {
+            return Ok(());
+        }
+        let source = self.read(name)?;
+        let path = self.path(name);
+        let file_index = out.files.len();
+        out.files.push(name.to_string());
+
+        // 嵌套的条件块
+        struct Condition {
+            line: usize,
+            // 所在的块是否输出
+            parent_active: bool,
+            // 条件本身是否成立
+            matched: bool,
+            in_else: bool,
+        }
+        impl Condition {
+            fn active(&self) -> bool {
+                self.parent_active && (self.matched != self.in_else)
+            }
+        }
+        let mut conditions: Vec<Condition> = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let active = conditions.last().is_none_or(Condition::active);
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    out.source.push_str(line);
+                    out.source.push('\n');
+                    out.lines.push((file_index, line_number as u32));
+                }
+                continue;
+            };
+            let error = |message: String| anyhow!("{}:{}: {}", path.display(), line_number, message);
+            let mut words = directive.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let argument = words.next();
+            if words.next().is_some() {
+                return Err(error(format!("unexpected tokens after #{}", keyword)));
+            }
+            // 宏名只能是标识符
+            let name_argument = || match argument {
+                Some(name) if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => Ok(name),
+                Some(name) => Err(error(format!("invalid name `{}` in #{}", name, keyword))),
+                None => Err(error(format!("#{} needs a name", keyword))),
+            };
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = defines.contains(name_argument()?);
+                    conditions.push(Condition { line: line_number, parent_active: active, matched: defined == (keyword == "ifdef"), in_else: false });
+                }
+                "else" | "endif" if argument.is_some() => return Err(error(format!("unexpected tokens after #{}", keyword))),
+                "else" => match conditions.last_mut() {
+                    Some(condition) if !condition.in_else => condition.in_else = true,
+                    Some(_) => return Err(error("duplicate #else".to_string())),
+                    None => return Err(error("#else without #ifdef".to_string())),
+                },
+                "endif" => {
+                    conditions.pop().ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                }
+                "define" => {
+                    let name = name_argument()?;
+                    if active {
+                        defines.insert(name.to_string());
+                    }
+                }
+                "undef" => {
+                    let name = name_argument()?;
+                    if active {
+                        defines.remove(name);
+                    }
+                }
+                "include" => {
+                    let file = argument
+                        .and_then(|file| file.strip_prefix('"')?.strip_suffix('"'))
+                        .ok_or_else(|| error("expected #include \"file\"".to_string()))?;
+                    if active {
+                        let include = resolve_include(name, file);
+                        self.process_file(&include, defines, out, included)
+                            .with_context(|| format!("{}:{}: included from here", path.display(), line_number))?;
+                    }
+                }
+                _ => return Err(error(format!("unknown directive #{}", keyword))),
+            }
+        }
+        if let Some(condition) = conditions.last() {
+            anyhow::bail!("{}:{}: conditional block is missing #endif", path.display(), condition.line);
+        }
+        Ok(())
+    }
+
+    /// 预处理并编译着色器，返回模块和用到的文件。先用 naga 验证，错误信息指向原始文件中的行
+    pub fn compile(&self, device: &wgpu::Device, name: &str, defines: &ShaderDefines, entry_points: &[&str]) -> anyhow::Result<CompiledShader> {
+        let shader = self.preprocess(name, defines)?;
+        shader.validate(self, entry_points)?;
+        let label = format!("{} {:?}", name, defines.iter().collect::<Vec<_>>());
+        let module = catch_validation(device, || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&label),
+                source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
+            })
+        })?;
+        let files = shader.files.iter().map(|file| self.path(file)).collect();
+        Ok(CompiledShader { module, files })
+    }
+}
+
+// include 的路径相对于包含它的文件
+fn resolve_include(from: &str, file: &str) -> String {
+    let mut parts: Vec<&str> = from.split('/').collect();
+    parts.pop();
+    for part in file.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// 展开后的着色器源码
+#[derive(Debug)]
+pub struct PreprocessedShader {
+    pub source: String,
+    // 输出的每一行来自哪个文件（files 的下标）的第几行
+    lines: Vec<(usize, u32)>,
+    // 用到的文件，第一个是主文件
+    files: Vec<String>,
+}
+
+impl PreprocessedShader {
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    // 展开后的行号（从 1 开始）对应的原始文件和行号
+    pub fn source_location(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = *self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], line))
+    }
+
+    /// 用 naga 验证展开后的源码。错误信息先给出原始文件中的位置，再附上展开后的源码片段
+    pub fn validate(&self, library: &ShaderLibrary, entry_points: &[&str]) -> anyhow::Result<naga::Module> {
+        let main = library.path(&self.files[0]).display().to_string();
+        validate_source(&self.source, &format!("{} (preprocessed)", main), entry_points, |line, column| {
+            self.source_location(line).map(|(file, line)| format!("{}:{}:{}", library.path(file).display(), line, column))
+        })
+        .with_context(|| format!("failed to compile {}", main))
+    }
+}
+
+pub struct CompiledShader {
+    pub module: wgpu::ShaderModule,
+    // 着色器用到的全部文件，热重载时监视它们
+    pub files: Vec<PathBuf>,
+}
+
+/// 一个着色器的各个变体，按宏组合缓存，每个变体只编译一次
+pub struct ShaderCache {
+    name: &'static str,
+    entry_points: &'static [&'static str],
+    modules: HashMap<ShaderDefines, wgpu::ShaderModule>,
+    files: BTreeSet<PathBuf>,
+}
+
+impl ShaderCache {
+    pub fn new(name: &'static str, entry_points: &'static [&'static str]) -> Self {
+        Self { name, entry_points, modules: HashMap::new(), files: BTreeSet::new() }
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, library: &ShaderLibrary, defines: &ShaderDefines) -> anyhow::Result<&wgpu::ShaderModule> {
+        if !self.modules.contains_key(defines) {
+            let shader = library.compile(device, self.name, defines, self.entry_points)?;
+            self.files.extend(shader.files);
+            self.modules.insert(defines.clone(), shader.module);
+        }
+        Ok(&self.modules[defines])
+    }
+
+    // 已经编译的变体，没有时返回 None
+    pub fn module(&self, defines: &ShaderDefines) -> Option<&wgpu::ShaderModule> {
+        self.modules.get(defines)
+    }
+
+    // 已经编译的变体数量
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    // 已编译的变体用到的文件
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.iter()
+    }
+}
@@ -0,0 +1,255 @@
+// 着色器反射：用 naga 解析 WGSL，从全局变量的 @group/@binding 得到绑定组布局，
+// 并把 Rust 端手写的顶点缓冲区布局与顶点着色器的 @location 输入对照，避免两边不同步
+
+use std::fmt;
+
+use anyhow::{anyhow, Context};
+use wgpu::naga;
+
+use crate::preprocessor::{ShaderDefines, ShaderLibrary};
+
+pub struct ShaderReflection {
+    module: naga::Module,
+    info: naga::valid::ModuleInfo,
+    // 错误信息中的着色器名称
+    label: String,
+}
+
+/// 顶点着色器的一个 @location 输入
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    // 参数名或结构体成员名
+    pub name: String,
+    pub kind: naga::ScalarKind,
+    // 标量为 1，向量为分量数
+    pub components: u32,
+}
+
+impl fmt::Display for VertexInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scalar = match self.kind {
+            naga::ScalarKind::Float => "f32",
+            naga::ScalarKind::Sint => "i32",
+            naga::ScalarKind::Uint => "u32",
+            _ => "?",
+        };
+        match self.components {
+            1 => write!(f, "`{}`: {}", self.name, scalar),
+            n => write!(f, "`{}`: vec{}<{}>", self.name, n, scalar),
+        }
+    }
+}
+
+impl ShaderReflection {
+    pub fn new(module: naga::Module, label: impl Into<String>) -> anyhow::Result<Self> {
+        let label = label.into();
+        // 验证的结果中记录了每个入口点用到的全局变量
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|e| anyhow!("{}: {}", label, e.into_inner()))?;
+        Ok(Self { module, info, label })
+    }
+
+    /// 预处理并解析着色器库中的着色器
+    pub fn load(library: &ShaderLibrary, name: &str, defines: &ShaderDefines) -> anyhow::Result<Self> {
+        let module = library.preprocess(name, defines)?.validate(library, &[])?;
+        Self::new(module, library.path(name).display().to_string())
+    }
+
+    /// 第 group 组的绑定组布局，按绑定编号排列。可见性是实际用到该变量的入口点的阶段。
+    /// 着色器中看不出纹理是否可过滤，浮点纹理总是按可过滤处理；缓冲区不指定最小绑定大小
+    pub fn bind_group_layout_entries(&self, group: u32) -> anyhow::Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        let mut entries = Vec::new();
+        for (handle, variable) in self.module.global_variables.iter() {
+            let Some(binding) = variable.binding.as_ref().filter(|binding| binding.group == group) else {
+                continue;
+            };
+            let name = variable.name.as_deref().unwrap_or("<unnamed>");
+            let (ty, count) = match self.module.types[variable.ty].inner {
+                naga::TypeInner::BindingArray { base, size } => {
+                    let count = match size {
+                        naga::ArraySize::Constant(count) => Some(count),
+                        naga::ArraySize::Dynamic => anyhow::bail!("{}: `{}` is a binding array without a size", self.label, name),
+                    };
+                    (&self.module.types[base].inner, count)
+                }
+                ref inner => (inner, None),
+            };
+            let ty = self.binding_type(variable.space, ty).with_context(|| format!("{}: unsupported binding `{}`", self.label, name))?;
+
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (index, entry_point) in self.module.entry_points.iter().enumerate() {
+                if !self.info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= match entry_point.stage {
+                        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                    };
+                }
+            }
+            entries.push(wgpu::BindGroupLayoutEntry { binding: binding.binding, visibility, ty, count });
+        }
+        entries.sort_by_key(|entry| entry.binding);
+        Ok(entries)
+    }
+
+    pub fn create_bind_group_layout(&self, device: &wgpu::Device, group: u32, label: &str) -> anyhow::Result<wgpu::BindGroupLayout> {
+        let entries = self.bind_group_layout_entries(group)?;
+        anyhow::ensure!(!entries.is_empty(), "{}: no bindings in group {}", self.label, group);
+        Ok(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some(label), entries: &entries }))
+    }
+
+    fn binding_type(&self, space: naga::AddressSpace, ty: &naga::TypeInner) -> anyhow::Result<wgpu::BindingType> {
+        let buffer = |ty| wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None };
+        Ok(match (space, ty) {
+            (naga::AddressSpace::Uniform, _) => buffer(wgpu::BufferBindingType::Uniform),
+            (naga::AddressSpace::Storage { access }, _) => buffer(wgpu::BufferBindingType::Storage { read_only: !access.contains(naga::StorageAccess::STORE) }),
+            (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => wgpu::BindingType::Sampler(if *comparison {
+                wgpu::SamplerBindingType::Comparison
+            } else {
+                wgpu::SamplerBindingType::Filtering
+            }),
+            (naga::AddressSpace::Handle, naga::TypeInner::Image { dim, arrayed, class }) => {
+                let view_dimension = match (dim, arrayed) {
+                    (naga::ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+                    (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                    (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                    (naga::ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+                    (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                    (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    (dim, _) => anyhow::bail!("arrayed {:?} textures are not supported", dim),
+                };
+                let (sample_type, multisampled) = match *class {
+                    naga::ImageClass::Sampled { kind, multi } => {
+                        let sample_type = match kind {
+                            // 多重采样的纹理不能过滤
+                            naga::ScalarKind::Float => wgpu::TextureSampleType::Float { filterable: !multi },
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            kind => anyhow::bail!("{:?} textures are not supported", kind),
+                        };
+                        (sample_type, multi)
+                    }
+                    naga::ImageClass::Depth { multi } => (wgpu::TextureSampleType::Depth, multi),
+                    naga::ImageClass::Storage { .. } => anyhow::bail!("storage textures are not supported"),
+                };
+                wgpu::BindingType::Texture { sample_type, view_dimension, multisampled }
+            }
+            (space, ty) => anyhow::bail!("{:?} in {:?} address space", ty, space),
+        })
+    }
+
+    /// 顶点着色器入口点的 @location 输入（包括结构体参数的成员），按位置排列
+    pub fn vertex_inputs(&self, entry_point: &str) -> anyhow::Result<Vec<VertexInput>> {
+        let entry = self
+            .module
+            .entry_points
+            .iter()
+            .find(|entry| entry.name == entry_point)
+            .ok_or_else(|| anyhow!("{}: missing entry point `{}`", self.label, entry_point))?;
+        anyhow::ensure!(entry.stage == naga::ShaderStage::Vertex, "{}: `{}` is not a vertex shader", self.label, entry_point);
+
+        let mut inputs = Vec::new();
+        for argument in &entry.function.arguments {
+            match &self.module.types[argument.ty].inner {
+                naga::TypeInner::Struct { members, .. } => {
+                    for member in members {
+                        self.push_input(&mut inputs, member.name.as_deref(), member.ty, member.binding.as_ref())?;
+                    }
+                }
+                _ => self.push_input(&mut inputs, argument.name.as_deref(), argument.ty, argument.binding.as_ref())?,
+            }
+        }
+        inputs.sort_by_key(|input| input.location);
+        Ok(inputs)
+    }
+
+    fn push_input(&self, inputs: &mut Vec<VertexInput>, name: Option<&str>, ty: naga::Handle<naga::Type>, binding: Option<&naga::Binding>) -> anyhow::Result<()> {
+        // 内置变量（vertex_index 等）不来自顶点缓冲区
+        let Some(naga::Binding::Location { location, .. }) = binding else {
+            return Ok(());
+        };
+        let name = name.unwrap_or("<unnamed>").to_string();
+        let (scalar, components) = match self.module.types[ty].inner {
+            naga::TypeInner::Scalar(scalar) => (scalar, 1),
+            naga::TypeInner::Vector { size, scalar } => (scalar, size as u32),
+            ref inner => anyhow::bail!("{}: vertex input `{}` has unsupported type {:?}", self.label, name, inner),
+        };
+        inputs.push(VertexInput { location: *location, name, kind: scalar.kind, components });
+        Ok(())
+    }
+
+    /// 检查顶点缓冲区布局与顶点着色器的输入是否一致：每个输入都有对应的属性，标量类型和分量数相同
+    /// （比 WebGPU 的规则更严格，分量数不同多半是写错了格式），属性不重叠、不超出步长。
+    /// 着色器没有用到的属性不算错误。所有不一致的地方在一个错误中列出
+    pub fn validate_vertex_buffers(&self, entry_point: &str, buffers: &[wgpu::VertexBufferLayout]) -> anyhow::Result<()> {
+        let inputs = self.vertex_inputs(entry_point)?;
+        let mut problems = Vec::new();
+
+        // (缓冲区下标, 属性)
+        let attributes: Vec<(usize, &wgpu::VertexAttribute)> =
+            buffers.iter().enumerate().flat_map(|(index, buffer)| buffer.attributes.iter().map(move |attribute| (index, attribute))).collect();
+        for (i, (buffer, attribute)) in attributes.iter().enumerate() {
+            let layout = &buffers[*buffer];
+            let end = attribute.offset + attribute.format.size();
+            if layout.array_stride != 0 && end > layout.array_stride {
+                problems.push(format!(
+                    "buffer {} location {}: {:?} at offset {} ends at {}, past the array stride {}",
+                    buffer, attribute.shader_location, attribute.format, attribute.offset, end, layout.array_stride
+                ));
+            }
+            for (other_buffer, other) in &attributes[..i] {
+                if other.shader_location == attribute.shader_location {
+                    problems.push(format!("location {} is used by buffer {} and buffer {}", attribute.shader_location, other_buffer, buffer));
+                } else if other_buffer == buffer && attribute.offset < other.offset + other.format.size() && other.offset < end {
+                    problems.push(format!("buffer {}: locations {} and {} overlap", buffer, other.shader_location, attribute.shader_location));
+                }
+            }
+        }
+
+        for input in &inputs {
+            let Some((buffer, attribute)) = attributes.iter().find(|(_, attribute)| attribute.shader_location == input.location) else {
+                problems.push(format!("location {} ({}) is not provided by any vertex buffer", input.location, input));
+                continue;
+            };
+            if vertex_format_type(attribute.format) != Some((input.kind, input.components)) {
+                problems.push(format!(
+                    "location {} ({}) does not match {:?} in buffer {}",
+                    input.location, input, attribute.format, buffer
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "{}: vertex buffer layouts do not match the inputs of `{}`:\n  - {}",
+                self.label,
+                entry_point,
+                problems.join("\n  - ")
+            );
+        }
+        Ok(())
+    }
+}
+
+// 顶点格式在着色器中读到的标量类型和分量数。unorm/snorm 和半精度浮点读到的是 f32，f64 不支持
+fn vertex_format_type(format: wgpu::VertexFormat) -> Option<(naga::ScalarKind, u32)> {
+    use naga::ScalarKind::{Float, Sint, Uint};
+    use wgpu::VertexFormat as F;
+    Some(match format {
+        F::Uint8x2 | F::Uint16x2 | F::Uint32x2 => (Uint, 2),
+        F::Uint32x3 => (Uint, 3),
+        F::Uint8x4 | F::Uint16x4 | F::Uint32x4 => (Uint, 4),
+        F::Uint32 => (Uint, 1),
+        F::Sint8x2 | F::Sint16x2 | F::Sint32x2 => (Sint, 2),
+        F::Sint32x3 => (Sint, 3),
+        F::Sint8x4 | F::Sint16x4 | F::Sint32x4 => (Sint, 4),
+        F::Sint32 => (Sint, 1),
+        F::Unorm8x2 | F::Snorm8x2 | F::Unorm16x2 | F::Snorm16x2 | F::Float16x2 | F::Float32x2 => (Float, 2),
+        F::Float32x3 => (Float, 3),
+        F::Unorm8x4 | F::Snorm8x4 | F::Unorm16x4 | F::Snorm16x4 | F::Float16x4 | F::Float32x4 | F::Unorm10_10_10_2 => (Float, 4),
+        F::Float32 => (Float, 1),
+        F::Float64 | F::Float64x2 | F::Float64x3 | F::Float64x4 => return None,
+    })
+}
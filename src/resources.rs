@@ -1,32 +1,47 @@
 use std::io::{BufReader,Cursor};
 
-use anyhow::Ok;
+use anyhow::{Context, Ok};
 use wgpu::util::DeviceExt;
-use crate::{model, texture};
+use crate::{bounds::Aabb, gltf_loader, model, texture};
 
-pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
+// 资源文件在构建时被复制到 OUT_DIR/res 下
+pub fn res_path(file_name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("OUT_DIR"))
         .join("res")
-        .join(file_name);
-    let data = std::fs::read_to_string(path)?;
+        .join(file_name)
+}
+
+pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
+    let data = std::fs::read_to_string(res_path(file_name))?;
     Ok(data)
 }
 
 pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let data = std::fs::read(path)?;
+    let data = std::fs::read(res_path(file_name))?;
     Ok(data)
 }
 
-pub async fn load_texture(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queue) ->anyhow::Result<texture::Texture> {
+pub async fn load_texture(file_name: &str,options: &texture::TextureOptions,device: &wgpu::Device,queue: &wgpu::Queue,mipmaps: &texture::MipmapGenerator) ->anyhow::Result<texture::Texture> {
     let data = load_binary(file_name).await?;
-    texture::Texture::from_bytes(device, queue, &data,file_name)
+    texture::Texture::from_bytes(device, queue, &data,file_name,options,mipmaps)
+}
+
+// 根据扩展名选择加载器，OBJ 和 glTF 最终都得到同样的 Model
+pub async fn load_model(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queue,layout: &wgpu::BindGroupLayout,mipmaps: &texture::MipmapGenerator) -> anyhow::Result<model::Model> {
+    let extension = std::path::Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => load_obj(file_name, device, queue, layout, mipmaps).await,
+        Some("gltf") | Some("glb") => gltf_loader::load_gltf(file_name, device, queue, layout, mipmaps).await,
+        _ => anyhow::bail!("unsupported model format: {}", file_name),
+    }
 }
 
-pub async fn load_model(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queue,layout: &wgpu::BindGroupLayout) -> anyhow::Result<model::Model> {
-    let obj_text = load_string(file_name).await?;
+async fn load_obj(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queue,layout: &wgpu::BindGroupLayout,mipmaps: &texture::MipmapGenerator) -> anyhow::Result<model::Model> {
+    let obj_text = load_string(file_name).await
+        .with_context(|| format!("failed to read {}", file_name))?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
@@ -38,76 +53,469 @@ pub async fn load_model(file_name: &str,device: &wgpu::Device,queue: &wgpu::Queu
             ..Default::default()
         },
         |p| async move{
-            let mat_text = load_string(&p).await.unwrap();
+            let mat_text = load_string(&p).await.map_err(|_| tobj::LoadError::OpenFileFailed)?;
             tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
         } 
-    ).await?;   
+    ).await.with_context(|| format!("failed to parse {}", file_name))?;
+
+    // 材质库缺失或损坏时不终止加载，所有网格退回默认材质
+    let obj_materials = obj_materials.unwrap_or_else(|e| {
+        log::warn!("{}: failed to load materials: {}", file_name, e);
+        Vec::new()
+    });
     let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor{
-            layout,
-            label: None,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
+    for m in obj_materials {
+        let diffuse_map = MtlTexture::parse(&m.diffuse_texture);
+        let normal_map = MtlTexture::parse(&m.normal_texture);
+
+        let diffuse_texture = if diffuse_map.file.is_empty() {
+            texture::Texture::from_color(device, queue, [255, 255, 255, 255], "white", &texture::TextureOptions::color())?
+        } else {
+            load_texture(&diffuse_map.file, &diffuse_map.options(texture::TextureOptions::color()), device, queue, mipmaps).await
+                .with_context(|| format!("{}: failed to load texture {}", m.name, diffuse_map.file))?
+        };
+        // 没有法线贴图时使用指向 +Z 的平坦法线，相当于不扰动法线
+        let normal_texture = if normal_map.file.is_empty() {
+            texture::Texture::from_color(device, queue, [128, 128, 255, 255], "flat_normal", &texture::TextureOptions::data())?
+        } else {
+            load_texture(&normal_map.file, &normal_map.options(texture::TextureOptions::data()), device, queue, mipmaps).await
+                .with_context(|| format!("{}: failed to load texture {}", m.name, normal_map.file))?
+        };
+
+        // 两张贴图共用同一组纹理坐标，以漫反射贴图的 -s/-o 为准
+        let uv_source = if diffuse_map.file.is_empty() { &normal_map } else { &diffuse_map };
+        if !diffuse_map.file.is_empty() && !normal_map.file.is_empty()
+            && (diffuse_map.scale != normal_map.scale || diffuse_map.offset != normal_map.offset) {
+            log::warn!("{}: normal map -s/-o differ from the diffuse map and are ignored", m.name);
+        }
+        let uniform = model::MaterialUniform::new(m.ambient, m.diffuse, m.specular, m.shininess)
+            .with_uv_transform(uv_source.scale, uv_source.offset);
+
+        materials.push(model::Material::new(device, &m.name, diffuse_texture, normal_texture, uniform, layout));
+    }
+
+    // 没有 usemtl 或引用了不存在的材质的网格使用灰白色的默认材质
+    let default_material = materials.len();
+    materials.push(model::Material::new(
+        device,
+        "obj_default",
+        texture::Texture::from_color(device, queue, [255, 255, 255, 255], "white", &texture::TextureOptions::color())?,
+        texture::Texture::from_color(device, queue, [128, 128, 255, 255], "flat_normal", &texture::TextureOptions::data())?,
+        model::MaterialUniform::new([0.8; 3], [0.8; 3], [0.5; 3], 32.0),
+        layout,
+    ));
+
+    let mut meshes = Vec::new();
+    for m in models {
+        let (vertices, indices) = obj_mesh_vertices(&m.mesh)
+            .with_context(|| format!("{}: invalid mesh {:?}", file_name, m.name))?;
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+            label: Some(&format!("{:?} Vertex Buffer",file_name)),
+            contents: bytemuck::cast_slice(vertices.as_slice()),
+            // 线框的回退方案在着色器中把顶点和索引当作存储缓冲区读取
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
         });
 
-        materials.push(model::Material{
-            name: m.name,
-            diffuse_texture,
-            bind_group,
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+            label: Some(&format!("{:?} Index Buffer",file_name)),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+        });
+
+        meshes.push(model::Mesh{
+            name: file_name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            bounds: Aabb::from_points(vertices.iter().map(|v| glam::Vec3::from(v.position))),
+            material: m.mesh.material_id
+                .filter(|&id| id < default_material)
+                .unwrap_or(default_material),
         });
     }
 
-    let meshes = models.into_iter().map(
-        |m| {
-            let vertices = (0..m.mesh.positions.len()/3)
-                .map(|i| model::ModelVertex{
-                    position:[
-                        m.mesh.positions[i*3],
-                        m.mesh.positions[i*3+1],
-                        m.mesh.positions[i*3+2]
-                    ],
-                    tex_coords: [
-                        m.mesh.texcoords[i*2],
-                        m.mesh.texcoords[i*2+1]
-                    ],
-                    normal:[
-                        m.mesh.normals[i*3],
-                        m.mesh.normals[i*3+1],
-                        m.mesh.normals[i*3+2]
-                    ]
-                }).collect::<Vec<_>>();
-            
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-                label: Some(&format!("{:?} Vertex Buffer",file_name)),
-                contents: bytemuck::cast_slice(vertices.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+    Ok(model::Model { meshes, materials })
+}
 
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-                label: Some(&format!("{:?} Index Buffer",file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
+// MTL 贴图语句可以在文件名前带选项，例如 `map_Kd -clamp on -s 2 2 1 bricks.png`，
+// tobj 会把整行原样保存下来。这里支持 -clamp、-s 和 -o，其余选项被跳过
+#[derive(Debug, PartialEq)]
+struct MtlTexture {
+    file: String,
+    clamp: bool,
+    scale: [f32; 2],
+    offset: [f32; 2],
+}
+
+impl MtlTexture {
+    fn parse(spec: &str) -> Self {
+        let mut texture = Self {
+            file: String::new(),
+            clamp: false,
+            scale: [1.0; 2],
+            offset: [0.0; 2],
+        };
+        let mut tokens = spec.split_whitespace().peekable();
+        let mut file = Vec::new();
+        while let Some(token) = tokens.next() {
+            // 选项后面跟着的数字参数，最多三个（u v w）
+            let mut numbers = || {
+                let mut values = Vec::new();
+                while values.len() < 3 {
+                    match tokens.peek().and_then(|t| t.parse::<f32>().ok()) {
+                        Some(value) => {
+                            values.push(value);
+                            tokens.next();
+                        }
+                        None => break,
+                    }
+                }
+                values
+            };
+            match token {
+                "-clamp" => texture.clamp = tokens.next() == Some("on"),
+                "-s" => {
+                    // 省略的 v 缩放与规范和 tinyobjloader 一致，默认为 1
+                    let values = numbers();
+                    if let Some(&u) = values.first() {
+                        texture.scale = [u, values.get(1).copied().unwrap_or(1.0)];
+                    }
+                }
+                "-o" => {
+                    let values = numbers();
+                    if let Some(&u) = values.first() {
+                        texture.offset = [u, values.get(1).copied().unwrap_or(0.0)];
+                    }
+                }
+                "-blendu" | "-blendv" | "-cc" | "-imfchan" | "-type" => {
+                    tokens.next();
+                }
+                "-bm" | "-boost" | "-texres" | "-mm" | "-t" => {
+                    numbers();
+                }
+                // 文件名中可能有空格
+                _ => file.push(token),
+            }
+        }
+        texture.file = file.join(" ");
+        texture
+    }
+
+    // MTL 规定贴图默认是重复的，-clamp on 时夹取到边缘
+    fn options(&self, options: texture::TextureOptions) -> texture::TextureOptions {
+        options.address_mode(if self.clamp {
+            wgpu::AddressMode::ClampToEdge
+        } else {
+            wgpu::AddressMode::Repeat
+        })
+    }
+}
+
+// 把 tobj 的网格转换成顶点数据。缺少纹理坐标时填 (0, 0)，缺少法线时根据几何生成，
+// 属性数量对不上或索引越界时返回错误，而不是在上传时越界 panic
+fn obj_mesh_vertices(mesh: &tobj::Mesh) -> anyhow::Result<(Vec<model::ModelVertex>, Vec<u32>)> {
+    anyhow::ensure!(mesh.positions.len().is_multiple_of(3), "position count {} is not a multiple of 3", mesh.positions.len());
+    let vertex_count = mesh.positions.len() / 3;
+    let has_tex_coords = !mesh.texcoords.is_empty();
+    let has_normals = !mesh.normals.is_empty();
+    anyhow::ensure!(
+        !has_tex_coords || mesh.texcoords.len() == vertex_count * 2,
+        "expected {} texture coordinates, found {}", vertex_count * 2, mesh.texcoords.len()
+    );
+    anyhow::ensure!(
+        !has_normals || mesh.normals.len() == vertex_count * 3,
+        "expected {} normal components, found {}", vertex_count * 3, mesh.normals.len()
+    );
+    anyhow::ensure!(mesh.indices.len().is_multiple_of(3), "index count {} is not a multiple of 3", mesh.indices.len());
+    if let Some(&i) = mesh.indices.iter().find(|&&i| i as usize >= vertex_count) {
+        anyhow::bail!("index {} out of bounds for {} vertices", i, vertex_count);
+    }
+
+    let mut vertices = (0..vertex_count)
+        .map(|i| model::ModelVertex{
+            position:[
+                mesh.positions[i*3],
+                mesh.positions[i*3+1],
+                mesh.positions[i*3+2]
+            ],
+            tex_coords: if has_tex_coords {
+                [mesh.texcoords[i*2], mesh.texcoords[i*2+1]]
+            } else {
+                [0.0; 2]
+            },
+            normal: if has_normals {
+                [mesh.normals[i*3], mesh.normals[i*3+1], mesh.normals[i*3+2]]
+            } else {
+                [0.0; 3]
+            },
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+        }).collect::<Vec<_>>();
+    let mut indices = mesh.indices.clone();
+
+    if !has_normals {
+        (vertices, indices) = generate_normals(&vertices, &indices);
+    }
+    compute_tangents(&mut vertices, &indices);
+    Ok((vertices, indices))
+}
+
+// 相邻面的夹角小于这个角度时共享平滑法线，否则保留硬边
+const CREASE_ANGLE: f32 = 60.0;
+
+// 文件中没有法线时根据几何生成：每个三角形的角点只累加与该三角形夹角
+// 小于 CREASE_ANGLE 的相邻面法线（按面积加权），因此曲面是平滑的，立方体的棱角是平的。
+// 同一个顶点在硬边两侧会得到不同的法线，需要拆分，所以返回新的顶点和索引
+pub fn generate_normals(vertices: &[model::ModelVertex], indices: &[u32]) -> (Vec<model::ModelVertex>, Vec<u32>) {
+    // 叉积的长度是三角形面积的两倍，正好作为权重
+    let face_normals = indices.chunks_exact(3).map(|c| {
+        let pos0 = glam::Vec3::from(vertices[c[0] as usize].position);
+        let pos1 = glam::Vec3::from(vertices[c[1] as usize].position);
+        let pos2 = glam::Vec3::from(vertices[c[2] as usize].position);
+        (pos1 - pos0).cross(pos2 - pos0)
+    }).collect::<Vec<_>>();
+
+    let mut adjacent_faces = vec![Vec::new(); vertices.len()];
+    for (face, c) in indices.chunks_exact(3).enumerate() {
+        for &i in c {
+            adjacent_faces[i as usize].push(face);
+        }
+    }
+
+    let min_cos = CREASE_ANGLE.to_radians().cos();
+    let mut new_vertices = Vec::with_capacity(vertices.len());
+    let mut new_indices = Vec::with_capacity(indices.len());
+    // (原顶点, 法线) 相同的角点合并成一个顶点
+    let mut lookup = std::collections::HashMap::new();
+    for (face, c) in indices.chunks_exact(3).enumerate() {
+        let face_normal = face_normals[face].normalize_or_zero();
+        for &i in c {
+            let normal = adjacent_faces[i as usize].iter()
+                .map(|&f| face_normals[f])
+                .filter(|n| n.normalize_or_zero().dot(face_normal) >= min_cos)
+                .sum::<glam::Vec3>()
+                .try_normalize()
+                .unwrap_or(glam::Vec3::Z);
+            let key = (i, normal.to_array().map(f32::to_bits));
+            let index = *lookup.entry(key).or_insert_with(|| {
+                let mut vertex = vertices[i as usize];
+                vertex.normal = normal.into();
+                new_vertices.push(vertex);
+                new_vertices.len() as u32 - 1
             });
+            new_indices.push(index);
+        }
+    }
+    (new_vertices, new_indices)
+}
 
-            model::Mesh{
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+// 根据每个三角形的位置和纹理坐标计算切线和副切线，共享顶点取平均值
+pub fn compute_tangents(vertices: &mut [model::ModelVertex], indices: &[u32]) {
+    let mut triangles_included = vec![0u32; vertices.len()];
+
+    for c in indices.chunks_exact(3) {
+        let v0 = vertices[c[0] as usize];
+        let v1 = vertices[c[1] as usize];
+        let v2 = vertices[c[2] as usize];
+
+        let pos0 = glam::Vec3::from(v0.position);
+        let pos1 = glam::Vec3::from(v1.position);
+        let pos2 = glam::Vec3::from(v2.position);
+
+        let uv0 = glam::Vec2::from(v0.tex_coords);
+        let uv1 = glam::Vec2::from(v1.tex_coords);
+        let uv2 = glam::Vec2::from(v2.tex_coords);
+
+        // 三角形的边
+        let delta_pos1 = pos1 - pos0;
+        let delta_pos2 = pos2 - pos0;
+
+        // 纹理坐标的变化方向
+        let delta_uv1 = uv1 - uv0;
+        let delta_uv2 = uv2 - uv0;
+
+        // 求解:
+        //     delta_pos1 = delta_uv1.x * T + delta_uv1.y * B
+        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+        let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        // 纹理坐标退化的三角形无法确定切线方向
+        if det.abs() <= f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        // wgpu 的纹理坐标 v 轴朝下，所以副切线需要翻转
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+        for &i in c {
+            let v = &mut vertices[i as usize];
+            v.tangent = (tangent + glam::Vec3::from(v.tangent)).into();
+            v.bitangent = (bitangent + glam::Vec3::from(v.bitangent)).into();
+            triangles_included[i as usize] += 1;
+        }
+    }
+
+    for (v, n) in vertices.iter_mut().zip(triangles_included) {
+        if n > 0 {
+            let denom = 1.0 / n as f32;
+            v.tangent = (glam::Vec3::from(v.tangent) * denom).into();
+            v.bitangent = (glam::Vec3::from(v.bitangent) * denom).into();
+        }
+        // 没有纹理坐标（或者纹理坐标全部退化）的顶点求不出切线，着色器 normalize 零向量会得到 NaN。
+        // 这时用法线构造任意一组正交的切线和副切线，平坦的法线贴图下结果就是原来的法线
+        let tangent = glam::Vec3::from(v.tangent).try_normalize();
+        let bitangent = glam::Vec3::from(v.bitangent).try_normalize();
+        if tangent.is_none() || bitangent.is_none() {
+            let normal = glam::Vec3::from(v.normal).try_normalize().unwrap_or(glam::Vec3::Z);
+            let tangent = normal.any_orthonormal_vector();
+            v.tangent = tangent.into();
+            v.bitangent = normal.cross(tangent).into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 解析 tests/obj 下手写的小 OBJ 文件
+    fn load_corpus(file_name: &str) -> anyhow::Result<Vec<(Vec<model::ModelVertex>, Vec<u32>)>> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("obj")
+            .join(file_name);
+        let (models, _) = tobj::load_obj(
+            &path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        ).with_context(|| format!("failed to parse {}", file_name))?;
+        models.iter().map(|m| obj_mesh_vertices(&m.mesh)).collect()
+    }
+
+    fn normal(v: &model::ModelVertex) -> glam::Vec3 {
+        glam::Vec3::from(v.normal)
+    }
+
+    #[test]
+    fn complete_mesh_keeps_attributes() {
+        let meshes = load_corpus("complete.obj").unwrap();
+        let (vertices, indices) = &meshes[0];
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        assert!(vertices.iter().all(|v| normal(v) == glam::Vec3::Z));
+        assert_eq!(vertices[2].tex_coords, [1.0, 1.0]);
+        assert!(vertices.iter().all(|v| glam::Vec3::from(v.tangent).normalize().abs_diff_eq(glam::Vec3::X, 1e-6)));
+    }
+
+    #[test]
+    fn missing_tex_coords_default_to_zero() {
+        let meshes = load_corpus("no_uvs.obj").unwrap();
+        let (vertices, _) = &meshes[0];
+        assert!(vertices.iter().all(|v| v.tex_coords == [0.0, 0.0]));
+        assert!(vertices.iter().all(|v| normal(v) == glam::Vec3::Z));
+    }
+
+    #[test]
+    fn missing_tex_coords_get_tangents_from_normals() {
+        let meshes = load_corpus("no_uvs.obj").unwrap();
+        let (vertices, _) = &meshes[0];
+        for v in vertices {
+            let tangent = glam::Vec3::from(v.tangent);
+            let bitangent = glam::Vec3::from(v.bitangent);
+            assert!(tangent.is_finite() && bitangent.is_finite());
+            assert!((tangent.length() - 1.0).abs() < 1e-6 && (bitangent.length() - 1.0).abs() < 1e-6);
+            assert!(tangent.dot(normal(v)).abs() < 1e-6 && bitangent.dot(normal(v)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn missing_normals_are_generated() {
+        let meshes = load_corpus("positions_only.obj").unwrap();
+        let (vertices, indices) = &meshes[0];
+        assert_eq!(indices.len(), 3);
+        assert!(vertices.iter().all(|v| normal(v).abs_diff_eq(glam::Vec3::Z, 1e-6)));
+    }
+
+    #[test]
+    fn hard_edges_get_flat_normals() {
+        let meshes = load_corpus("no_normals_cube.obj").unwrap();
+        let (vertices, indices) = &meshes[0];
+        // 每个角被三个面共享，拆分成三个顶点
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        for c in indices.chunks_exact(3) {
+            let n = normal(&vertices[c[0] as usize]);
+            assert!(c.iter().all(|&i| normal(&vertices[i as usize]) == n));
+            // 法线沿坐标轴并指向立方体外侧
+            assert_eq!(n.abs().max_element(), 1.0);
+            assert!(c.iter().all(|&i| glam::Vec3::from(vertices[i as usize].position).dot(n) > 0.0));
+        }
+    }
+
+    #[test]
+    fn soft_edges_get_smooth_normals() {
+        let meshes = load_corpus("no_normals_roof.obj").unwrap();
+        let (vertices, _) = &meshes[0];
+        assert_eq!(vertices.len(), 6);
+        for v in vertices {
+            let n = normal(v);
+            assert!((n.length() - 1.0).abs() < 1e-6);
+            if v.position[0] == 0.0 {
+                // 屋脊上的顶点取两侧面法线的加权平均，接近竖直
+                assert!(n.abs_diff_eq(glam::Vec3::Y, 0.1));
+            } else {
+                assert!(n.y > 0.9 && n.x != 0.0);
             }
         }
-    ).collect::<Vec<_>>();
+    }
 
-    Ok(model::Model { meshes, materials })
-}
\ No newline at end of file
+    #[test]
+    fn malformed_files_return_errors() {
+        for file_name in ["bad_index.obj", "bad_number.obj", "missing.obj"] {
+            let error = load_corpus(file_name).unwrap_err();
+            assert!(error.to_string().contains(file_name), "{:#}", error);
+        }
+    }
+
+    #[test]
+    fn mtl_texture_options() {
+        assert_eq!(MtlTexture::parse("cube-diffuse.jpg"), MtlTexture {
+            file: "cube-diffuse.jpg".to_string(),
+            clamp: false,
+            scale: [1.0, 1.0],
+            offset: [0.0, 0.0],
+        });
+        assert_eq!(MtlTexture::parse("-clamp on -s 2 3 1 -o 0.5 -bm 0.8 brick wall.png"), MtlTexture {
+            file: "brick wall.png".to_string(),
+            clamp: true,
+            scale: [2.0, 3.0],
+            offset: [0.5, 0.0],
+        });
+        assert_eq!(MtlTexture::parse("-s 4 -clamp off tiles.png").scale, [4.0, 1.0]);
+        assert!(MtlTexture::parse("").file.is_empty());
+    }
+
+    #[test]
+    fn inconsistent_attributes_return_errors() {
+        let mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            texcoords: vec![0.0, 0.0],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        let error = obj_mesh_vertices(&mesh).unwrap_err();
+        assert!(error.to_string().contains("texture coordinates"), "{:#}", error);
+
+        let mesh = tobj::Mesh {
+            positions: vec![0.0; 9],
+            indices: vec![0, 1, 3],
+            ..Default::default()
+        };
+        let error = obj_mesh_vertices(&mesh).unwrap_err();
+        assert!(error.to_string().contains("out of bounds"), "{:#}", error);
+    }
+}
@@ -0,0 +1,364 @@
+// 场景图：节点有局部变换和父子关系，可以挂载模型和摄像机。
+// 世界矩阵 = 父节点世界矩阵 × 局部矩阵。修改局部变换只设置脏标记，
+// update_world_transforms 时只重新计算脏节点及其子树。
+// 挂载同一个模型的节点作为该模型的实例一起绘制，所以多部件的物体可以由若干个节点拼装而成
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{
+    bounds::Aabb,
+    camera::{Camera, Projection},
+    instance::InstanceRaw,
+    model::{DrawModel, Model},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+            scale: glam::Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: glam::Vec3) -> Self {
+        Self { translation, ..Default::default() }
+    }
+
+    pub fn with_rotation(mut self, rotation: glam::Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: glam::Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModelId(usize);
+
+pub struct Node {
+    pub name: String,
+    transform: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    model: Option<ModelId>,
+    // 摄像机的位置和朝向来自节点的世界矩阵（看向 -Z，上方是 +Y），这里只保存投影
+    camera: Option<Projection>,
+    world: glam::Mat4,
+    dirty: bool,
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn model(&self) -> Option<ModelId> {
+        self.model
+    }
+
+    pub fn camera(&self) -> Option<Projection> {
+        self.camera
+    }
+
+    // 上一次 update_world_transforms 计算的世界矩阵
+    pub fn world_matrix(&self) -> glam::Mat4 {
+        self.world
+    }
+}
+
+#[derive(Default)]
+pub struct Scene {
+    // 删除的节点留下空位，NodeId 不会被复用
+    nodes: Vec<Option<Node>>,
+    roots: Vec<NodeId>,
+    models: Vec<Model>,
+    // 节点、模型挂载或世界矩阵变化后需要重新生成实例数据
+    instances_dirty: bool,
+    instance_buffer: Option<wgpu::Buffer>,
+    instance_capacity: usize,
+    // 每个模型的实例在实例缓冲区中的范围
+    batches: Vec<(ModelId, Range<u32>)>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_model(&mut self, model: Model) -> ModelId {
+        self.models.push(model);
+        ModelId(self.models.len() - 1)
+    }
+
+    pub fn model(&self, id: ModelId) -> &Model {
+        &self.models[id.0]
+    }
+
+    // 添加根节点
+    pub fn add_node(&mut self, name: &str, transform: Transform) -> NodeId {
+        let id = self.insert(name, transform, None);
+        self.roots.push(id);
+        id
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, name: &str, transform: Transform) -> anyhow::Result<NodeId> {
+        self.node_mut(parent)?;
+        let id = self.insert(name, transform, Some(parent));
+        self.node_mut(parent)?.children.push(id);
+        Ok(id)
+    }
+
+    fn insert(&mut self, name: &str, transform: Transform, parent: Option<NodeId>) -> NodeId {
+        self.nodes.push(Some(Node {
+            name: name.to_string(),
+            transform,
+            parent,
+            children: Vec::new(),
+            model: None,
+            camera: None,
+            world: glam::Mat4::IDENTITY,
+            dirty: true,
+        }));
+        self.instances_dirty = true;
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> anyhow::Result<&mut Node> {
+        self.nodes
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| anyhow::anyhow!("node {:?} does not exist", id))
+    }
+
+    // 按名字查找第一个匹配的节点
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.as_ref().is_some_and(|node| node.name == name))
+            .map(NodeId)
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // 所有存在的节点
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| node.as_ref().map(|node| (NodeId(i), node)))
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) -> anyhow::Result<()> {
+        let node = self.node_mut(id)?;
+        node.transform = transform;
+        node.dirty = true;
+        Ok(())
+    }
+
+    pub fn set_model(&mut self, id: NodeId, model: Option<ModelId>) -> anyhow::Result<()> {
+        if let Some(model) = model {
+            anyhow::ensure!(model.0 < self.models.len(), "model {:?} does not exist", model);
+        }
+        self.node_mut(id)?.model = model;
+        self.instances_dirty = true;
+        Ok(())
+    }
+
+    pub fn set_camera(&mut self, id: NodeId, projection: Option<Projection>) -> anyhow::Result<()> {
+        self.node_mut(id)?.camera = projection;
+        Ok(())
+    }
+
+    // 把节点移到新的父节点下（None 表示成为根节点），局部变换保持不变
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> anyhow::Result<()> {
+        self.node_mut(id)?;
+        if let Some(parent) = parent {
+            // 新的父节点不能是自己或自己的后代
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                anyhow::ensure!(current != id, "cannot parent {:?} to its own descendant {:?}", id, parent);
+                ancestor = self.node_mut(current)?.parent;
+            }
+        }
+
+        self.detach(id);
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.node_mut(id)?;
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+
+    // 删除节点及其整个子树
+    pub fn remove_node(&mut self, id: NodeId) -> anyhow::Result<()> {
+        self.node_mut(id)?;
+        self.detach(id);
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            if let Some(node) = self.nodes[current.0].take() {
+                stack.extend(node.children);
+            }
+        }
+        self.instances_dirty = true;
+        Ok(())
+    }
+
+    // 从父节点的子节点列表（或根节点列表）中移除
+    fn detach(&mut self, id: NodeId) {
+        let parent = self.node(id).and_then(|node| node.parent);
+        let siblings = match parent.and_then(|parent| self.nodes[parent.0].as_mut()) {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&child| child != id);
+    }
+
+    // 从根节点向下传播世界矩阵，只重新计算脏节点及其子树，返回重新计算的节点数量
+    pub fn update_world_transforms(&mut self) -> usize {
+        let mut updated = 0;
+        let mut stack = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, glam::Mat4::IDENTITY, false))
+            .collect::<Vec<_>>();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let Some(node) = self.nodes[id.0].as_mut() else {
+                continue;
+            };
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+                updated += 1;
+                if node.model.is_some() {
+                    self.instances_dirty = true;
+                }
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+        updated
+    }
+
+    // 所有挂载了模型的节点的包围盒（世界空间），使用上一次计算的世界矩阵
+    pub fn bounds(&self) -> Aabb {
+        self.nodes()
+            .filter_map(|(_, node)| Some(self.models[node.model?.0].bounds().transform(&node.world)))
+            .fold(Aabb::EMPTY, |aabb, bounds| aabb.union(&bounds))
+    }
+
+    // 由摄像机节点的世界矩阵构造摄像机
+    pub fn camera(&self, id: NodeId, aspect: f32) -> Option<Camera> {
+        let node = self.node(id)?;
+        let projection = node.camera?;
+        let eye = node.world.transform_point3(glam::Vec3::ZERO);
+        let forward = node.world.transform_vector3(glam::Vec3::NEG_Z).normalize();
+        let mut camera = Camera::new(aspect);
+        camera.eye = eye;
+        camera.target = eye + forward;
+        camera.up = node.world.transform_vector3(glam::Vec3::Y).normalize();
+        camera.projection = projection;
+        Some(camera)
+    }
+
+    // 更新世界矩阵，有变化时按模型分组重新生成实例数据并上传
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.update_world_transforms();
+        if !self.instances_dirty {
+            return;
+        }
+        self.instances_dirty = false;
+
+        let mut groups = BTreeMap::<ModelId, Vec<InstanceRaw>>::new();
+        for (_, node) in self.nodes() {
+            if let Some(model) = node.model {
+                groups.entry(model).or_default().push(InstanceRaw::new(node.world, glam::Vec4::ONE, None));
+            }
+        }
+        self.batches.clear();
+        let mut raw = Vec::new();
+        for (model, instances) in groups {
+            let start = raw.len() as u32;
+            raw.extend(instances);
+            self.batches.push((model, start..raw.len() as u32));
+        }
+        if raw.is_empty() {
+            return;
+        }
+
+        // 容量不足时按 2 的幂扩容
+        if self.instance_buffer.is_none() || raw.len() > self.instance_capacity {
+            self.instance_capacity = raw.len().next_power_of_two();
+            self.instance_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("scene_instance_buffer"),
+                size: (self.instance_capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        queue.write_buffer(self.instance_buffer.as_ref().unwrap(), 0, bytemuck::cast_slice(&raw));
+    }
+
+    // 上一次 prepare 的结果：每个模型、实例缓冲区和实例范围，供阴影等通道使用
+    pub fn draws(&self) -> impl Iterator<Item = (&Model, &wgpu::Buffer, Range<u32>)> {
+        self.batches.iter().filter_map(|(model, range)| {
+            let buffer = self.instance_buffer.as_ref()?;
+            Some((&self.models[model.0], buffer, range.clone()))
+        })
+    }
+}
+
+pub trait DrawScene<'a> {
+    // 绘制 prepare 之后的整个场景，调用前需要设置好渲染管线和阴影绑定组
+    fn draw_scene(&mut self, scene: &'a Scene, camera_bind_group: &'a wgpu::BindGroup, light_bind_group: &'a wgpu::BindGroup);
+}
+
+impl<'a, 'b> DrawScene<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_scene(&mut self, scene: &'b Scene, camera_bind_group: &'b wgpu::BindGroup, light_bind_group: &'b wgpu::BindGroup) {
+        for (model, buffer, instances) in scene.draws() {
+            self.set_vertex_buffer(1, buffer.slice(..));
+            self.draw_model_instanced(model, instances, camera_bind_group, light_bind_group);
+        }
+    }
+}
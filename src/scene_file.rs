@@ -0,0 +1,544 @@
+// 场景描述文件：要加载的模型、实例的摆放方式（单个、网格、随机散布）、摄像机、光源和清屏颜色。
+// 支持 RON 和 JSON 两种格式，按扩展名区分。文件中的角度都使用度，位置和颜色写成 (x, y, z) 元组（JSON 中为数组）。
+//
+// 加载分为两步：load 解析并检查文件本身，State::load_scene_file 再加载模型并检查材质覆盖。
+// 任何一步出错都不会修改当前场景，错误信息会指出出错的条目，例如 `models[0].instances[1].layout.spacing`
+
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::{
+    camera::{Camera, Projection},
+    instance::Instance,
+    lights::{DirectionalLight, Light, PointLight, SpotLight},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    // RGBA，线性空间
+    #[serde(default = "default_clear_color")]
+    pub clear_color: [f64; 4],
+    #[serde(default)]
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub lights: Vec<LightDesc>,
+    pub models: Vec<ModelDesc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    #[serde(default)]
+    pub projection: ProjectionDesc,
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            eye: [0.0, 1.0, 2.0],
+            target: [0.0, 1.0, 0.0],
+            up: default_up(),
+            projection: ProjectionDesc::default(),
+        }
+    }
+}
+
+// 与 camera::Projection 一一对应，只是视野角使用度
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ProjectionDesc {
+    Perspective { fovy: f32, znear: f32, zfar: f32 },
+    Orthographic { height: f32, znear: f32, zfar: f32 },
+    InfinitePerspectiveReverseZ { fovy: f32, znear: f32 },
+}
+
+impl Default for ProjectionDesc {
+    fn default() -> Self {
+        ProjectionDesc::Perspective { fovy: 45.0, znear: 0.1, zfar: 100.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum LightDesc {
+    Directional {
+        direction: [f32; 3],
+        #[serde(default = "default_color")]
+        color: [f32; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+    Point {
+        position: [f32; 3],
+        #[serde(default = "default_color")]
+        color: [f32; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        range: f32,
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        #[serde(default = "default_color")]
+        color: [f32; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDesc {
+    // res 目录下的 OBJ 或 glTF 文件
+    pub file: String,
+    #[serde(default)]
+    pub instances: Vec<PlacementDesc>,
+}
+
+// 一组实例：layout 决定位置，其余属性所有实例共用
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlacementDesc {
+    pub layout: LayoutDesc,
+    #[serde(default)]
+    pub rotation: RotationDesc,
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+    #[serde(default = "default_tint")]
+    pub tint: [f32; 4],
+    // 材质覆盖，是模型材质列表中的下标
+    #[serde(default)]
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum LayoutDesc {
+    Single {
+        position: [f32; 3],
+    },
+    // XZ 平面上 count[0] 列（沿 X）乘 count[1] 行（沿 Z）的网格，center 是网格的中心
+    Grid {
+        count: [u32; 2],
+        spacing: f32,
+        #[serde(default)]
+        center: [f32; 3],
+    },
+    // 在 [min, max] 范围内均匀随机摆放，相同的 seed 总是得到相同的结果
+    Scatter {
+        count: u32,
+        min: [f32; 3],
+        max: [f32; 3],
+        #[serde(default)]
+        seed: u64,
+        // 每个实例再绕 Y 轴随机旋转
+        #[serde(default)]
+        random_yaw: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum RotationDesc {
+    // 按 X、Y、Z 的顺序旋转的欧拉角
+    Euler([f32; 3]),
+    // 绕“世界原点指向实例”的轴旋转，位于原点的实例不旋转
+    Radial(f32),
+}
+
+impl Default for RotationDesc {
+    fn default() -> Self {
+        RotationDesc::Euler([0.0; 3])
+    }
+}
+
+fn default_clear_color() -> [f64; 4] {
+    [0.1, 0.2, 0.3, 1.0]
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_color() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_tint() -> [f32; 4] {
+    [1.0; 4]
+}
+
+impl SceneFile {
+    // 读取、解析并检查场景文件
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene file {}", path.display()))?;
+        let scene = Self::parse(&text, path)
+            .with_context(|| format!("failed to parse scene file {}", path.display()))?;
+        scene.validate()
+            .with_context(|| format!("invalid scene file {}", path.display()))?;
+        Ok(scene)
+    }
+
+    // 只解析不检查，格式由 path 的扩展名决定。错误信息中带有行号和列号
+    pub fn parse(text: &str, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let extension = path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            // 可选字段直接写值，不需要 Some(...)
+            Some("ron") => Ok(ron::Options::default()
+                .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+                .from_str(text)?),
+            Some("json") => Ok(serde_json::from_str(text)?),
+            _ => anyhow::bail!("unsupported scene format: {} (expected .ron or .json)", path.as_ref().display()),
+        }
+    }
+
+    // 检查所有条目，把全部错误一起报告，每条错误以出错条目的路径开头
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+        if !self.clear_color.iter().all(|c| c.is_finite()) {
+            errors.push("clear_color: components must be finite".to_string());
+        }
+        self.camera.validate("camera", &mut errors);
+        for (i, light) in self.lights.iter().enumerate() {
+            light.validate(&format!("lights[{}]", i), &mut errors);
+        }
+        if self.models.is_empty() {
+            errors.push("models: at least one model is required".to_string());
+        }
+        for (i, model) in self.models.iter().enumerate() {
+            model.validate(&format!("models[{}]", i), &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("{}", errors.join("\n"))
+        }
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = self.clear_color;
+        wgpu::Color { r, g, b, a }
+    }
+}
+
+impl CameraDesc {
+    pub fn to_camera(&self, aspect: f32) -> Camera {
+        Camera {
+            eye: self.eye.into(),
+            target: self.target.into(),
+            up: self.up.into(),
+            aspect,
+            projection: self.projection.to_projection(),
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        let eye = glam::Vec3::from(self.eye);
+        let target = glam::Vec3::from(self.target);
+        let up = glam::Vec3::from(self.up);
+        if !eye.is_finite() || !target.is_finite() {
+            errors.push(format!("{}: eye and target must be finite", path));
+        } else if eye.distance(target) <= f32::EPSILON {
+            errors.push(format!("{}: eye and target must not be the same point", path));
+        } else if up.cross(target - eye).length() <= f32::EPSILON {
+            errors.push(format!("{}.up: must not be zero or parallel to the view direction", path));
+        }
+        self.projection.validate(&format!("{}.projection", path), errors);
+    }
+}
+
+impl ProjectionDesc {
+    pub fn to_projection(&self) -> Projection {
+        match *self {
+            ProjectionDesc::Perspective { fovy, znear, zfar } => Projection::Perspective { fovy: fovy.to_radians(), znear, zfar },
+            ProjectionDesc::Orthographic { height, znear, zfar } => Projection::Orthographic { height, znear, zfar },
+            ProjectionDesc::InfinitePerspectiveReverseZ { fovy, znear } => {
+                Projection::InfinitePerspectiveReverseZ { fovy: fovy.to_radians(), znear }
+            }
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        let (fovy, height, znear, zfar) = match *self {
+            ProjectionDesc::Perspective { fovy, znear, zfar } => (Some(fovy), None, znear, Some(zfar)),
+            ProjectionDesc::Orthographic { height, znear, zfar } => (None, Some(height), znear, Some(zfar)),
+            ProjectionDesc::InfinitePerspectiveReverseZ { fovy, znear } => (Some(fovy), None, znear, None),
+        };
+        if let Some(fovy) = fovy {
+            if !(fovy > 0.0 && fovy < 180.0) {
+                errors.push(format!("{}.fovy: must be between 0 and 180 degrees, got {}", path, fovy));
+            }
+        }
+        if let Some(height) = height {
+            if !(height > 0.0 && height.is_finite()) {
+                errors.push(format!("{}.height: must be positive, got {}", path, height));
+            }
+        }
+        if !(znear > 0.0 && znear.is_finite()) {
+            errors.push(format!("{}.znear: must be positive, got {}", path, znear));
+        }
+        if let Some(zfar) = zfar {
+            if !(zfar > znear && zfar.is_finite()) {
+                errors.push(format!("{}.zfar: must be greater than znear ({}), got {}", path, znear, zfar));
+            }
+        }
+    }
+}
+
+impl LightDesc {
+    pub fn to_light(&self) -> Light {
+        match *self {
+            LightDesc::Directional { direction, color, intensity } => DirectionalLight {
+                direction: direction.into(),
+                color: color.into(),
+                intensity,
+            }
+            .into(),
+            LightDesc::Point { position, color, intensity, range } => PointLight {
+                position: position.into(),
+                color: color.into(),
+                intensity,
+                range,
+            }
+            .into(),
+            LightDesc::Spot { position, direction, color, intensity, range, inner_angle, outer_angle } => SpotLight {
+                position: position.into(),
+                direction: direction.into(),
+                color: color.into(),
+                intensity,
+                range,
+                inner_angle: inner_angle.to_radians(),
+                outer_angle: outer_angle.to_radians(),
+            }
+            .into(),
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        let (direction, color, intensity, range) = match *self {
+            LightDesc::Directional { direction, color, intensity } => (Some(direction), color, intensity, None),
+            LightDesc::Point { color, intensity, range, .. } => (None, color, intensity, Some(range)),
+            LightDesc::Spot { direction, color, intensity, range, inner_angle, outer_angle, .. } => {
+                if !(0.0..90.0).contains(&outer_angle) {
+                    errors.push(format!("{}.outer_angle: must be between 0 and 90 degrees, got {}", path, outer_angle));
+                }
+                if !(0.0..=outer_angle).contains(&inner_angle) {
+                    errors.push(format!("{}.inner_angle: must be between 0 and outer_angle ({}), got {}", path, outer_angle, inner_angle));
+                }
+                (Some(direction), color, intensity, Some(range))
+            }
+        };
+        if let Some(direction) = direction {
+            if glam::Vec3::from(direction).length() <= f32::EPSILON {
+                errors.push(format!("{}.direction: must not be zero", path));
+            }
+        }
+        if !color.iter().all(|c| *c >= 0.0 && c.is_finite()) {
+            errors.push(format!("{}.color: components must be non-negative", path));
+        }
+        if !(intensity >= 0.0 && intensity.is_finite()) {
+            errors.push(format!("{}.intensity: must be non-negative, got {}", path, intensity));
+        }
+        if let Some(range) = range {
+            if !(range > 0.0 && range.is_finite()) {
+                errors.push(format!("{}.range: must be positive, got {}", path, range));
+            }
+        }
+    }
+}
+
+impl ModelDesc {
+    // 所有摆放方式生成的实例，按条目顺序排列
+    pub fn instances(&self) -> Vec<Instance> {
+        self.instances.iter().flat_map(|placement| placement.instances()).collect()
+    }
+
+    // 模型加载之后才知道材质数量，所以材质覆盖单独检查
+    pub fn validate_materials(&self, path: &str, material_count: usize) -> anyhow::Result<()> {
+        let errors = self.instances.iter().enumerate()
+            .filter_map(|(i, placement)| {
+                let material = placement.material?;
+                (material >= material_count).then(|| {
+                    format!("{}.instances[{}].material: index {} is out of range ({} has {} materials)",
+                        path, i, material, self.file, material_count)
+                })
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("{}", errors.join("\n"))
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        let extension = Path::new(&self.file)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        if !matches!(extension.as_deref(), Some("obj" | "gltf" | "glb")) {
+            errors.push(format!("{}.file: unsupported model format {:?} (expected .obj, .gltf or .glb)", path, self.file));
+        }
+        for (i, placement) in self.instances.iter().enumerate() {
+            placement.validate(&format!("{}.instances[{}]", path, i), errors);
+        }
+    }
+}
+
+impl PlacementDesc {
+    pub fn instances(&self) -> Vec<Instance> {
+        let scale = glam::Vec3::from(self.scale);
+        let tint = glam::Vec4::from(self.tint);
+        self.layout.transforms()
+            .into_iter()
+            .map(|(position, yaw)| Instance {
+                position,
+                rotation: self.rotation.at(position) * yaw,
+                scale,
+                tint,
+                material: self.material,
+            })
+            .collect()
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        self.layout.validate(&format!("{}.layout", path), errors);
+        let angles = match self.rotation {
+            RotationDesc::Euler(angles) => angles.to_vec(),
+            RotationDesc::Radial(angle) => vec![angle],
+        };
+        if !angles.iter().all(|a| a.is_finite()) {
+            errors.push(format!("{}.rotation: angles must be finite", path));
+        }
+        // 缩放为 0 时法线矩阵不可逆
+        if !self.scale.iter().all(|s| *s != 0.0 && s.is_finite()) {
+            errors.push(format!("{}.scale: components must be non-zero, got {:?}", path, self.scale));
+        }
+        if !self.tint.iter().all(|c| c.is_finite()) {
+            errors.push(format!("{}.tint: components must be finite", path));
+        }
+    }
+}
+
+impl LayoutDesc {
+    // 每个实例的位置，以及摆放方式自带的旋转
+    fn transforms(&self) -> Vec<(glam::Vec3, glam::Quat)> {
+        match *self {
+            LayoutDesc::Single { position } => vec![(position.into(), glam::Quat::IDENTITY)],
+            LayoutDesc::Grid { count: [columns, rows], spacing, center } => {
+                let center = glam::Vec3::from(center);
+                (0..rows).flat_map(|z| {
+                    (0..columns).map(move |x| {
+                        let offset = glam::Vec3::new(
+                            x as f32 - (columns - 1) as f32 / 2.0,
+                            0.0,
+                            z as f32 - (rows - 1) as f32 / 2.0,
+                        );
+                        (center + offset * spacing, glam::Quat::IDENTITY)
+                    })
+                })
+                .collect()
+            }
+            LayoutDesc::Scatter { count, min, max, seed, random_yaw } => {
+                let (min, max) = (glam::Vec3::from(min), glam::Vec3::from(max));
+                let mut rng = SplitMix64(seed);
+                (0..count).map(|_| {
+                    let t = glam::Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32());
+                    let yaw = if random_yaw {
+                        glam::Quat::from_rotation_y(rng.next_f32() * std::f32::consts::TAU)
+                    } else {
+                        glam::Quat::IDENTITY
+                    };
+                    (min + (max - min) * t, yaw)
+                })
+                .collect()
+            }
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<String>) {
+        match *self {
+            LayoutDesc::Single { position } => {
+                if !position.iter().all(|p| p.is_finite()) {
+                    errors.push(format!("{}.position: components must be finite", path));
+                }
+            }
+            LayoutDesc::Grid { count, spacing, center } => {
+                if count.contains(&0) {
+                    errors.push(format!("{}.count: must be at least 1 in each direction, got {:?}", path, count));
+                }
+                if !(spacing > 0.0 && spacing.is_finite()) {
+                    errors.push(format!("{}.spacing: must be positive, got {}", path, spacing));
+                }
+                if !center.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{}.center: components must be finite", path));
+                }
+            }
+            LayoutDesc::Scatter { count, min, max, .. } => {
+                if count == 0 {
+                    errors.push(format!("{}.count: must be at least 1", path));
+                }
+                if !min.iter().chain(&max).all(|c| c.is_finite()) {
+                    errors.push(format!("{}: min and max must be finite", path));
+                } else if min.iter().zip(&max).any(|(a, b)| a > b) {
+                    errors.push(format!("{}: min {:?} must not be greater than max {:?}", path, min, max));
+                }
+            }
+        }
+    }
+}
+
+impl RotationDesc {
+    fn at(&self, position: glam::Vec3) -> glam::Quat {
+        match *self {
+            RotationDesc::Euler([x, y, z]) => {
+                glam::Quat::from_euler(glam::EulerRot::XYZ, x.to_radians(), y.to_radians(), z.to_radians())
+            }
+            RotationDesc::Radial(angle) => match position.try_normalize() {
+                Some(axis) => glam::Quat::from_axis_angle(axis, angle.to_radians()),
+                None => glam::Quat::IDENTITY,
+            },
+        }
+    }
+}
+
+// 散布用的伪随机数，不依赖外部库，保证同一个 seed 在任何平台上结果一致
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
@@ -1,28 +1,30 @@
-// 顶点着色器
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4f,
-    @location(6) model_matrix_1: vec4f,
-    @location(7) model_matrix_2: vec4f,
-    @location(8) model_matrix_3: vec4f
-}
+#include "include/camera.wgsl"
+#include "include/instance.wgsl"
+#include "include/lights.wgsl"
+#include "include/vertex.wgsl"
 
-struct CameraUniform {
-    view_proj: mat4x4f,
-}
+// 顶点着色器
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+@group(2) @binding(0)
+var<storage, read> lights: array<Light>;
+@group(2) @binding(1)
+var<uniform> light_config: LightConfig;
+@group(2) @binding(2)
+var<storage, read> clusters: array<ClusterLights>;
 
-struct VertexInput {
-    @location(0) position: vec3f,
-    // @location(1) color: vec3f
-    @location(1) tex_coords: vec2f,
-}
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     // @location(0) color: vec3f
-    @location(0) tex_coords: vec2f
+    @location(0) tex_coords: vec2f,
+    // 世界空间下的切线空间基向量
+    @location(1) world_normal: vec3f,
+    @location(2) world_tangent: vec3f,
+    @location(3) world_bitangent: vec3f,
+    @location(4) world_position: vec3f,
+    @location(5) tint: vec4f,
 };
 
 // @vertex 
@@ -36,18 +38,21 @@ struct VertexOutput {
 
 @vertex
 fn vs_main(model:VertexInput,instance:InstanceInput) -> VertexOutput {
-    let model_matrix = mat4x4f(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3
-    );
+    let model_matrix = instance_model_matrix(instance);
 
     var out: VertexOutput;
     // out.color = model.color;
     out.tex_coords = model.tex_coords;
+    let normal_matrix = instance_normal_matrix(instance);
+    out.world_normal = normal_matrix * model.normal;
+    let tangent_matrix = instance_tangent_matrix(instance);
+    out.world_tangent = tangent_matrix * model.tangent;
+    out.world_bitangent = tangent_matrix * model.bitangent;
     // out.clip_position = vec4f(model.position,1.0);
-    out.clip_position = camera.view_proj *model_matrix * vec4f(model.position,1.0);
+    let world_position = model_matrix * vec4f(model.position,1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.tint = instance.tint;
     return out;
 }
 
@@ -56,10 +61,175 @@ fn vs_main(model:VertexInput,instance:InstanceInput) -> VertexOutput {
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+
+struct Material {
+    ambient: vec3f,
+    diffuse: vec3f,
+    specular: vec3f,
+    shininess: f32,
+    uv_scale: vec2f,
+    uv_offset: vec2f,
+}
+@group(0) @binding(4)
+var<uniform> material: Material;
+
+#ifdef SHADOWS
+// 平行光的级联阴影贴图
+const MAX_CASCADES: u32 = 4u;
+
+struct Shadow {
+    light_view_proj: array<mat4x4f, MAX_CASCADES>,
+    splits: vec4f,
+    cascade_count: u32,
+    light_index: u32,
+    texel_size: f32,
+    normal_bias: f32,
+}
+
+@group(3) @binding(0)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(1)
+var s_shadow: sampler_comparison;
+@group(3) @binding(2)
+var<uniform> shadow: Shadow;
+
+// 返回 0（完全在阴影中）到 1（完全被照亮）
+fn shadow_factor(surface_position: vec3f, surface_normal: vec3f) -> f32 {
+    let world_position = surface_position + surface_normal * shadow.normal_bias;
+    // 根据观察空间深度选择级联
+    let view_depth = -(light_config.view * vec4f(world_position, 1.0)).z;
+    var cascade = 0u;
+    for (var i = 0u; i < shadow.cascade_count - 1u; i++) {
+        if view_depth > shadow.splits[i] {
+            cascade = i + 1u;
+        }
+    }
+    if view_depth > shadow.splits[shadow.cascade_count - 1u] {
+        return 1.0;
+    }
+
+    let light_space = shadow.light_view_proj[cascade] * vec4f(world_position, 1.0);
+    let ndc = light_space.xyz / light_space.w;
+    // NDC 的 y 轴朝上，纹理坐标的 v 轴朝下
+    let uv = ndc.xy * vec2f(0.5, -0.5) + 0.5;
+    if any(uv < vec2f(0.0)) || any(uv > vec2f(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    // 3x3 PCF，每次比较采样本身还会做 2x2 的双线性过滤
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2f(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, cascade, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+#endif
+
+// 观察空间深度所在的切片，与 light_cull.wgsl 中的 slice_depth 互逆
+fn cluster_slice(depth: f32) -> u32 {
+    var t: f32;
+    if light_config.logarithmic != 0u {
+        t = log(max(depth, light_config.depth_near) / light_config.depth_near) / log(light_config.depth_far / light_config.depth_near);
+    } else {
+        t = (depth - light_config.depth_near) / (light_config.depth_far - light_config.depth_near);
+    }
+    return u32(clamp(t * f32(CLUSTER_SLICES), 0.0, f32(CLUSTER_SLICES - 1u)));
+}
+
+// 环境光强度
+const AMBIENT_STRENGTH: f32 = 0.1;
+
+struct Shading {
+    diffuse: vec3f,
+    specular: vec3f,
+}
+
+// 单个光源的 Blinn-Phong 光照，点光源和聚光灯带距离衰减，聚光灯带锥角衰减
+fn shade_light(light: Light, position: vec3f, normal: vec3f, view_dir: vec3f) -> Shading {
+    var light_dir: vec3f;
+    var attenuation = 1.0;
+    if light.kind == LIGHT_DIRECTIONAL {
+        light_dir = -light.direction;
+    } else {
+        let to_light = light.position - position;
+        let distance = length(to_light);
+        light_dir = to_light / distance;
+        // 平方反比衰减，并在 range 处平滑地降到 0
+        let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+        attenuation = falloff * falloff / (distance * distance + 1.0);
+        if light.kind == LIGHT_SPOT {
+            let cos_angle = dot(-light_dir, light.direction);
+            attenuation *= smoothstep(light.outer_cos, light.inner_cos, cos_angle);
+        }
+    }
+
+    // Blinn-Phong：高光使用光线方向与视线方向的半程向量
+    let half_dir = normalize(view_dir + light_dir);
+    let light_color = light.color * light.intensity * attenuation;
+
+    var out: Shading;
+    out.diffuse = light_color * max(dot(normal, light_dir), 0.0) * material.diffuse;
+    let specular_strength = pow(max(dot(normal, half_dir), 0.0), material.shininess);
+    out.specular = light_color * specular_strength * material.specular;
+    return out;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
     // return vec4f(0.3, 0.2, 0.1, 1.0);
     // return vec4f(in.color,1.0);
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    let tex_coords = in.tex_coords * material.uv_scale + material.uv_offset;
+    let object_color = textureSample(t_diffuse, s_diffuse, tex_coords) * in.tint;
+#ifdef NORMAL_MAP
+    // 法线贴图的值在 [0, 1] 之间，需要映射回 [-1, 1]
+    let object_normal = textureSample(t_normal, s_normal, tex_coords).xyz * 2.0 - 1.0;
+
+    // 从切线空间变换到世界空间
+    let tangent_matrix = mat3x3f(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal)
+    );
+    let normal = normalize(tangent_matrix * object_normal);
+#else
+    let normal = normalize(in.world_normal);
+#endif
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+
+    var diffuse_color = vec3f(0.0);
+    var specular_color = vec3f(0.0);
+    // 平行光照亮所有像素，投射阴影的是第一个平行光
+    for (var i = 0u; i < light_config.directional_count; i++) {
+        let shading = shade_light(lights[i], in.world_position, normal, view_dir);
+        var visibility = 1.0;
+#ifdef SHADOWS
+        if i == shadow.light_index {
+            visibility = shadow_factor(in.world_position, normalize(in.world_normal));
+        }
+#endif
+        diffuse_color += shading.diffuse * visibility;
+        specular_color += shading.specular * visibility;
+    }
+
+    // 只遍历当前像素所在簇中可见的点光源和聚光灯
+    let tile = min(vec2u(in.clip_position.xy) / TILE_SIZE, light_config.tile_count - 1u);
+    let view_depth = -(light_config.view * vec4f(in.world_position, 1.0)).z;
+    let cluster_index = (cluster_slice(view_depth) * light_config.tile_count.y + tile.y) * light_config.tile_count.x + tile.x;
+    let light_count = min(clusters[cluster_index].count, MAX_LIGHTS_PER_CLUSTER);
+    for (var i = 0u; i < light_count; i++) {
+        let shading = shade_light(lights[clusters[cluster_index].indices[i]], in.world_position, normal, view_dir);
+        diffuse_color += shading.diffuse;
+        specular_color += shading.specular;
+    }
+    let ambient_color = AMBIENT_STRENGTH * material.ambient;
+
+    let result = (ambient_color + diffuse_color) * object_color.rgb + specular_color;
+    return vec4f(result, object_color.a);
+}
@@ -0,0 +1,391 @@
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+
+use crate::{camera::Camera, hot_reload::catch_validation, instance::InstanceRaw, lights::DirectionalLight, model::{self, DrawGeometry, Vertex}, texture};
+
+// 最多支持的级联数量，必须与 shader.wgsl 中的 ShadowUniform 保持一致
+pub const MAX_CASCADES: usize = 4;
+
+// 级联之外的投射者也可能挡住视锥体内的物体，沿光线方向额外保留的距离
+const CASTER_MARGIN: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowConfig {
+    // 每一级阴影贴图的分辨率
+    pub resolution: u32,
+    // 级联数量，1 到 MAX_CASCADES
+    pub cascade_count: u32,
+    // 光栅化阶段的深度偏移，用于消除阴影失真（shadow acne）
+    pub depth_bias: i32,
+    pub slope_bias: f32,
+    // 采样前沿表面法线偏移的距离（世界空间），进一步减少掠射角下的失真
+    pub normal_bias: f32,
+    // 阴影覆盖的最远距离，超出的部分不产生阴影
+    pub max_distance: f32,
+    // 对数划分与均匀划分的混合系数，越大近处的级联越精细
+    pub split_lambda: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascade_count: 3,
+            depth_bias: 2,
+            slope_bias: 2.0,
+            normal_bias: 0.05,
+            max_distance: 60.0,
+            split_lambda: 0.75,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    // 每一级级联在观察空间中的最远深度
+    splits: [f32; 4],
+    cascade_count: u32,
+    // 投射阴影的光源在光源列表中的索引，没有时为 u32::MAX
+    light_index: u32,
+    texel_size: f32,
+    normal_bias: f32,
+}
+
+// 平行光的级联阴影贴图（CSM）
+pub struct ShadowMap {
+    config: ShadowConfig,
+    texture: texture::Texture,
+    cascade_views: Vec<wgpu::TextureView>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    cascade_bind_group_layout: wgpu::BindGroupLayout,
+    shader: wgpu::ShaderModule,
+    pipeline: wgpu::RenderPipeline,
+    uniform: ShadowUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMap {
+    pub const ENTRY_POINTS: &'static [&'static str] = &["vs_main"];
+
+    pub fn new(device: &wgpu::Device, shader: wgpu::ShaderModule, config: ShadowConfig) -> Self {
+        let cascade_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow_cascade_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        // 主渲染通道中读取阴影贴图
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform = ShadowUniform {
+            light_view_proj: [glam::Mat4::IDENTITY.to_cols_array_2d(); MAX_CASCADES],
+            splits: [0.0; 4],
+            cascade_count: 0,
+            light_index: u32::MAX,
+            texel_size: 0.0,
+            normal_bias: 0.0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("shadow_uniform"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let config = Self::clamp_config(config);
+        let texture = texture::Texture::create_shadow_texture(device, config.resolution, config.cascade_count, "shadow_map");
+        let (cascade_views, cascade_buffers, cascade_bind_groups) = Self::create_cascades(device, &config, &texture, &cascade_bind_group_layout);
+        let pipeline = Self::create_pipeline(device, &shader, &config, &cascade_bind_group_layout);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &texture, &uniform_buffer);
+
+        Self {
+            config,
+            texture,
+            cascade_views,
+            cascade_buffers,
+            cascade_bind_groups,
+            cascade_bind_group_layout,
+            shader,
+            pipeline,
+            uniform,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    // 修改分辨率、级联数量或深度偏移，需要重建阴影贴图和管线，
+    // 主通道使用的绑定组布局保持不变
+    pub fn set_config(&mut self, device: &wgpu::Device, config: ShadowConfig) {
+        self.config = Self::clamp_config(config);
+        self.texture = texture::Texture::create_shadow_texture(device, self.config.resolution, self.config.cascade_count, "shadow_map");
+        (self.cascade_views, self.cascade_buffers, self.cascade_bind_groups) =
+            Self::create_cascades(device, &self.config, &self.texture, &self.cascade_bind_group_layout);
+        self.pipeline = Self::create_pipeline(device, &self.shader, &self.config, &self.cascade_bind_group_layout);
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.texture, &self.uniform_buffer);
+    }
+
+    // 替换着色器并重建管线，出错时保留原来的着色器和管线
+    pub fn set_shader(&mut self, device: &wgpu::Device, shader: wgpu::ShaderModule) -> anyhow::Result<()> {
+        self.pipeline = catch_validation(device, || Self::create_pipeline(device, &shader, &self.config, &self.cascade_bind_group_layout))?;
+        self.shader = shader;
+        Ok(())
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    // 按摄像机视锥体划分级联，并为每一级计算覆盖它的光源正交投影
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, light: Option<(usize, DirectionalLight)>) {
+        let Some((light_index, light)) = light else {
+            self.uniform.light_index = u32::MAX;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+            return;
+        };
+
+        let (znear, zfar) = camera.depth_range();
+        let far = zfar.min(self.config.max_distance);
+        let count = self.config.cascade_count as usize;
+        let direction = light.direction.normalize();
+
+        let mut near = znear;
+        for i in 0..count {
+            // 对数划分和均匀划分的混合（Practical Split Scheme）
+            let p = (i + 1) as f32 / count as f32;
+            let log_split = znear * (far / znear).powf(p);
+            let uniform_split = znear + (far - znear) * p;
+            let split = self.config.split_lambda * log_split + (1.0 - self.config.split_lambda) * uniform_split;
+
+            let view_proj = self.cascade_matrix(camera.frustum_corners(near, split), direction);
+            queue.write_buffer(&self.cascade_buffers[i], 0, bytemuck::cast_slice(&view_proj.to_cols_array_2d()));
+            self.uniform.light_view_proj[i] = view_proj.to_cols_array_2d();
+            self.uniform.splits[i] = split;
+            near = split;
+        }
+
+        self.uniform.cascade_count = count as u32;
+        self.uniform.light_index = light_index as u32;
+        self.uniform.texel_size = 1.0 / self.config.resolution as f32;
+        self.uniform.normal_bias = self.config.normal_bias;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    // 从光源方向渲染每一级级联的深度
+    // casters 中每一项是一个模型、它的实例缓冲区和要绘制的实例范围
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, casters: &[(&model::Model, &wgpu::Buffer, Range<u32>)]) {
+        if self.uniform.light_index == u32::MAX {
+            return;
+        }
+
+        for (view, bind_group) in self.cascade_views.iter().zip(&self.cascade_bind_groups) {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+            shadow_pass.set_pipeline(&self.pipeline);
+            shadow_pass.set_bind_group(0, bind_group, &[]);
+            for (model, instance_buffer, instances) in casters {
+                shadow_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                shadow_pass.draw_model_geometry_instanced(model, instances.clone());
+            }
+        }
+    }
+
+    // 用包围球包住这一级视锥体，使阴影贴图的大小不随摄像机旋转而变化，
+    // 再把投影原点对齐到纹素上，避免摄像机移动时阴影边缘闪烁
+    fn cascade_matrix(&self, corners: [glam::Vec3; 8], direction: glam::Vec3) -> glam::Mat4 {
+        let center = corners.iter().copied().sum::<glam::Vec3>() / corners.len() as f32;
+        let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let up = if direction.y.abs() > 0.99 { glam::Vec3::Z } else { glam::Vec3::Y };
+        let eye = center - direction * (radius + CASTER_MARGIN);
+        let view = glam::Mat4::look_at_rh(eye, center, up);
+        let mut proj = glam::Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 2.0 * radius + CASTER_MARGIN);
+
+        let half_resolution = self.config.resolution as f32 * 0.5;
+        let origin = (proj * view).project_point3(glam::Vec3::ZERO) * half_resolution;
+        let offset = (origin.round() - origin) / half_resolution;
+        proj.w_axis.x += offset.x;
+        proj.w_axis.y += offset.y;
+
+        proj * view
+    }
+
+    fn clamp_config(config: ShadowConfig) -> ShadowConfig {
+        ShadowConfig {
+            resolution: config.resolution.max(1),
+            cascade_count: config.cascade_count.clamp(1, MAX_CASCADES as u32),
+            ..config
+        }
+    }
+
+    fn create_cascades(
+        device: &wgpu::Device,
+        config: &ShadowConfig,
+        texture: &texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> (Vec<wgpu::TextureView>, Vec<wgpu::Buffer>, Vec<wgpu::BindGroup>) {
+        let mut views = Vec::new();
+        let mut buffers = Vec::new();
+        let mut bind_groups = Vec::new();
+        for i in 0..config.cascade_count {
+            // 渲染时每一级只写入数组中的一层
+            views.push(texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("shadow_cascade_view"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: i,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }));
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("shadow_cascade_uniform"),
+                contents: bytemuck::cast_slice(&glam::Mat4::IDENTITY.to_cols_array_2d()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            bind_groups.push(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("shadow_cascade_bind_group"),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            }));
+            buffers.push(buffer);
+        }
+        (views, buffers, bind_groups)
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        config: &ShadowConfig,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                compilation_options: Default::default(),
+                entry_point: "vs_main",
+                buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+            },
+            // 只写入深度，不需要片元着色器
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: config.depth_bias,
+                    slope_scale: config.slope_bias,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &texture::Texture,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
@@ -0,0 +1,12 @@
+// 阴影贴图：从光源方向只渲染深度
+#include "include/vertex.wgsl"
+#include "include/instance.wgsl"
+
+// 当前级联的光源观察投影矩阵
+@group(0) @binding(0)
+var<uniform> light_view_proj: mat4x4f;
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4f {
+    return light_view_proj * instance_model_matrix(instance) * vec4f(model.position, 1.0);
+}
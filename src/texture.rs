@@ -1,17 +1,160 @@
+use std::{collections::HashMap, sync::Mutex};
+
 use anyhow::Result;
 use image::GenericImageView;
 use wgpu::naga::back::msl::sampler;
 
+use crate::preprocessor::{ShaderDefines, ShaderLibrary};
+
+const MIPMAP_SHADER_FILE: &str = "mipmap.wgsl";
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
+// 创建纹理和采样器的选项。默认值适合颜色贴图：sRGB、完整 mip 链、三线性 + 16x 各向异性过滤
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // 只有三种过滤方式都是 Linear 时各向异性过滤才会生效
+    pub anisotropy: u16,
+    // 颜色贴图使用 sRGB，法线、粗糙度等数据贴图必须是线性的
+    pub srgb: bool,
+    pub mipmaps: bool,
+    // 在 TEXTURE_BINDING | COPY_DST 之外额外需要的用途
+    pub usage: wgpu::TextureUsages,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 16,
+            srgb: true,
+            mipmaps: true,
+            usage: wgpu::TextureUsages::empty(),
+        }
+    }
+}
+
+impl TextureOptions {
+    // 颜色贴图（漫反射、基础色）
+    pub fn color() -> Self {
+        Self::default()
+    }
+
+    // 数据贴图（法线、粗糙度等），按线性值存储
+    pub fn data() -> Self {
+        Self::default().srgb(false)
+    }
+
+    pub fn address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    pub fn filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.mag_filter = mode;
+        self.min_filter = mode;
+        self.mipmap_filter = mode;
+        self
+    }
+
+    pub fn mag_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.mag_filter = mode;
+        self
+    }
+
+    pub fn min_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.min_filter = mode;
+        self
+    }
+
+    pub fn mipmap_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.mipmap_filter = mode;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        if self.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|&f| f == wgpu::FilterMode::Linear);
+        device.create_sampler(&wgpu::SamplerDescriptor{
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            // wgpu 要求开启各向异性时所有过滤方式都是 Linear
+            anisotropy_clamp: if linear { self.anisotropy.max(1) } else { 1 },
+            ..Default::default()
+        })
+    }
+}
+
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration,label: &str)-> Self {
+    // 深度测试的比较函数：普通模式近处深度小，reverse-Z 近处深度大
+    pub fn depth_compare(reverse_z: bool) -> wgpu::CompareFunction {
+        if reverse_z {
+            wgpu::CompareFunction::Greater
+        } else {
+            wgpu::CompareFunction::Less
+        }
+    }
+
+    // 每帧清除深度缓冲用的值，即最远处的深度
+    pub fn depth_clear_value(reverse_z: bool) -> f32 {
+        if reverse_z { 0.0 } else { 1.0 }
+    }
+
+    // 采样数必须与颜色附件一致
+    pub fn create_depth_texture(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration,reverse_z: bool,sample_count: u32,label: &str)-> Self {
         //深度纹理的宽高需要与展示平面一致
         let size = wgpu::Extent3d{
             width: config.width,
@@ -19,14 +162,21 @@ impl Texture {
             depth_or_array_layers: 1
         };
 
+        // 多重采样的深度纹理只作为附件。GL 后端要求帧缓冲区的附件要么都是纹理要么都是渲染缓冲，
+        // 多重采样颜色附件没有 TEXTURE_BINDING，深度纹理也不能有，否则帧缓冲区不完整，什么都画不出来
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        };
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage,
             view_formats: &[]
         };
 
@@ -39,7 +189,11 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
+            compare: Some(if reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::LessEqual
+            }),
             lod_min_clamp: 0.0,
             lod_max_clamp: 200.0,
             ..Default::default()
@@ -48,12 +202,108 @@ impl Texture {
         Self { texture, view, sampler }
     }
 
-    pub fn from_bytes(device: &wgpu::Device,queue:&wgpu::Queue,bytes:&[u8],label:&str) -> Result<Self> {
+    // 阴影贴图：每一级级联占用数组中的一层，使用比较采样器做硬件 PCF
+    pub fn create_shadow_texture(device: &wgpu::Device,resolution: u32,layers: u32,label: &str) -> Self {
+        let size = wgpu::Extent3d{
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: layers
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor{
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
+    }
+
+    // MSAA 的多重采样颜色附件，每帧结束时解析（resolve）到 surface 或离屏纹理上
+    pub fn create_msaa_target(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration,sample_count: u32,label: &str) -> Self {
+        let size = wgpu::Extent3d{
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    pub fn create_render_target(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration,label: &str) -> Self {
+        // 离屏渲染目标，COPY_SRC 用于把渲染结果复制回 CPU
+        let size = wgpu::Extent3d{
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    pub fn from_bytes(device: &wgpu::Device,queue:&wgpu::Queue,bytes:&[u8],label:&str,options: &TextureOptions,mipmaps: &MipmapGenerator) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device,queue,&img,Some(label))
+        Self::from_image(device,queue,&img,Some(label),options,Some(mipmaps))
     }
 
-    pub fn from_image(device: &wgpu::Device,queue:&wgpu::Queue,img:&image::DynamicImage,label:Option<&str>) -> Result<Self> {
+    // 1x1 的纯色纹理，用作材质缺少贴图时的默认值
+    pub fn from_color(device: &wgpu::Device,queue:&wgpu::Queue,color:[u8; 4],label:&str,options: &TextureOptions) -> Result<Self> {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image(device,queue,&img,Some(label),options,None)
+    }
+
+    // 完整 mip 链的级数：一直缩小到 1x1
+    pub fn mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+
+    // 没有 mipmaps 时在 CPU 上生成 mipmap
+    pub fn from_image(device: &wgpu::Device,queue:&wgpu::Queue,img:&image::DynamicImage,label:Option<&str>,options: &TextureOptions,mipmaps: Option<&MipmapGenerator>) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
         let size = wgpu::Extent3d {
@@ -61,15 +311,32 @@ impl Texture {
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
+        let format = options.format();
+        let mip_level_count = if options.mipmaps {
+            Self::mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
+        // 能作为渲染目标的格式在 GPU 上生成 mipmap，否则退回 CPU
+        let gpu_mipmaps = mip_level_count > 1
+            && mipmaps.is_some()
+            && format
+                .guaranteed_format_features(device.features())
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
 
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | options.usage;
+        if gpu_mipmaps {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
         let texture = device.create_texture(&wgpu::TextureDescriptor{
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format,
+            usage,
             view_formats: &[],
         });
 
@@ -89,17 +356,224 @@ impl Texture {
             size,
         );
 
+        if let (true, Some(mipmaps)) = (gpu_mipmaps, mipmaps) {
+            mipmaps.generate(device, queue, &texture);
+        } else if mip_level_count > 1 {
+            let mut level = rgba;
+            for mip_level in 1..mip_level_count {
+                level = downsample(&level, format.is_srgb());
+                write_mip_level(queue, &texture, mip_level, &level);
+            }
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor{
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
+        let sampler = options.create_sampler(device, label);
+
+        Ok(Self { texture,view, sampler })
+    }
+}
+
+fn write_mip_level(queue: &wgpu::Queue, texture: &wgpu::Texture, mip_level: u32, img: &image::RgbaImage) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * img.width()),
+            rows_per_image: Some(img.height()),
+        },
+        wgpu::Extent3d {
+            width: img.width(),
+            height: img.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+// 在 GPU 上逐级生成 mipmap：每一级都是一次渲染通道，以上一级作为输入。
+// 着色器和采样器只创建一次，管线按纹理格式缓存，加载纹理时共用。
+// 纹理只在加载时生成一次 mipmap，所以总是使用编译进程序的着色器，不参与热重载
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    pipelines: Mutex<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = ShaderLibrary::embedded().compile(device, MIPMAP_SHADER_FILE, &ShaderDefines::new(), &["vs_main", "fs_main"])?.module;
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap Sampler"),
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
+        Ok(Self { shader, sampler, pipelines: Mutex::new(HashMap::new()) })
+    }
 
-        Ok(Self { texture,view, sampler })
+    // 已经创建的管线数量，每种纹理格式一个
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mipmap Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        let pipeline = pipelines
+            .entry(texture.format())
+            .or_insert_with(|| self.create_pipeline(device, texture.format()));
+        let bind_group_layout = pipeline.get_bind_group_layout(0);
+
+        let views = (0..texture.mip_level_count())
+            .map(|mip| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mip View"),
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+            .collect::<Vec<_>>();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+        for target in 1..views.len() {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &views[target],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+// CPU 上把图像缩小一半：2x2 盒式滤波，sRGB 颜色先转到线性空间再平均，alpha 始终是线性的
+pub fn downsample(img: &image::RgbaImage, srgb: bool) -> image::RgbaImage {
+    let width = (img.width() / 2).max(1);
+    let height = (img.height() / 2).max(1);
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let sx = (x * 2 + dx).min(img.width() - 1);
+            let sy = (y * 2 + dy).min(img.height() - 1);
+            let pixel = img.get_pixel(sx, sy);
+            for c in 0..4 {
+                sum[c] += if srgb && c < 3 {
+                    srgb_to_linear(pixel[c])
+                } else {
+                    pixel[c] as f32 / 255.0
+                };
+            }
+        }
+        image::Rgba(std::array::from_fn(|c| {
+            let average = sum[c] / 4.0;
+            if srgb && c < 3 {
+                linear_to_srgb(average)
+            } else {
+                (average * 255.0).round() as u8
+            }
+        }))
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_level_count_reaches_one_pixel() {
+        assert_eq!(Texture::mip_level_count(1, 1), 1);
+        assert_eq!(Texture::mip_level_count(256, 256), 9);
+        assert_eq!(Texture::mip_level_count(300, 20), 9);
+    }
+
+    #[test]
+    fn downsample_averages_in_linear_space() {
+        let mut img = image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 0, 0]));
+        img.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+        img.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+
+        // 线性空间的 0.5 编码成 sRGB 约为 188，而不是 128；alpha 不做转换
+        assert_eq!(downsample(&img, true).get_pixel(0, 0), &image::Rgba([188, 188, 188, 128]));
+        assert_eq!(downsample(&img, false).get_pixel(0, 0), &image::Rgba([128, 128, 128, 128]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn downsample_handles_odd_sizes() {
+        let img = image::RgbaImage::from_pixel(3, 1, image::Rgba([10, 20, 30, 40]));
+        let level = downsample(&img, true);
+        assert_eq!(level.dimensions(), (1, 1));
+        assert_eq!(level.get_pixel(0, 0), &image::Rgba([10, 20, 30, 40]));
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use wgpu_test::bounds::Aabb;
+use wgpu_test::camera::{Camera, CameraControl, FpsCameraController, OrbitCameraController, Projection};
+use winit::{event::{ElementState, MouseScrollDelta}, keyboard::KeyCode};
+
+fn camera() -> Camera {
+    let mut camera = Camera::new(1.0);
+    camera.eye = glam::Vec3::ZERO;
+    camera.target = glam::Vec3::NEG_Z;
+    camera
+}
+
+#[test]
+fn movement_is_frame_rate_independent() {
+    let mut controller = FpsCameraController::new(2.0, 0.01);
+    controller.process_keyboard(KeyCode::KeyW, ElementState::Pressed);
+
+    // 一次 1 秒和十次 0.1 秒应该移动同样的距离
+    let mut once = camera();
+    controller.update_camera(&mut once, Duration::from_secs(1));
+    let mut stepped = camera();
+    for _ in 0..10 {
+        controller.update_camera(&mut stepped, Duration::from_millis(100));
+    }
+
+    assert!(once.eye.abs_diff_eq(glam::Vec3::new(0.0, 0.0, -2.0), 1e-5));
+    assert!(stepped.eye.abs_diff_eq(once.eye, 1e-5));
+    assert!((once.target - once.eye).normalize().abs_diff_eq(glam::Vec3::NEG_Z, 1e-5));
+}
+
+#[test]
+fn vertical_movement() {
+    let mut controller = FpsCameraController::new(1.0, 0.01);
+    let mut camera = camera();
+    controller.process_keyboard(KeyCode::Space, ElementState::Pressed);
+    controller.update_camera(&mut camera, Duration::from_secs(3));
+    assert!(camera.eye.abs_diff_eq(glam::Vec3::new(0.0, 3.0, 0.0), 1e-5));
+
+    controller.process_keyboard(KeyCode::Space, ElementState::Released);
+    controller.process_keyboard(KeyCode::ShiftLeft, ElementState::Pressed);
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert!(camera.eye.abs_diff_eq(glam::Vec3::new(0.0, 2.0, 0.0), 1e-5));
+}
+
+#[test]
+fn mouse_look_requires_captured_cursor() {
+    let mut controller = FpsCameraController::new(1.0, 0.01);
+    let mut camera = camera();
+    controller.process_mouse_motion(100.0, 0.0);
+    controller.update_camera(&mut camera, Duration::ZERO);
+    assert!(camera.target.abs_diff_eq(glam::Vec3::NEG_Z, 1e-5));
+
+    controller.process_keyboard(KeyCode::Tab, ElementState::Pressed);
+    assert!(controller.is_mouse_captured());
+    // 向右移动 π/2 / 0.01 个像素，视线从 -Z 转向 +X
+    controller.process_mouse_motion(std::f64::consts::FRAC_PI_2 / 0.01, 0.0);
+    controller.update_camera(&mut camera, Duration::ZERO);
+    assert!(camera.target.abs_diff_eq(glam::Vec3::X, 1e-4));
+}
+
+#[test]
+fn pitch_is_clamped() {
+    let mut controller = FpsCameraController::new(1.0, 0.01);
+    controller.set_mouse_captured(true);
+    let mut camera = camera();
+    // 鼠标向上移动很远，视线不能越过正上方
+    controller.process_mouse_motion(0.0, -10_000.0);
+    controller.update_camera(&mut camera, Duration::ZERO);
+    let direction = (camera.target - camera.eye).normalize();
+    assert!(direction.y > 0.999);
+    assert!(direction.z < 0.0);
+    assert!(camera.build_view_matrix().is_finite());
+}
+
+#[test]
+fn scroll_adjusts_speed() {
+    let mut controller = FpsCameraController::new(1.0, 0.01);
+    controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 2.0));
+    assert!((controller.speed() - 1.44).abs() < 1e-5);
+    controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, -2.0));
+    assert!((controller.speed() - 1.0).abs() < 1e-5);
+}
+
+fn orbit_camera() -> Camera {
+    let mut camera = Camera::new(1.0);
+    camera.eye = glam::Vec3::new(0.0, 0.0, 5.0);
+    camera.target = glam::Vec3::ZERO;
+    camera
+}
+
+#[test]
+fn orbit_rotates_around_target() {
+    let mut controller = OrbitCameraController::new(0.01);
+    let mut camera = orbit_camera();
+    controller.set_rotating(true);
+    // 转动 π/2：从 +Z 转到 -X
+    controller.process_mouse_motion(std::f64::consts::FRAC_PI_2 / 0.01, 0.0);
+    CameraControl::update_camera(&mut controller, &mut camera, Duration::ZERO);
+
+    assert_eq!(camera.target, glam::Vec3::ZERO);
+    assert!(camera.eye.abs_diff_eq(glam::Vec3::new(-5.0, 0.0, 0.0), 1e-4));
+
+    // 反复转动不会漂移
+    for _ in 0..100 {
+        controller.process_mouse_motion(37.0, 0.0);
+        controller.update_camera(&mut camera);
+    }
+    assert!((camera.eye.length() - 5.0).abs() < 1e-3);
+}
+
+#[test]
+fn orbit_ignores_motion_without_drag() {
+    let mut controller = OrbitCameraController::new(0.01);
+    let mut camera = orbit_camera();
+    controller.process_mouse_motion(100.0, 100.0);
+    controller.update_camera(&mut camera);
+    assert!(camera.eye.abs_diff_eq(glam::Vec3::new(0.0, 0.0, 5.0), 1e-5));
+}
+
+#[test]
+fn orbit_pitch_is_clamped() {
+    let mut controller = OrbitCameraController::new(0.01);
+    let mut camera = orbit_camera();
+    controller.set_rotating(true);
+    controller.process_mouse_motion(0.0, 10_000.0);
+    controller.update_camera(&mut camera);
+
+    let direction = (camera.eye - camera.target).normalize();
+    assert!(direction.y > 0.999);
+    assert!(direction.z > 0.0);
+    assert!(camera.build_view_matrix().is_finite());
+}
+
+#[test]
+fn orbit_pans_and_dollies() {
+    let mut controller = OrbitCameraController::new(0.01);
+    let mut camera = orbit_camera();
+
+    // 向右拖动，target 和 eye 一起向 -X 移动
+    controller.set_panning(true);
+    controller.process_mouse_motion(10.0, 0.0);
+    controller.update_camera(&mut camera);
+    assert!(camera.target.abs_diff_eq(glam::Vec3::new(-0.5, 0.0, 0.0), 1e-5));
+    assert!(camera.eye.abs_diff_eq(glam::Vec3::new(-0.5, 0.0, 5.0), 1e-5));
+    controller.set_panning(false);
+
+    controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1.0));
+    controller.update_camera(&mut camera);
+    assert!(((camera.eye - camera.target).length() - 4.5).abs() < 1e-5);
+}
+
+#[test]
+fn frame_fits_bounding_box() {
+    let mut camera = orbit_camera();
+    let bounds = Aabb::new(glam::Vec3::new(9.0, -1.0, -1.0), glam::Vec3::new(11.0, 1.0, 1.0));
+    camera.frame(&bounds);
+
+    assert_eq!(camera.target, bounds.center());
+    // 观察方向保持不变
+    assert!((camera.eye - camera.target).normalize().abs_diff_eq(glam::Vec3::Z, 1e-5));
+    // 所有角点都在裁剪空间内
+    let view_proj = camera.build_view_projection_matrix();
+    for corner in bounds.corners() {
+        let clip = view_proj.project_point3(corner);
+        assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?}", clip);
+        assert!(clip.z > 0.0 && clip.z < 1.0);
+    }
+}
+
+// 比默认远平面大得多的包围盒也能完整显示，远平面随之扩展
+#[test]
+fn frame_extends_far_plane() {
+    let bounds = Aabb::new(glam::Vec3::splat(-400.0), glam::Vec3::splat(400.0));
+    for projection in [
+        Projection::default(),
+        Projection::Orthographic { height: 1.0, znear: 0.1, zfar: 100.0 },
+    ] {
+        let mut camera = orbit_camera();
+        camera.projection = projection;
+        camera.frame(&bounds);
+
+        let (_, zfar) = camera.depth_range();
+        assert!(zfar > 100.0, "{:?}", camera.projection);
+        let view_proj = camera.build_view_projection_matrix();
+        for corner in bounds.corners() {
+            let clip = view_proj.project_point3(corner);
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?}", clip);
+            assert!(clip.z > 0.0 && clip.z < 1.0, "{:?} {:?}", camera.projection, clip);
+        }
+    }
+
+    // 远平面足够远时保持不变
+    let mut camera = orbit_camera();
+    camera.frame(&Aabb::new(glam::Vec3::splat(-1.0), glam::Vec3::splat(1.0)));
+    assert_eq!(camera.depth_range(), (0.1, 100.0));
+}
+
+#[test]
+fn aabb_transform_and_union() {
+    let unit = Aabb::new(glam::Vec3::splat(-1.0), glam::Vec3::splat(1.0));
+    let moved = unit.transform(&glam::Mat4::from_translation(glam::Vec3::X * 3.0));
+    assert_eq!(moved, Aabb::new(glam::Vec3::new(2.0, -1.0, -1.0), glam::Vec3::new(4.0, 1.0, 1.0)));
+
+    let rotated = unit.transform(&glam::Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+    assert!((rotated.max.x - 2f32.sqrt()).abs() < 1e-5);
+
+    assert!(Aabb::EMPTY.is_empty());
+    assert_eq!(Aabb::EMPTY.union(&unit), unit);
+    assert_eq!(unit.union(&moved), Aabb::new(glam::Vec3::splat(-1.0), glam::Vec3::new(4.0, 1.0, 1.0)));
+}
+
+#[test]
+fn orthographic_projection_ignores_distance() {
+    let mut camera = orbit_camera();
+    camera.aspect = 2.0;
+    camera.projection = Projection::Orthographic { height: 4.0, znear: 0.1, zfar: 50.0 };
+    let view_proj = camera.build_view_projection_matrix();
+
+    // 视口高 4、宽 8，与距离无关
+    for z in [0.0, -10.0] {
+        let clip = view_proj.project_point3(glam::Vec3::new(4.0, 2.0, z));
+        assert!(clip.truncate().abs_diff_eq(glam::Vec2::ONE, 1e-5));
+    }
+    assert_eq!(camera.depth_range(), (0.1, 50.0));
+}
+
+#[test]
+fn infinite_reverse_z_projection() {
+    let mut camera = orbit_camera();
+    camera.projection = Projection::InfinitePerspectiveReverseZ { fovy: 60f32.to_radians(), znear: 0.5 };
+    assert!(camera.projection.is_reverse_z());
+    assert_eq!(camera.depth_range(), (0.5, f32::INFINITY));
+
+    let proj = camera.build_projection_matrix();
+    // 近平面深度为 1，越远越接近 0
+    assert!((proj.project_point3(glam::Vec3::new(0.0, 0.0, -0.5)).z - 1.0).abs() < 1e-6);
+    let far = proj.project_point3(glam::Vec3::new(0.0, 0.0, -1.0e6)).z;
+    assert!(far > 0.0 && far < 1.0e-5);
+}
+
+#[test]
+fn frame_with_orthographic_projection() {
+    let mut camera = orbit_camera();
+    camera.aspect = 0.5;
+    camera.projection = Projection::Orthographic { height: 1.0, znear: 0.1, zfar: 100.0 };
+    let bounds = Aabb::new(glam::Vec3::splat(-1.0), glam::Vec3::splat(1.0));
+    camera.frame(&bounds);
+
+    let view_proj = camera.build_view_projection_matrix();
+    for corner in bounds.corners() {
+        let clip = view_proj.project_point3(corner);
+        assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?}", clip);
+        assert!(clip.z > 0.0 && clip.z < 1.0);
+    }
+}
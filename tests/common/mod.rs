@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::{Rgba, RgbaImage};
+
+// 单个通道允许的最大差值（0-255）
+pub const PIXEL_TOLERANCE: u8 = 4;
+// 感知差异阈值（0-1，基于 YIQ 色彩空间，与 pixelmatch 的做法相同）。
+// 和 pixelmatch 一样，阈值是 YIQ 距离，与平方后的 perceptual_delta 比较时也要平方。
+// 0.05 大约对应 8 位灰度上 11 级的变化：llvmpipe 上法线贴图采样的舍入误差在此之下，
+// 而光照或贴图出错时的变化远大于它
+pub const PERCEPTUAL_THRESHOLD: f32 = 0.05;
+// 允许超出阈值的像素比例
+pub const MAX_DIFF_RATIO: f32 = 0.001;
+
+pub const WIDTH: u32 = 320;
+pub const HEIGHT: u32 = 240;
+
+pub fn headless_state() -> wgpu_test::State {
+    pollster::block_on(wgpu_test::State::new_headless(WIDTH, HEIGHT, wgpu::TextureFormat::Rgba8UnormSrgb))
+        .expect("failed to create headless renderer")
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+// RGB -> YIQ 的亮度与色度分量
+fn yiq(p: &Rgba<u8>) -> [f32; 3] {
+    let [r, g, b, _] = p.0.map(|c| c as f32 / 255.0);
+    [
+        0.2988953 * r + 0.5866225 * g + 0.1144822 * b,
+        0.595978 * r - 0.2741761 * g - 0.3218019 * b,
+        0.2114702 * r - 0.5226171 * g + 0.3111469 * b,
+    ]
+}
+
+// 两个像素之间感知差异的平方，归一化到 0-1
+pub fn perceptual_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let (a, b) = (yiq(a), yiq(b));
+    let (y, i, q) = (a[0] - b[0], a[1] - b[1], a[2] - b[2]);
+    // 0.35215 是 YIQ 差异的最大值
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / 0.35215
+}
+
+// perceptual_delta 是距离的平方，与阈值的平方比较
+pub fn exceeds_threshold(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    perceptual_delta(a, b) > PERCEPTUAL_THRESHOLD * PERCEPTUAL_THRESHOLD
+}
+
+pub struct Comparison {
+    pub differing_pixels: u32,
+    pub diff_image: RgbaImage,
+}
+
+impl Comparison {
+    pub fn ratio(&self) -> f32 {
+        self.differing_pixels as f32 / (self.diff_image.width() * self.diff_image.height()) as f32
+    }
+}
+
+// 逐像素比较：通道差值超过容差且感知差异超过阈值的像素记为不同，
+// 在差异图中标红，其余像素以变暗的灰度显示参考图
+pub fn compare(expected: &RgbaImage, actual: &RgbaImage) -> Comparison {
+    assert_eq!(expected.dimensions(), actual.dimensions(), "image dimensions differ");
+
+    let mut diff_image = RgbaImage::new(expected.width(), expected.height());
+    let mut differing_pixels = 0;
+    for (x, y, e) in expected.enumerate_pixels() {
+        let a = actual.get_pixel(x, y);
+        let max_channel_diff = e.0.iter().zip(a.0.iter()).map(|(e, a)| e.abs_diff(*a)).max().unwrap_or(0);
+        let differs = max_channel_diff > PIXEL_TOLERANCE && exceeds_threshold(e, a);
+
+        let pixel = if differs {
+            differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (yiq(e)[0] * 255.0 * 0.3) as u8;
+            Rgba([luma, luma, luma, 255])
+        };
+        diff_image.put_pixel(x, y, pixel);
+    }
+
+    Comparison { differing_pixels, diff_image }
+}
+
+// 与 tests/golden/<name>.png 比较，失败时把实际结果和差异图写到 target 目录。
+// 设置 UPDATE_GOLDEN=1 会用当前结果覆盖参考图。
+pub fn assert_golden(name: &str, actual: &RgbaImage) {
+    let reference_path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&reference_path).expect("failed to write reference image");
+        return;
+    }
+
+    let expected = image::open(&reference_path)
+        .with_context(|| format!("missing reference image {}, rerun with UPDATE_GOLDEN=1", reference_path.display()))
+        .unwrap()
+        .to_rgba8();
+
+    let comparison = compare(&expected, actual);
+    if comparison.ratio() > MAX_DIFF_RATIO {
+        let out = output_dir();
+        std::fs::create_dir_all(&out).unwrap();
+        let actual_path = out.join(format!("{name}.actual.png"));
+        let diff_path = out.join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        comparison.diff_image.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {} pixels ({:.3}%) differ from the reference, see {} and {}",
+            comparison.differing_pixels,
+            comparison.ratio() * 100.0,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
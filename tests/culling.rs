@@ -0,0 +1,173 @@
+// 只用到 common 中的一部分辅助函数
+#[allow(dead_code)]
+mod common;
+
+use std::time::Duration;
+
+use wgpu_test::bounds::{Aabb, Frustum};
+use wgpu_test::camera::{Camera, Projection};
+
+fn unit_box_at(center: glam::Vec3) -> Aabb {
+    Aabb::new(center - glam::Vec3::splat(0.5), center + glam::Vec3::splat(0.5))
+}
+
+fn looking_down_neg_z(projection: Projection) -> Frustum {
+    let mut camera = Camera::new(1.0);
+    camera.eye = glam::Vec3::ZERO;
+    camera.target = glam::Vec3::NEG_Z;
+    camera.projection = projection;
+    Frustum::from_view_projection(&camera.build_view_projection_matrix())
+}
+
+#[test]
+fn frustum_classifies_boxes() {
+    let frustum = looking_down_neg_z(Projection::default());
+
+    assert!(frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(0.0, 0.0, -10.0))));
+    // 相机后方、远平面之外、视锥侧面之外
+    assert!(!frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(0.0, 0.0, 10.0))));
+    assert!(!frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(0.0, 0.0, -200.0))));
+    assert!(!frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(20.0, 0.0, -10.0))));
+    // 跨越侧面的包围盒仍然可见
+    assert!(frustum.intersects_aabb(&Aabb::new(glam::Vec3::new(0.0, -1.0, -11.0), glam::Vec3::new(50.0, 1.0, -9.0))));
+    assert!(!frustum.intersects_aabb(&Aabb::EMPTY));
+}
+
+#[test]
+fn infinite_reverse_z_frustum_has_no_far_plane() {
+    let frustum = looking_down_neg_z(Projection::InfinitePerspectiveReverseZ {
+        fovy: 45f32.to_radians(),
+        znear: 0.1,
+    });
+
+    assert!(frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(0.0, 0.0, -10_000.0))));
+    assert!(!frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(0.0, 0.0, 10.0))));
+    assert!(!frustum.intersects_aabb(&unit_box_at(glam::Vec3::new(20.0, 0.0, -10.0))));
+}
+
+#[test]
+fn culling_counts_offscreen_instances() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    let overview = state.culling_stats();
+    assert_eq!(overview.total_instances, 100);
+    // 俯瞰时只有靠近画面边缘的几个立方体被剔除
+    assert!(overview.culled_instances() < 10, "{:?}", overview);
+
+    // 贴近一个角落，大部分立方体都在视锥之外
+    let camera = state.camera_mut();
+    camera.eye = (-15.0, 2.0, -9.0).into();
+    camera.target = (-15.0, 0.0, -15.0).into();
+    state.update(Duration::ZERO);
+    let close = state.culling_stats();
+    assert!(close.culled_instances() > overview.culled_instances() + 50, "{:?}", close);
+    assert!(close.visible_instances > 0);
+    assert_eq!(close.visible_draws + close.culled_draws(), close.total_draws);
+
+    state.set_frustum_culling(false);
+    state.update(Duration::ZERO);
+    assert_eq!(state.culling_stats().culled_instances(), 0);
+}
+
+// 剔除只去掉看不见的实例，画面应该与剔除前完全一致
+#[test]
+fn culled_render_matches_golden() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (-15.0, 2.0, -9.0).into();
+    camera.target = (-15.0, 0.0, -15.0).into();
+    state.update(Duration::ZERO);
+    assert!(state.culling_stats().culled_instances() > 0);
+    let culled = state.capture_frame().unwrap();
+
+    state.set_frustum_culling(false);
+    state.update(Duration::ZERO);
+    let unculled = state.capture_frame().unwrap();
+    assert_eq!(common::compare(&unculled, &culled).differing_pixels, 0);
+}
+
+// GPU 剔除和间接绘制应当得到和 CPU 剔除一样的画面和可见数量
+#[test]
+fn gpu_culling_matches_cpu() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (-15.0, 2.0, -9.0).into();
+    camera.target = (-15.0, 0.0, -15.0).into();
+    state.update(Duration::ZERO);
+    let cpu_stats = state.culling_stats();
+    let cpu_frame = state.capture_frame().unwrap();
+
+    state.set_gpu_culling(true);
+    state.update(Duration::ZERO);
+    let gpu_frame = state.capture_frame().unwrap();
+    let counts = state.gpu_visible_counts().unwrap();
+    assert_eq!(counts.iter().sum::<u32>(), cpu_stats.visible_draws);
+    assert_eq!(common::compare(&cpu_frame, &gpu_frame).differing_pixels, 0);
+}
+
+#[test]
+fn gpu_culling_cube_grid() {
+    let mut state = common::headless_state();
+    state.set_gpu_culling(true);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+
+    // 替换实例后输出缓冲区随之扩容
+    let instances = (0..300)
+        .map(|i| {
+            wgpu_test::instance::Instance::new(
+                glam::Vec3::new((i % 20) as f32 * 2.0 - 19.0, 0.0, (i / 20) as f32 * -2.0),
+                glam::Quat::IDENTITY,
+            )
+        })
+        .collect::<Vec<_>>();
+    state.set_instances(instances);
+    state.update(Duration::ZERO);
+    state.capture_frame().unwrap();
+    let visible = state.gpu_visible_counts().unwrap().iter().sum::<u32>();
+    assert!(visible > 0 && visible < 300, "{}", visible);
+}
+
+// 间接绘制参数在每次剔除前重置，没有调用 update 的情况下重复绘制不会累加实例数量
+#[test]
+fn gpu_culling_redraw_without_update() {
+    let mut state = common::headless_state();
+    state.set_gpu_culling(true);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    let first = state.capture_frame().unwrap();
+    let counts = state.gpu_visible_counts().unwrap();
+
+    let second = state.capture_frame().unwrap();
+    assert_eq!(state.gpu_visible_counts().unwrap(), counts);
+    assert_eq!(common::compare(&first, &second).differing_pixels, 0);
+}
+
+// 输出缓冲区超出设备的存储缓冲区限制时退回 CPU 剔除，而不是创建无法绑定的缓冲区
+#[test]
+fn gpu_culling_falls_back_when_buffers_exceed_limits() {
+    let mut state = common::headless_state();
+    state.set_gpu_culling(true);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    // 设备使用默认限制，存储缓冲区最大 128 MiB，每个实例 120 字节
+    let limit = wgpu::Limits::default().max_storage_buffer_binding_size as usize;
+    let count = limit / std::mem::size_of::<wgpu_test::instance::InstanceRaw>() + 1;
+    // 全部放在相机身后，CPU 剔除后什么也不画
+    let instance = wgpu_test::instance::Instance::new(glam::Vec3::new(0.0, 12.0, 100.0), glam::Quat::IDENTITY);
+    state.set_instances(vec![instance; count]);
+    state.update(Duration::ZERO);
+    let stats = state.culling_stats();
+    assert_eq!(stats.total_instances, count as u32);
+    assert_eq!(stats.visible_instances, 0);
+    assert!(state.gpu_visible_counts().unwrap().is_empty());
+}
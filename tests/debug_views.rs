@@ -0,0 +1,140 @@
+#[allow(dead_code)]
+mod common;
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use image::{Rgba, RgbaImage};
+use wgpu_test::debug::DebugView;
+
+fn cube_grid_state() -> wgpu_test::State {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state
+}
+
+fn capture(state: &mut wgpu_test::State, view: DebugView) -> RgbaImage {
+    state.set_debug_view(view).unwrap();
+    state.update(Duration::ZERO);
+    state.capture_frame().unwrap()
+}
+
+// 与清屏颜色不同的像素的颜色集合
+fn foreground_colors(frame: &RgbaImage) -> HashSet<Rgba<u8>> {
+    let background = frame.get_pixel(0, 0);
+    frame.pixels().filter(|pixel| *pixel != background).copied().collect()
+}
+
+fn foreground_pixels(frame: &RgbaImage) -> usize {
+    let background = frame.get_pixel(0, 0);
+    frame.pixels().filter(|pixel| *pixel != background).count()
+}
+
+#[test]
+fn debug_views_golden() {
+    let mut state = cube_grid_state();
+    assert_eq!(state.debug_view(), DebugView::Shaded);
+    for (view, name) in [
+        (DebugView::Wireframe, "debug_wireframe"),
+        (DebugView::Normals, "debug_normals"),
+        (DebugView::TexCoords, "debug_tex_coords"),
+        (DebugView::Tangents, "debug_tangents"),
+        (DebugView::Depth, "debug_depth"),
+        (DebugView::InstanceId, "debug_instance_id"),
+    ] {
+        common::assert_golden(name, &capture(&mut state, view));
+    }
+
+    // 回到正常渲染
+    common::assert_golden("cube_grid", &capture(&mut state, DebugView::Shaded));
+}
+
+// 没有 PolygonMode::Line 时的回退方案画出的线框与线框模式大体一致，GPU 剔除的间接绘制也能使用
+#[test]
+fn barycentric_wireframe_fallback() {
+    let mut state = cube_grid_state();
+    assert!(state.is_line_wireframe(), "the fallback adapter supports POLYGON_MODE_LINE");
+    let lines = capture(&mut state, DebugView::Wireframe);
+
+    state.set_barycentric_wireframe(true).unwrap();
+    assert!(!state.is_line_wireframe());
+    state.update(Duration::ZERO);
+    let barycentric = state.capture_frame().unwrap();
+    common::assert_golden("debug_wireframe_barycentric", &barycentric);
+    let (a, b) = (foreground_pixels(&lines) as f32, foreground_pixels(&barycentric) as f32);
+    assert!(a > 0.0 && (0.5..2.0).contains(&(b / a)), "{} line pixels, {} barycentric pixels", a, b);
+
+    state.set_gpu_culling(true);
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&barycentric, &state.capture_frame().unwrap()).differing_pixels, 0);
+}
+
+// 实例 ID 的颜色与剔除方式和绘制顺序无关
+#[test]
+fn instance_ids_are_stable() {
+    let mut state = cube_grid_state();
+    let frame = capture(&mut state, DebugView::InstanceId);
+    // 可见的实例各不相同
+    assert!(foreground_colors(&frame).len() > 50, "{} colors", foreground_colors(&frame).len());
+
+    state.set_gpu_culling(true);
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&frame, &state.capture_frame().unwrap()).differing_pixels, 0);
+}
+
+// 每个网格一种颜色：场景中有两个模型时只有两种颜色
+#[test]
+fn mesh_ids_color_each_mesh() {
+    let mut state = common::headless_state();
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("scenes");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("debug_meshes.ron");
+    std::fs::write(
+        &path,
+        r#"(
+            camera: (eye: (0.0, 6.0, 10.0), target: (0.0, 0.0, 0.0)),
+            models: [
+                (file: "cube.obj", instances: [(layout: Grid(count: (3, 1), spacing: 2.0, center: (0.0, 1.0, 0.0)))]),
+                (file: "quad.obj", instances: [(layout: Single(position: (0.0, 0.0, 0.0)), rotation: Euler((-90.0, 0.0, 0.0)), scale: (6.0, 6.0, 6.0))]),
+            ],
+        )"#,
+    )
+    .unwrap();
+    pollster::block_on(state.load_scene_file(&path)).unwrap();
+
+    let frame = capture(&mut state, DebugView::MeshId);
+    assert_eq!(foreground_colors(&frame).len(), 2);
+    let instances = capture(&mut state, DebugView::InstanceId);
+    assert_eq!(foreground_colors(&instances).len(), 4);
+}
+
+// 多重采样的深度纹理不能在着色器中读取
+#[test]
+fn depth_view_requires_msaa_off() {
+    let mut state = cube_grid_state();
+    state.set_sample_count(4).unwrap();
+    assert!(state.set_debug_view(DebugView::Depth).is_err());
+    assert_eq!(state.debug_view(), DebugView::Shaded);
+
+    // 其他视图跟着 MSAA 使用多重采样
+    let wireframe = capture(&mut state, DebugView::Wireframe);
+    assert!(foreground_pixels(&wireframe) > 0);
+
+    state.set_sample_count(1).unwrap();
+    state.set_debug_view(DebugView::Depth).unwrap();
+    assert!(state.set_sample_count(4).is_err());
+    assert_eq!(state.sample_count(), 1);
+}
+
+#[test]
+fn debug_views_cycle() {
+    let mut view = DebugView::Shaded;
+    for _ in 0..DebugView::ALL.len() {
+        view = view.next();
+    }
+    assert_eq!(view, DebugView::Shaded);
+    assert_eq!(DebugView::Shaded.next(), DebugView::Wireframe);
+}
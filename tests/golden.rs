@@ -0,0 +1,326 @@
+mod common;
+
+use std::time::Duration;
+
+use wgpu_test::camera::Projection;
+use wgpu_test::instance::Instance;
+
+#[test]
+fn cube_grid() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("cube_grid", &frame);
+}
+
+// 4 倍 MSAA 只改变三角形边缘的像素，关闭后回到原来的画面
+#[test]
+fn msaa_cube_grid() {
+    let mut state = common::headless_state();
+    assert_eq!(state.sample_count(), 1);
+    assert!(state.supported_sample_counts().contains(&4));
+    assert!(state.set_sample_count(3).is_err());
+    assert_eq!(state.sample_count(), 1);
+
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    let aliased = state.capture_frame().unwrap();
+
+    state.set_sample_count(4).unwrap();
+    state.update(Duration::ZERO);
+    let antialiased = state.capture_frame().unwrap();
+    common::assert_golden("msaa_cube_grid", &antialiased);
+    let edges = common::compare(&aliased, &antialiased).differing_pixels;
+    assert!(edges > 0 && edges < aliased.len() as u32 / 4 / 10, "{} pixels changed", edges);
+
+    // 改变窗口大小后多重采样纹理跟着重建
+    state.resize(winit::dpi::PhysicalSize::new(common::WIDTH / 2, common::HEIGHT / 2));
+    state.resize(winit::dpi::PhysicalSize::new(common::WIDTH, common::HEIGHT));
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&antialiased, &state.capture_frame().unwrap()).differing_pixels, 0);
+
+    state.set_sample_count(1).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+}
+
+// 场景在远平面 100 以内，reverse-Z 只改变深度精度的分布，画面应该与普通模式一致
+#[test]
+fn reverse_z_cube_grid() {
+    let mut state = common::headless_state();
+    state.set_reverse_z(true).unwrap();
+    assert!(state.is_reverse_z());
+    let camera = state.camera_mut();
+    assert!(camera.projection.is_reverse_z());
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+
+    // 直接修改投影也会切换深度模式
+    state.camera_mut().projection = Projection::default();
+    state.update(Duration::ZERO);
+    assert!(!state.is_reverse_z());
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+}
+
+// 关闭 reverse-Z 时恢复原来的远平面，正交投影不能开启 reverse-Z
+#[test]
+fn reverse_z_restores_projection() {
+    let mut state = common::headless_state();
+    let perspective = Projection::Perspective { fovy: 60f32.to_radians(), znear: 0.5, zfar: 500.0 };
+    state.camera_mut().projection = perspective;
+    state.set_reverse_z(true).unwrap();
+    assert_eq!(state.camera_mut().projection, Projection::InfinitePerspectiveReverseZ { fovy: 60f32.to_radians(), znear: 0.5 });
+    state.set_reverse_z(false).unwrap();
+    assert_eq!(state.camera_mut().projection, perspective);
+    assert!(!state.is_reverse_z());
+
+    let orthographic = Projection::Orthographic { height: 20.0, znear: 0.1, zfar: 100.0 };
+    state.camera_mut().projection = orthographic;
+    let error = state.set_reverse_z(true).unwrap_err();
+    assert!(format!("{:#}", error).contains("perspective projection"), "{:#}", error);
+    assert_eq!(state.camera_mut().projection, orthographic);
+    assert!(!state.is_reverse_z());
+    state.set_reverse_z(false).unwrap();
+}
+
+#[test]
+fn framed_cube_grid() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (10.0, 10.0, 10.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.frame_model();
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("framed_cube_grid", &frame);
+}
+
+#[test]
+fn orthographic_cube_grid() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (20.0, 20.0, 20.0).into();
+    camera.target = glam::Vec3::ZERO;
+    camera.projection = Projection::Orthographic { height: 30.0, znear: 0.1, zfar: 100.0 };
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("orthographic_cube_grid", &frame);
+}
+
+#[test]
+fn resize_updates_aspect() {
+    let mut state = common::headless_state();
+    state.resize(winit::dpi::PhysicalSize::new(200, 100));
+    assert_eq!(state.camera_mut().aspect, 2.0);
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    assert_eq!(frame.dimensions(), (200, 100));
+}
+
+#[test]
+fn happy_tree_quad() {
+    let mut state = common::headless_state();
+    pollster::block_on(state.load_model("quad.obj")).unwrap();
+    state.set_instances(vec![Instance::default()]);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 0.0, 3.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("happy_tree_quad", &frame);
+}
+
+#[test]
+fn tiled_quad() {
+    let mut state = common::headless_state();
+    pollster::block_on(state.load_model("tiled-quad.obj")).unwrap();
+    state.set_instances(vec![Instance::default()]);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 0.0, 3.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("tiled_quad", &frame);
+}
+
+// mipmap 管线按纹理格式缓存，加载更多纹理不会创建新的管线
+#[test]
+fn mipmap_pipelines_are_cached_per_format() {
+    let mut state = common::headless_state();
+    pollster::block_on(state.load_model("quad.obj")).unwrap();
+    let count = state.mipmaps().pipeline_count();
+    assert!(count > 0);
+    pollster::block_on(state.load_model("tiled-quad.obj")).unwrap();
+    assert_eq!(state.mipmaps().pipeline_count(), count);
+}
+
+// .gltf（外部缓冲和贴图）与 .glb（全部内嵌）描述的是同一个场景，应当渲染出同一张图
+fn render_tree_pair(file_name: &str) -> image::RgbaImage {
+    let mut state = common::headless_state();
+    pollster::block_on(state.load_model(file_name)).unwrap();
+    state.set_instances(vec![Instance::default()]);
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 0.0, 5.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+
+    state.capture_frame().unwrap()
+}
+
+#[test]
+fn gltf_tree_pair() {
+    common::assert_golden("gltf_tree_pair", &render_tree_pair("tree-pair.gltf"));
+}
+
+#[test]
+fn glb_tree_pair() {
+    common::assert_golden("gltf_tree_pair", &render_tree_pair("tree-pair.glb"));
+}
+
+// 图像的缓冲视图超出缓冲的范围：返回错误而不是 panic
+#[test]
+fn gltf_image_outside_buffer_is_an_error() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("image-outside-buffer.gltf");
+    std::fs::write(&path, r#"{
+        "asset": { "version": "2.0" },
+        "buffers": [{ "byteLength": 4, "uri": "data:application/octet-stream;base64,AAAAAA==" }],
+        "bufferViews": [{ "buffer": 0, "byteOffset": 2, "byteLength": 8 }],
+        "images": [{ "bufferView": 0, "mimeType": "image/png" }],
+        "textures": [{ "source": 0 }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }],
+        "scenes": [{ "nodes": [] }]
+    }"#).unwrap();
+
+    let mut state = common::headless_state();
+    let error = pollster::block_on(state.load_model(path.to_str().unwrap())).unwrap_err();
+    assert!(format!("{:#}", error).contains("outside of buffer 0"), "{:#}", error);
+}
+
+// 索引超出顶点数量：返回指出网格和图元的错误，而不是在生成法线时 panic
+#[test]
+fn gltf_index_out_of_bounds_is_an_error() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("index-out-of-bounds.gltf");
+    // 三个顶点的位置，然后是索引 0, 1, 5
+    std::fs::write(&path, r#"{
+        "asset": { "version": "2.0" },
+        "buffers": [{ "byteLength": 44, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAUAAAA=" }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ],
+        "meshes": [{ "name": "triangle", "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }],
+        "nodes": [{ "mesh": 0 }],
+        "scenes": [{ "nodes": [0] }]
+    }"#).unwrap();
+
+    let mut state = common::headless_state();
+    let error = pollster::block_on(state.load_model(path.to_str().unwrap())).unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("triangle: primitive 0: index 5 out of bounds for 3 vertices"), "{}", message);
+}
+
+#[test]
+fn compare_reports_changed_pixels() {
+    let expected = image::RgbaImage::from_pixel(8, 8, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    // 容差内的差异不计
+    actual.put_pixel(0, 0, image::Rgba([102, 101, 100, 255]));
+    actual.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+
+    let comparison = common::compare(&expected, &actual);
+    assert_eq!(comparison.differing_pixels, 1);
+    assert_eq!(comparison.diff_image.get_pixel(1, 1), &image::Rgba([255, 0, 0, 255]));
+}
+
+// 阈值按 YIQ 距离计算：灰度变化 8 级在阈值之内，12 级超出
+#[test]
+fn perceptual_threshold_is_a_distance() {
+    let gray = |v| image::Rgba([v, v, v, 255]);
+    assert!(!common::exceeds_threshold(&gray(100), &gray(108)));
+    assert!(common::exceeds_threshold(&gray(100), &gray(112)));
+    // 同样亮度下色度的变化也会被发现
+    assert!(common::exceeds_threshold(&gray(100), &image::Rgba([120, 92, 92, 255])));
+}
+
+#[test]
+fn many_lights() {
+    use wgpu_test::lights::{PointLight, SpotLight};
+
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+
+    // 每个立方体旁边放一个彩色点光源，总数超过单个簇的容量以测试剔除
+    let lights = state.lights_mut();
+    lights.clear();
+    for z in 0..10 {
+        for x in 0..10 {
+            let color = glam::Vec3::new(x as f32 / 9.0, 0.5, z as f32 / 9.0);
+            lights.add(PointLight {
+                position: glam::Vec3::new(x as f32 * 3.0 - 13.5, 1.5, z as f32 * 3.0 - 13.5),
+                color,
+                intensity: 4.0,
+                range: 4.0,
+            });
+        }
+    }
+    lights.add(SpotLight {
+        position: glam::Vec3::new(0.0, 8.0, 0.0),
+        direction: glam::Vec3::NEG_Y,
+        color: glam::Vec3::ONE,
+        intensity: 60.0,
+        range: 20.0,
+        inner_angle: 10f32.to_radians(),
+        outer_angle: 20f32.to_radians(),
+    });
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("many_lights", &frame);
+}
+
+#[test]
+fn low_sun_shadows() {
+    use wgpu_test::{lights::DirectionalLight, shadow::ShadowConfig};
+
+    let mut state = common::headless_state();
+    state.set_shadow_config(ShadowConfig {
+        resolution: 1024,
+        cascade_count: 2,
+        ..Default::default()
+    });
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 6.0, 14.0).into();
+    camera.target = glam::Vec3::ZERO;
+
+    // 低角度的平行光让每个立方体的阴影落到相邻的立方体上
+    let lights = state.lights_mut();
+    lights.clear();
+    lights.add(DirectionalLight {
+        direction: glam::Vec3::new(-1.0, -0.3, -0.2),
+        color: glam::Vec3::ONE,
+        intensity: 1.5,
+    });
+    state.update(Duration::ZERO);
+
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("low_sun_shadows", &frame);
+}
@@ -0,0 +1,140 @@
+#[allow(dead_code)]
+mod common;
+
+use std::time::Duration;
+
+use wgpu_test::instance::{Instance, InstanceRaw};
+
+const RAW_SIZE: u64 = std::mem::size_of::<InstanceRaw>() as u64;
+
+#[test]
+fn instance_raw_layout() {
+    // mat4 + mat3 + vec4 + u32
+    assert_eq!(RAW_SIZE, 30 * 4);
+    let raw = Instance { material: Some(3), ..Default::default() }.to_raw();
+    assert_eq!(raw.material(), 3);
+    assert_eq!(Instance::default().to_raw().material(), wgpu_test::instance::NO_MATERIAL_OVERRIDE);
+
+    let scaled = Instance { scale: glam::Vec3::new(2.0, 1.0, 1.0), ..Default::default() };
+    assert_eq!(scaled.model_matrix().transform_point3(glam::Vec3::X), glam::Vec3::new(2.0, 0.0, 0.0));
+}
+
+// 缩放为 0 的实例不可逆，法线矩阵退回单位矩阵而不是 inf/NaN
+#[test]
+fn degenerate_scale_keeps_normal_matrix_finite() {
+    let scaled = Instance { scale: glam::Vec3::new(2.0, 1.0, 1.0), ..Default::default() }.to_raw();
+    assert!(scaled.normal_matrix().abs_diff_eq(glam::Mat3::from_diagonal(glam::Vec3::new(0.5, 1.0, 1.0)), 1e-6));
+
+    for scale in [glam::Vec3::ZERO, glam::Vec3::new(1.0, 0.0, 1.0)] {
+        let raw = Instance { scale, ..Default::default() }.to_raw();
+        assert_eq!(raw.normal_matrix(), glam::Mat3::IDENTITY, "{:?}", scale);
+    }
+}
+
+#[test]
+fn only_dirty_instances_are_uploaded() {
+    let mut state = common::headless_state();
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().len(), 100);
+    assert_eq!(state.instances().uploaded_bytes(), 0);
+
+    // 修改一个实例只上传一个实例的数据
+    state.instances_mut().get_mut(42).unwrap().position.y = 5.0;
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().uploaded_bytes(), RAW_SIZE);
+
+    // 相邻的修改合并成一次上传，不相邻的分开上传，但总量只包含脏数据
+    let instances = state.instances_mut();
+    for i in [10, 11, 12, 80] {
+        instances.get_mut(i).unwrap().tint = glam::Vec4::new(1.0, 0.0, 0.0, 1.0);
+    }
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().uploaded_bytes(), 4 * RAW_SIZE);
+
+    // 删除中间的实例时最后一个实例移到空位上，只需要上传这一个
+    let last = *state.instances().get(99).unwrap();
+    let removed = state.instances_mut().remove(42);
+    assert_eq!(removed.position.y, 5.0);
+    assert_eq!(state.instances().get(42), Some(&last));
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().len(), 99);
+    assert_eq!(state.instances().uploaded_bytes(), RAW_SIZE);
+}
+
+#[test]
+fn instance_buffer_grows() {
+    let mut state = common::headless_state();
+    state.update(Duration::ZERO);
+    let generation = state.instances().generation();
+
+    // 100 个实例的容量是 128，之内的新增不需要重建缓冲区
+    for i in 0..28 {
+        state.instances_mut().add(Instance::new(glam::Vec3::new(i as f32, 5.0, 0.0), glam::Quat::IDENTITY));
+    }
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().generation(), generation);
+    assert_eq!(state.instances().uploaded_bytes(), 28 * RAW_SIZE);
+
+    // 超过容量时扩容并上传全部实例
+    let index = state.instances_mut().add(Instance::default());
+    assert_eq!(index, 128);
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().generation(), generation + 1);
+    assert_eq!(state.instances().uploaded_bytes(), 129 * RAW_SIZE);
+    assert_eq!(state.culling_stats().total_instances, 129);
+}
+
+#[test]
+fn material_overrides_are_tracked() {
+    let mut state = common::headless_state();
+    let instances = state.instances_mut();
+    instances.get_mut(0).unwrap().material = Some(1);
+    instances.get_mut(1).unwrap().material = Some(1);
+    instances.get_mut(2).unwrap().material = Some(0);
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().material_overrides().collect::<Vec<_>>(), vec![0, 1]);
+
+    state.instances_mut().get_mut(2).unwrap().material = None;
+    state.instances_mut().remove(0);
+    state.update(Duration::ZERO);
+    assert_eq!(state.instances().material_overrides().collect::<Vec<_>>(), vec![1]);
+}
+
+// 三个立方体：原样、放大并染成红色、覆盖为模型的默认白色材质
+fn attribute_instances() -> Vec<Instance> {
+    vec![
+        Instance::new(glam::Vec3::new(-3.0, 0.0, 0.0), glam::Quat::IDENTITY),
+        Instance {
+            position: glam::Vec3::ZERO,
+            scale: glam::Vec3::splat(1.5),
+            tint: glam::Vec4::new(1.0, 0.3, 0.3, 1.0),
+            ..Default::default()
+        },
+        Instance {
+            position: glam::Vec3::new(3.0, 0.0, 0.0),
+            material: Some(1),
+            ..Default::default()
+        },
+    ]
+}
+
+fn render_attributes(gpu_culling: bool) -> image::RgbaImage {
+    let mut state = common::headless_state();
+    state.set_gpu_culling(gpu_culling);
+    state.set_instances(attribute_instances());
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 3.0, 8.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    state.capture_frame().unwrap()
+}
+
+#[test]
+fn instance_attributes() {
+    common::assert_golden("instance_attributes", &render_attributes(false));
+}
+
+#[test]
+fn gpu_culled_instance_attributes() {
+    common::assert_golden("instance_attributes", &render_attributes(true));
+}
@@ -0,0 +1,73 @@
+// 只用到 common 中的一部分辅助函数
+#[allow(dead_code)]
+mod common;
+
+use std::time::Duration;
+
+use wgpu_test::lights::{Light, PointLight, MAX_LIGHTS_PER_CLUSTER};
+
+fn point_light(position: glam::Vec3) -> PointLight {
+    PointLight { position, color: glam::Vec3::ONE, intensity: 1.0, range: 1.0 }
+}
+
+// 视线方向上前后排列的光源落在同一个图块里，但分布在不同的深度切片中，不会超出簇的容量
+#[test]
+fn lights_in_depth_are_split_across_clusters() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 0.0, 10.0).into();
+    camera.target = glam::Vec3::ZERO;
+
+    let lights = state.lights_mut();
+    lights.clear();
+    let count = MAX_LIGHTS_PER_CLUSTER as usize + 20;
+    for i in 0..count {
+        lights.add(point_light(glam::Vec3::new(0.0, 0.0, 5.0 - i as f32)));
+    }
+    state.update(Duration::ZERO);
+    state.capture_frame().unwrap();
+    assert_eq!(state.light_cluster_overflow().unwrap(), 0);
+}
+
+// 同一位置的光源超过簇的容量时可以检查出来
+#[test]
+fn cluster_overflow_is_reported() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 0.0, 10.0).into();
+    camera.target = glam::Vec3::ZERO;
+
+    let lights = state.lights_mut();
+    lights.clear();
+    for _ in 0..MAX_LIGHTS_PER_CLUSTER + 1 {
+        lights.add(point_light(glam::Vec3::ZERO));
+    }
+    state.update(Duration::ZERO);
+    state.capture_frame().unwrap();
+    assert!(state.light_cluster_overflow().unwrap() > 0);
+}
+
+// 平行光不经过簇的列表，即使点光源填满了簇也照亮整个画面
+#[test]
+fn directional_lights_bypass_clusters() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state.update(Duration::ZERO);
+    let before = state.capture_frame().unwrap();
+
+    // 点光源悬在摄像机和立方体之间，占满所在簇的容量但照不到任何物体。
+    // 平行光放到列表末尾，仍然照亮整个画面
+    let lights = state.lights_mut();
+    let sun = lights.lights().iter().position(|light| matches!(light, Light::Directional(_))).unwrap();
+    let sun = lights.lights_mut().remove(sun);
+    for _ in 0..MAX_LIGHTS_PER_CLUSTER * 2 {
+        lights.add(point_light(glam::Vec3::new(0.0, 8.0, 16.0)));
+    }
+    lights.add(sun);
+    state.update(Duration::ZERO);
+    let crowded = state.capture_frame().unwrap();
+    assert!(state.light_cluster_overflow().unwrap() > 0);
+    assert_eq!(common::compare(&before, &crowded).differing_pixels, 0);
+}
@@ -0,0 +1,161 @@
+#[allow(dead_code)]
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wgpu_test::preprocessor::{ShaderDefines, ShaderLibrary};
+
+// 每个测试使用自己的着色器目录，files 是（相对路径，内容）
+fn library(name: &str, files: &[(&str, &str)]) -> (ShaderLibrary, PathBuf) {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("preprocessor").join(name);
+    for (file, source) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+    (ShaderLibrary::from_dir(&dir), dir)
+}
+
+fn lines(source: &str) -> Vec<&str> {
+    source.lines().collect()
+}
+
+#[test]
+fn includes_are_relative_and_included_once() {
+    let (library, _) = library(
+        "include",
+        &[
+            ("main.wgsl", "#include \"lib/a.wgsl\"\n#include \"lib/b.wgsl\"\nmain"),
+            ("lib/a.wgsl", "#include \"b.wgsl\"\na"),
+            ("lib/b.wgsl", "#include \"../main.wgsl\"\nb"),
+        ],
+    );
+    let shader = library.preprocess("main.wgsl", &ShaderDefines::new()).unwrap();
+    assert_eq!(lines(&shader.source), ["b", "a", "main"]);
+    assert_eq!(shader.files(), ["main.wgsl", "lib/a.wgsl", "lib/b.wgsl"]);
+    // 展开后的行号对应到原始文件
+    assert_eq!(shader.source_location(1), Some(("lib/b.wgsl", 2)));
+    assert_eq!(shader.source_location(3), Some(("main.wgsl", 3)));
+    assert_eq!(shader.source_location(4), None);
+}
+
+#[test]
+fn conditionals_select_lines() {
+    let source = "\
+#ifdef A
+a
+#ifndef B
+a_not_b
+#else
+a_b
+#endif
+#else
+not_a
+#endif
+#define C
+#ifdef C
+c
+#endif
+";
+    let (library, _) = library("conditionals", &[("main.wgsl", source)]);
+    let preprocess = |defines: &[&str]| library.preprocess("main.wgsl", &defines.iter().copied().collect()).unwrap().source;
+    assert_eq!(lines(&preprocess(&[])), ["not_a", "c"]);
+    assert_eq!(lines(&preprocess(&["A"])), ["a", "a_not_b", "c"]);
+    assert_eq!(lines(&preprocess(&["A", "B"])), ["a", "a_b", "c"]);
+}
+
+// 被包含文件中的 #define 对之后的内容有效，未选中的分支中的 #define 和 #include 不生效
+#[test]
+fn defines_flow_through_includes() {
+    let (library, _) = library(
+        "defines",
+        &[
+            ("main.wgsl", "#ifdef SKIP\n#include \"missing.wgsl\"\n#define WRONG\n#endif\n#include \"config.wgsl\"\n#ifdef FEATURE\nfeature\n#endif\n#ifdef WRONG\nwrong\n#endif"),
+            ("config.wgsl", "#define FEATURE"),
+        ],
+    );
+    let shader = library.preprocess("main.wgsl", &ShaderDefines::new()).unwrap();
+    assert_eq!(lines(&shader.source), ["feature"]);
+}
+
+#[test]
+fn directive_errors_report_file_and_line() {
+    let (library, dir) = library(
+        "errors",
+        &[
+            ("unknown.wgsl", "fn f() {}\n#pragma once"),
+            ("endif.wgsl", "#endif"),
+            ("else.wgsl", "#ifdef A\n#else\n#else\n#endif"),
+            ("unterminated.wgsl", "#ifdef A\n#ifdef B\n#endif"),
+            ("include.wgsl", "\n#include \"unterminated.wgsl\""),
+            ("missing.wgsl", "#include \"nothing.wgsl\""),
+            ("syntax.wgsl", "#include nothing.wgsl"),
+        ],
+    );
+    let error = |name: &str| format!("{:#}", library.preprocess(name, &ShaderDefines::new()).unwrap_err());
+    let path = |name: &str| dir.join(name).display().to_string();
+
+    assert!(error("unknown.wgsl").contains(&format!("{}:2: unknown directive #pragma", path("unknown.wgsl"))));
+    assert!(error("endif.wgsl").contains(&format!("{}:1: #endif without #ifdef", path("endif.wgsl"))));
+    assert!(error("else.wgsl").contains(&format!("{}:3: duplicate #else", path("else.wgsl"))));
+    assert!(error("unterminated.wgsl").contains(&format!("{}:1: conditional block is missing #endif", path("unterminated.wgsl"))));
+    // 被包含的文件出错时同时给出包含它的位置
+    let message = error("include.wgsl");
+    assert!(message.contains(&format!("{}:2: included from here", path("include.wgsl"))), "{}", message);
+    assert!(message.contains(&path("unterminated.wgsl")), "{}", message);
+    assert!(error("missing.wgsl").contains(&format!("failed to read {}", path("nothing.wgsl"))));
+    assert!(error("syntax.wgsl").contains("expected #include \"file\""));
+}
+
+// naga 的错误指向原始文件中的行，而不是展开后的行
+#[test]
+fn compile_errors_point_to_original_file() {
+    let (library, dir) = library(
+        "naga",
+        &[
+            ("main.wgsl", "#include \"types.wgsl\"\n\nfn main_fn() -> f32 {\n    return f();\n}\n"),
+            ("types.wgsl", "#ifdef UNUSED\nunused\n#endif\nfn f() -> f32 {\n    return 1u;\n}\n"),
+        ],
+    );
+    let shader = library.preprocess("main.wgsl", &ShaderDefines::new()).unwrap();
+    let message = format!("{:#}", shader.validate(&library, &[]).unwrap_err());
+    // 展开后 f 在第 1 行，原始文件中在第 4 行
+    assert!(message.contains(&format!("{}:4:1", dir.join("types.wgsl").display())), "{}", message);
+}
+
+// 每种宏组合只编译一次，之后切换回来直接使用缓存的变体和管线
+#[test]
+fn shader_variants_are_cached() {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    assert!(state.shader_defines().contains(wgpu_test::NORMAL_MAP) && state.shader_defines().contains(wgpu_test::SHADOWS));
+    assert_eq!((state.shader_variant_count(), state.render_pipeline_count()), (1, 1));
+    state.update(Duration::ZERO);
+    let full = state.capture_frame().unwrap();
+    common::assert_golden("cube_grid", &full);
+
+    // 关闭阴影得到新的变体，画面不同
+    state.set_shader_define(wgpu_test::SHADOWS, false).unwrap();
+    assert_eq!((state.shader_variant_count(), state.render_pipeline_count()), (2, 2));
+    state.update(Duration::ZERO);
+    assert!(common::compare(&full, &state.capture_frame().unwrap()).differing_pixels > 0);
+
+    // 经过 {} 到 {SHADOWS}
+    state.set_shader_define(wgpu_test::NORMAL_MAP, false).unwrap();
+    state.set_shader_define(wgpu_test::SHADOWS, true).unwrap();
+    assert_eq!((state.shader_variant_count(), state.render_pipeline_count()), (4, 4));
+
+    // 回到默认设置不再编译
+    state.set_shader_define(wgpu_test::NORMAL_MAP, true).unwrap();
+    assert_eq!((state.shader_variant_count(), state.render_pipeline_count()), (4, 4));
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&full, &state.capture_frame().unwrap()).differing_pixels, 0);
+
+    // 采样数不同的管线共用同一个着色器变体
+    state.set_sample_count(4).unwrap();
+    state.set_sample_count(1).unwrap();
+    assert_eq!((state.shader_variant_count(), state.render_pipeline_count()), (4, 5));
+}
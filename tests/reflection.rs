@@ -0,0 +1,113 @@
+use wgpu::{BindGroupLayoutEntry, BindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+use wgpu_test::instance::InstanceRaw;
+use wgpu_test::model::{ModelVertex, Vertex};
+use wgpu_test::preprocessor::{ShaderDefines, ShaderLibrary};
+use wgpu_test::reflection::ShaderReflection;
+
+fn main_shader() -> ShaderReflection {
+    let defines = ShaderDefines::from_iter([wgpu_test::NORMAL_MAP, wgpu_test::SHADOWS]);
+    ShaderReflection::load(&ShaderLibrary::embedded(), "shader.wgsl", &defines).unwrap()
+}
+
+fn debug_shader() -> ShaderReflection {
+    ShaderReflection::load(&ShaderLibrary::embedded(), "debug.wgsl", &ShaderDefines::new()).unwrap()
+}
+
+fn texture_2d(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture { sample_type: TextureSampleType::Float { filterable: true }, view_dimension: TextureViewDimension::D2, multisampled: false },
+        count: None,
+    }
+}
+
+fn sampler(binding: u32, ty: wgpu::SamplerBindingType) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry { binding, visibility: ShaderStages::FRAGMENT, ty: BindingType::Sampler(ty), count: None }
+}
+
+fn uniform(binding: u32, visibility: ShaderStages) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+        count: None,
+    }
+}
+
+// 反射得到的布局与原来手写的布局相同
+#[test]
+fn bind_group_layouts_match_shader() {
+    let shader = main_shader();
+    let filtering = wgpu::SamplerBindingType::Filtering;
+    assert_eq!(
+        shader.bind_group_layout_entries(0).unwrap(),
+        [texture_2d(0), sampler(1, filtering), texture_2d(2), sampler(3, filtering), uniform(4, ShaderStages::FRAGMENT)]
+    );
+    assert_eq!(shader.bind_group_layout_entries(1).unwrap(), [uniform(0, ShaderStages::VERTEX | ShaderStages::FRAGMENT)]);
+
+    let shadow = shader.bind_group_layout_entries(3).unwrap();
+    assert_eq!(
+        shadow[0].ty,
+        BindingType::Texture { sample_type: TextureSampleType::Depth, view_dimension: TextureViewDimension::D2Array, multisampled: false }
+    );
+    assert_eq!(shadow[1], sampler(1, wgpu::SamplerBindingType::Comparison));
+    assert!(shader.bind_group_layout_entries(4).unwrap().is_empty());
+
+    // 只有顶点着色器读取的存储缓冲区
+    let wire = debug_shader().bind_group_layout_entries(2).unwrap();
+    assert_eq!(wire.len(), 2);
+    assert!(wire.iter().all(|entry| entry.visibility == ShaderStages::VERTEX
+        && entry.ty == BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: true }, has_dynamic_offset: false, min_binding_size: None }));
+}
+
+#[test]
+fn vertex_layouts_match_shaders() {
+    let shader = main_shader();
+    let locations = shader.vertex_inputs("vs_main").unwrap().iter().map(|input| input.location).collect::<Vec<_>>();
+    assert_eq!(locations, (0..=12).collect::<Vec<_>>());
+    shader.validate_vertex_buffers("vs_main", &[ModelVertex::desc(), InstanceRaw::desc()]).unwrap();
+
+    let debug = debug_shader();
+    debug.validate_vertex_buffers("vs_main", &[ModelVertex::desc(), InstanceRaw::desc()]).unwrap();
+    // 顶点拉取的线框只有实例数据来自顶点缓冲区
+    debug.validate_vertex_buffers("vs_wireframe_barycentric", &[InstanceRaw::desc()]).unwrap();
+
+    assert!(shader.validate_vertex_buffers("fs_main", &[]).is_err());
+    assert!(shader.validate_vertex_buffers("vs_missing", &[]).is_err());
+}
+
+// 所有不一致的地方一起报告
+#[test]
+fn vertex_layout_mismatches_are_reported() {
+    let shader = main_shader();
+    let model = ModelVertex::desc();
+    let mut attributes = model.attributes.to_vec();
+    // 法线写成了 2 个分量，副切线的偏移与切线重叠，最后一个属性超出步长
+    attributes[2].format = wgpu::VertexFormat::Float32x2;
+    attributes[4].offset -= 4;
+    attributes.push(wgpu::VertexAttribute { format: wgpu::VertexFormat::Uint32, offset: model.array_stride, shader_location: 14 });
+    let instance = InstanceRaw::desc();
+    // 去掉颜色，法线矩阵的最后一列只写了一个分量
+    let mut instance_attributes = instance.attributes.iter().filter(|attribute| attribute.shader_location != 12).copied().collect::<Vec<_>>();
+    instance_attributes.last_mut().unwrap().format = wgpu::VertexFormat::Float32;
+    instance_attributes.push(wgpu::VertexAttribute { format: wgpu::VertexFormat::Float32, offset: 0, shader_location: 0 });
+
+    let buffers = [
+        wgpu::VertexBufferLayout { attributes: &attributes, ..model },
+        wgpu::VertexBufferLayout { attributes: &instance_attributes, ..instance },
+    ];
+    let message = format!("{:#}", shader.validate_vertex_buffers("vs_main", &buffers).unwrap_err());
+    for expected in [
+        "shader.wgsl: vertex buffer layout",
+        "location 2 (`normal`: vec3<f32>) does not match Float32x2 in buffer 0",
+        "buffer 0: locations 3 and 4 overlap",
+        "buffer 0 location 14: Uint32 at offset 56 ends at 60, past the array stride 56",
+        "location 0 is used by buffer 0 and buffer 1",
+        "location 12 (`tint`: vec4<f32>) is not provided by any vertex buffer",
+        "location 11 (`normal_matrix_2`: vec3<f32>) does not match Float32 in buffer 1",
+    ] {
+        assert!(message.contains(expected), "missing `{}` in:\n{}", expected, message);
+    }
+    assert_eq!(message.lines().count(), 8, "{}", message);
+}
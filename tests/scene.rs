@@ -0,0 +1,166 @@
+#[allow(dead_code)]
+mod common;
+
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+use wgpu_test::camera::Projection;
+use wgpu_test::scene::{Scene, Transform};
+
+fn assert_near(a: glam::Vec3, b: glam::Vec3) {
+    assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+}
+
+#[test]
+fn world_transforms_follow_parents() {
+    let mut scene = Scene::new();
+    let parent = scene.add_node(
+        "parent",
+        Transform::from_translation(glam::Vec3::new(1.0, 0.0, 0.0))
+            .with_rotation(glam::Quat::from_rotation_y(FRAC_PI_2))
+            .with_scale(glam::Vec3::splat(2.0)),
+    );
+    let child = scene.add_child(parent, "child", Transform::from_translation(glam::Vec3::new(0.0, 0.0, -1.0))).unwrap();
+    let grandchild = scene.add_child(child, "grandchild", Transform::from_translation(glam::Vec3::Y)).unwrap();
+    scene.update_world_transforms();
+
+    // 父节点绕 Y 旋转 90° 并放大 2 倍：子节点的 -Z 变成 -X，距离变成 2
+    let origin = |id| scene.node(id).unwrap().world_matrix().transform_point3(glam::Vec3::ZERO);
+    assert_near(origin(child), glam::Vec3::new(-1.0, 0.0, 0.0));
+    assert_near(origin(grandchild), glam::Vec3::new(-1.0, 2.0, 0.0));
+    assert_eq!(scene.find("grandchild"), Some(grandchild));
+}
+
+#[test]
+fn only_dirty_subtrees_are_recomputed() {
+    let mut scene = Scene::new();
+    let root = scene.add_node("root", Transform::default());
+    let a = scene.add_child(root, "a", Transform::default()).unwrap();
+    let b = scene.add_child(root, "b", Transform::default()).unwrap();
+    scene.add_child(a, "a1", Transform::default()).unwrap();
+    assert_eq!(scene.update_world_transforms(), 4);
+    assert_eq!(scene.update_world_transforms(), 0);
+
+    // 叶子节点只更新自己
+    scene.set_transform(b, Transform::from_translation(glam::Vec3::X)).unwrap();
+    assert_eq!(scene.update_world_transforms(), 1);
+
+    // 根节点的变化传播到整棵树
+    scene.set_transform(root, Transform::from_translation(glam::Vec3::Y)).unwrap();
+    assert_eq!(scene.update_world_transforms(), 4);
+    let a1 = scene.find("a1").unwrap();
+    assert_near(scene.node(a1).unwrap().world_matrix().transform_point3(glam::Vec3::ZERO), glam::Vec3::Y);
+}
+
+#[test]
+fn reparenting_and_removal() {
+    let mut scene = Scene::new();
+    let a = scene.add_node("a", Transform::from_translation(glam::Vec3::X));
+    let b = scene.add_node("b", Transform::from_translation(glam::Vec3::Z));
+    let c = scene.add_child(a, "c", Transform::default()).unwrap();
+
+    // 不能把节点挂到自己的后代下
+    assert!(scene.set_parent(a, Some(c)).is_err());
+    assert!(scene.set_parent(a, Some(a)).is_err());
+
+    scene.set_parent(c, Some(b)).unwrap();
+    assert_eq!(scene.node(a).unwrap().children(), &[]);
+    assert_eq!(scene.node(b).unwrap().children(), &[c]);
+    scene.update_world_transforms();
+    assert_near(scene.node(c).unwrap().world_matrix().transform_point3(glam::Vec3::ZERO), glam::Vec3::Z);
+
+    // 删除子树
+    scene.remove_node(b).unwrap();
+    assert!(scene.node(b).is_none());
+    assert!(scene.node(c).is_none());
+    assert_eq!(scene.roots(), &[a]);
+    assert!(scene.add_child(b, "orphan", Transform::default()).is_err());
+}
+
+#[test]
+fn camera_nodes_use_world_transform() {
+    let mut scene = Scene::new();
+    let rig = scene.add_node("rig", Transform::from_translation(glam::Vec3::new(0.0, 2.0, 0.0)));
+    let camera = scene
+        .add_child(rig, "camera", Transform::from_translation(glam::Vec3::new(0.0, 0.0, 5.0)))
+        .unwrap();
+    assert!(scene.camera(camera, 1.0).is_none());
+    scene.set_camera(camera, Some(Projection::default())).unwrap();
+    scene.update_world_transforms();
+
+    let camera = scene.camera(camera, 1.5).unwrap();
+    assert_near(camera.eye, glam::Vec3::new(0.0, 2.0, 5.0));
+    assert_near((camera.target - camera.eye).normalize(), glam::Vec3::NEG_Z);
+    assert_near(camera.up, glam::Vec3::Y);
+    assert_eq!(camera.aspect, 1.5);
+}
+
+// 由一个立方体模型拼成的桌子：桌面和四条腿都是桌子节点的子节点，整张桌子旋转后一起绘制
+#[test]
+fn scene_hierarchy() {
+    let mut state = common::headless_state();
+    state.set_instances(Vec::new());
+    let cube = pollster::block_on(state.load_scene_model("cube.obj")).unwrap();
+
+    let scene = state.scene_mut();
+    let table = scene.add_node(
+        "table",
+        Transform::default().with_rotation(glam::Quat::from_rotation_y(0.5)),
+    );
+    let top = scene
+        .add_child(
+            table,
+            "top",
+            Transform::from_translation(glam::Vec3::new(0.0, 1.0, 0.0)).with_scale(glam::Vec3::new(1.5, 0.1, 1.0)),
+        )
+        .unwrap();
+    scene.set_model(top, Some(cube)).unwrap();
+    for (x, z) in [(-1.2, -0.7), (1.2, -0.7), (-1.2, 0.7), (1.2, 0.7)] {
+        let leg = scene
+            .add_child(
+                table,
+                "leg",
+                Transform::from_translation(glam::Vec3::new(x, 0.0, z)).with_scale(glam::Vec3::new(0.1, 0.9, 0.1)),
+            )
+            .unwrap();
+        scene.set_model(leg, Some(cube)).unwrap();
+    }
+    let camera = scene.add_node(
+        "camera",
+        Transform::from_translation(glam::Vec3::new(0.0, 2.5, 5.0)).with_rotation(glam::Quat::from_rotation_x(-0.35)),
+    );
+    scene.set_camera(camera, Some(Projection::default())).unwrap();
+    assert!(state.use_scene_camera(camera));
+
+    state.update(Duration::ZERO);
+    common::assert_golden("scene_hierarchy", &state.capture_frame().unwrap());
+}
+
+// 父节点非等比缩放、子节点旋转得到带切变的世界矩阵：切线跟随模型矩阵，法线使用逆转置，
+// 两者保持垂直，法线贴图的光照不会歪
+#[test]
+fn nonuniform_scale_normal_map() {
+    let mut state = common::headless_state();
+    state.set_instances(Vec::new());
+    let cube = pollster::block_on(state.load_scene_model("cube.obj")).unwrap();
+
+    let scene = state.scene_mut();
+    let stretch = scene.add_node("stretch", Transform::default().with_scale(glam::Vec3::new(3.0, 1.0, 0.6)));
+    let tilted = scene
+        .add_child(
+            stretch,
+            "tilted",
+            Transform::default().with_rotation(glam::Quat::from_rotation_z(0.6) * glam::Quat::from_rotation_y(0.7)),
+        )
+        .unwrap();
+    scene.set_model(tilted, Some(cube)).unwrap();
+    let camera = state.camera_mut();
+    camera.eye = (4.0, 5.0, 10.0).into();
+    camera.target = glam::Vec3::ZERO;
+
+    state.update(Duration::ZERO);
+    common::assert_golden("nonuniform_scale_normal_map", &state.capture_frame().unwrap());
+    state.set_debug_view(wgpu_test::debug::DebugView::Tangents).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("nonuniform_scale_tangents", &state.capture_frame().unwrap());
+}
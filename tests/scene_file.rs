@@ -0,0 +1,194 @@
+#[allow(dead_code)]
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wgpu_test::scene_file::SceneFile;
+
+// 把场景文件写到临时目录，返回路径
+fn write_scene(name: &str, text: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("scenes");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+fn validation_error(text: &str) -> String {
+    let scene = SceneFile::parse(text, "test.ron").unwrap();
+    format!("{:#}", scene.validate().unwrap_err())
+}
+
+#[test]
+fn default_scene_reproduces_cube_grid() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/scenes/default.ron");
+    let scene = SceneFile::load(path).unwrap();
+    assert_eq!(scene.models.len(), 1);
+    assert_eq!(scene.models[0].file, "cube.obj");
+
+    let instances = scene.models[0].instances();
+    assert_eq!(instances.len(), 100);
+    // 第一行从 (-15, 0, -15) 开始，间隔 3
+    assert_eq!(instances[0].position, glam::Vec3::new(-15.0, 0.0, -15.0));
+    assert_eq!(instances[1].position, glam::Vec3::new(-12.0, 0.0, -15.0));
+    assert_eq!(instances[10].position, glam::Vec3::new(-15.0, 0.0, -12.0));
+    // 位于原点的实例不旋转，其余绕原点指向它的轴旋转 45°
+    assert_eq!(instances[55].position, glam::Vec3::ZERO);
+    assert_eq!(instances[55].rotation, glam::Quat::IDENTITY);
+    let expected = glam::Quat::from_axis_angle(instances[0].position.normalize(), std::f32::consts::FRAC_PI_4);
+    assert!(instances[0].rotation.abs_diff_eq(expected, 1e-6));
+}
+
+#[test]
+fn ron_and_json_are_equivalent() {
+    let ron = SceneFile::parse(
+        r#"(
+            clear_color: (0.0, 0.0, 0.0, 1.0),
+            lights: [Point(position: (1.0, 2.0, 3.0), range: 5.0)],
+            models: [(file: "cube.obj", instances: [(layout: Single(position: (1.0, 0.0, 0.0)), scale: (2.0, 2.0, 2.0))])],
+        )"#,
+        "scene.ron",
+    )
+    .unwrap();
+    let json = SceneFile::parse(
+        r#"{
+            "clear_color": [0.0, 0.0, 0.0, 1.0],
+            "lights": [{ "Point": { "position": [1.0, 2.0, 3.0], "range": 5.0 } }],
+            "models": [{ "file": "cube.obj", "instances": [{ "layout": { "Single": { "position": [1.0, 0.0, 0.0] } }, "scale": [2.0, 2.0, 2.0] }] }]
+        }"#,
+        "scene.json",
+    )
+    .unwrap();
+    assert_eq!(ron.models[0].instances(), json.models[0].instances());
+    assert_eq!(ron.models[0].instances()[0].scale, glam::Vec3::splat(2.0));
+    assert_eq!(ron.clear_color, json.clear_color);
+    assert_eq!(ron.lights.len(), json.lights.len());
+}
+
+#[test]
+fn scatter_is_deterministic() {
+    let scatter = |seed: u64| {
+        let text = format!(
+            "(models: [(file: \"cube.obj\", instances: [(layout: Scatter(count: 50, min: (-10.0, 0.0, -5.0), max: (10.0, 2.0, 5.0), seed: {}, random_yaw: true))])])",
+            seed
+        );
+        let scene = SceneFile::parse(&text, "scatter.ron").unwrap();
+        scene.validate().unwrap();
+        scene.models[0].instances()
+    };
+    let a = scatter(7);
+    assert_eq!(a.len(), 50);
+    assert_eq!(a, scatter(7));
+    assert_ne!(a, scatter(8));
+    for instance in &a {
+        let p = instance.position;
+        assert!(p.cmpge(glam::Vec3::new(-10.0, 0.0, -5.0)).all() && p.cmple(glam::Vec3::new(10.0, 2.0, 5.0)).all(), "{}", p);
+    }
+}
+
+#[test]
+fn validation_errors_point_to_entry() {
+    let message = validation_error(
+        r#"(
+            camera: (eye: (0.0, 1.0, 2.0), target: (0.0, 1.0, 2.0)),
+            lights: [Spot(position: (0.0, 0.0, 0.0), direction: (0.0, -1.0, 0.0), range: 10.0, inner_angle: 40.0, outer_angle: 30.0)],
+            models: [
+                (file: "cube.obj", instances: [
+                    (layout: Single(position: (0.0, 0.0, 0.0))),
+                    (layout: Grid(count: (0, 3), spacing: -1.0)),
+                ]),
+                (file: "cube.fbx"),
+            ],
+        )"#,
+    );
+    assert!(message.contains("camera: eye and target must not be the same point"), "{}", message);
+    assert!(message.contains("lights[0].inner_angle"), "{}", message);
+    assert!(message.contains("models[0].instances[1].layout.count"), "{}", message);
+    assert!(message.contains("models[0].instances[1].layout.spacing"), "{}", message);
+    assert!(message.contains("models[1].file: unsupported model format"), "{}", message);
+    assert!(!message.contains("instances[0]"), "{}", message);
+
+    let message = validation_error("(models: [])");
+    assert!(message.contains("models: at least one model is required"), "{}", message);
+
+    let message = validation_error(
+        r#"(models: [(file: "cube.obj", instances: [(layout: Single(position: (0.0, 0.0, 0.0)), scale: (1.0, 0.0, 1.0))])])"#,
+    );
+    assert!(message.contains("models[0].instances[0].scale"), "{}", message);
+}
+
+#[test]
+fn parse_errors_report_location() {
+    // 拼错的字段名
+    let error = SceneFile::parse("(\n    models: [],\n    clear_colour: (0.0, 0.0, 0.0, 1.0),\n)", "typo.ron").unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("3:"), "{}", message);
+    assert!(message.contains("clear_colour"), "{}", message);
+
+    let error = SceneFile::parse("{ \"models\": [ { \"file\": 1 } ] }", "bad.json").unwrap_err();
+    assert!(format!("{:#}", error).contains("line 1"), "{:#}", error);
+
+    assert!(SceneFile::parse("", "scene.yaml").is_err());
+}
+
+#[test]
+fn invalid_scene_keeps_current_scene() {
+    let mut state = common::headless_state();
+    let default_path = state.scene_path().to_path_buf();
+
+    // 材质覆盖要在模型加载之后才能检查
+    let path = write_scene(
+        "bad_material.ron",
+        r#"(models: [(file: "cube.obj", instances: [(layout: Single(position: (0.0, 0.0, 0.0)), material: 3)])])"#,
+    );
+    let error = pollster::block_on(state.load_scene_file(&path)).unwrap_err();
+    assert!(format!("{:#}", error).contains("models[0].instances[0].material: index 3 is out of range"), "{:#}", error);
+
+    let path = write_scene("missing_model.ron", r#"(models: [(file: "cube.obj"), (file: "missing.obj")])"#);
+    let error = pollster::block_on(state.load_scene_file(&path)).unwrap_err();
+    assert!(format!("{:#}", error).contains("models[1].file: failed to load \"missing.obj\""), "{:#}", error);
+
+    assert_eq!(state.model_count(), 1);
+    assert_eq!(state.instances().len(), 100);
+    assert_eq!(state.scene_path(), default_path);
+}
+
+// 两个模型：随机散布的立方体和一块着色的平面，摄像机、光源和清屏颜色都来自场景文件
+#[test]
+fn scene_file_render() {
+    let mut state = common::headless_state();
+    let path = write_scene(
+        "two_models.ron",
+        r#"(
+            clear_color: (0.02, 0.02, 0.05, 1.0),
+            camera: (eye: (0.0, 6.0, 10.0), target: (0.0, 0.0, 0.0)),
+            lights: [
+                Directional(direction: (-0.3, -1.0, -0.5), intensity: 0.8),
+                Point(position: (0.0, 2.0, 2.0), color: (1.0, 0.5, 0.2), intensity: 3.0, range: 6.0),
+            ],
+            models: [
+                (file: "cube.obj", instances: [
+                    (layout: Scatter(count: 12, min: (-4.0, 0.5, -4.0), max: (4.0, 1.5, 2.0), seed: 3, random_yaw: true), scale: (0.4, 0.4, 0.4)),
+                ]),
+                (file: "quad.obj", instances: [
+                    (layout: Single(position: (0.0, 0.0, 0.0)), rotation: Euler((-90.0, 0.0, 0.0)), scale: (6.0, 6.0, 6.0), tint: (0.6, 0.8, 0.6, 1.0)),
+                ]),
+            ],
+        )"#,
+    );
+    pollster::block_on(state.load_scene_file(&path)).unwrap();
+    assert_eq!(state.model_count(), 2);
+    assert_eq!(state.model_instances(1).unwrap().len(), 1);
+    assert_eq!(state.lights_mut().lights().len(), 2);
+
+    state.update(Duration::ZERO);
+    assert_eq!(state.culling_stats().total_instances, 13);
+    let frame = state.capture_frame().unwrap();
+    common::assert_golden("scene_file", &frame);
+
+    // 重新加载得到同样的画面
+    pollster::block_on(state.reload_scene()).unwrap();
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&frame, &state.capture_frame().unwrap()).differing_pixels, 0);
+}
@@ -0,0 +1,204 @@
+#[allow(dead_code)]
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wgpu_test::debug::DebugView;
+use wgpu_test::hot_reload::{validate_wgsl, ShaderWatcher};
+use wgpu_test::preprocessor::{ShaderDefines, ShaderLibrary};
+
+const SHADER: &str = include_str!("../src/shader.wgsl");
+const DEBUG_SHADER: &str = include_str!("../src/debug.wgsl");
+
+// 每个测试使用自己的着色器目录
+fn shader_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("shaders").join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    copy_shaders(&dir);
+    copy_includes(&dir);
+    dir
+}
+
+// src 下的全部着色器：主着色器、调试、阴影、光源剔除和实例剔除
+fn copy_shaders(dir: &Path) {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    for entry in std::fs::read_dir(src).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "wgsl") {
+            std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+}
+
+fn copy_includes(dir: &Path) {
+    let includes = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/include");
+    std::fs::create_dir_all(dir.join("include")).unwrap();
+    for entry in std::fs::read_dir(includes).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.join("include").join(path.file_name().unwrap())).unwrap();
+    }
+}
+
+fn cube_grid_state() -> wgpu_test::State {
+    let mut state = common::headless_state();
+    let camera = state.camera_mut();
+    camera.eye = (0.0, 12.0, 24.0).into();
+    camera.target = glam::Vec3::ZERO;
+    state
+}
+
+// 片元着色器直接输出纯色
+fn solid_color_shader() -> String {
+    let shader = SHADER.replace("fn fs_main(in: VertexOutput) -> @location(0) vec4f {", "fn fs_main(in: VertexOutput) -> @location(0) vec4f {\n    if true { return vec4f(1.0, 0.0, 0.0, 1.0); }");
+    assert_ne!(shader, SHADER, "fs_main signature changed");
+    shader
+}
+
+#[test]
+fn errors_report_file_and_line() {
+    let path = Path::new("shaders/broken.wgsl");
+    let error = validate_wgsl("@vertex\nfn vs_main() -> @builtin(position) vec4f {\n    return vec4f(1.0)\n}\n", path, &[]).unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("shaders/broken.wgsl:4:1"), "{}", message);
+
+    // 类型错误由验证器报告，附带出错的源码行
+    let error = validate_wgsl("fn f() -> f32 {\n    return 1u;\n}\n", path, &[]).unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("shaders/broken.wgsl:1:1") && message.contains("2 │ │     return 1u;"), "{}", message);
+
+    let library = ShaderLibrary::embedded();
+    let shader = library.preprocess("shader.wgsl", &ShaderDefines::from_iter(["NORMAL_MAP", "SHADOWS"])).unwrap();
+    let error = shader.validate(&library, &["vs_main", "fs_missing"]).unwrap_err();
+    assert!(format!("{:#}", error).contains("missing entry point `fs_missing`"), "{:#}", error);
+    shader.validate(&library, &["vs_main", "fs_main"]).unwrap();
+}
+
+#[test]
+fn watcher_reports_modified_files() {
+    let dir = shader_dir("watcher");
+    let path = dir.join("shader.wgsl");
+    let mut watcher = ShaderWatcher::new([path.clone(), dir.join("missing.wgsl")]);
+    assert!(watcher.poll().is_empty());
+
+    // 部分文件系统的时间戳精度较低，等一会儿再写入
+    std::thread::sleep(Duration::from_millis(20));
+    std::fs::write(&path, solid_color_shader()).unwrap();
+    assert_eq!(watcher.poll(), vec![path]);
+    assert!(watcher.poll().is_empty());
+}
+
+#[test]
+fn hot_reload_keeps_last_good_pipeline() {
+    let dir = shader_dir("reload");
+    let mut state = cube_grid_state();
+    assert!(state.enable_shader_hot_reload(dir.join("missing")).is_err());
+    assert!(state.reload_shaders().is_err(), "hot reload is not enabled");
+
+    state.enable_shader_hot_reload(&dir).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+
+    // 修改后在下一次 update 时重新加载
+    std::thread::sleep(Duration::from_millis(20));
+    std::fs::write(dir.join("shader.wgsl"), solid_color_shader()).unwrap();
+    state.update(Duration::ZERO);
+    let red = state.capture_frame().unwrap();
+    let center = red.get_pixel(common::WIDTH / 2, common::HEIGHT * 3 / 4);
+    assert_eq!(center.0, [255, 0, 0, 255]);
+
+    // 语法错误：保留纯色的管线，错误信息指向出错的文件和行
+    let broken = solid_color_shader().replace("let world_position = model_matrix", "let world_position = model_matrix +");
+    std::fs::write(dir.join("shader.wgsl"), broken).unwrap();
+    let message = format!("{:#}", state.reload_shaders().unwrap_err());
+    let shader_path = dir.join("shader.wgsl").display().to_string();
+    assert!(message.contains(&format!("{}:", shader_path)), "{}", message);
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&red, &state.capture_frame().unwrap()).differing_pixels, 0);
+
+    // 能通过 naga 验证、但与管线布局不一致的着色器同样被拒绝
+    let mismatched = solid_color_shader().replace("@group(1) @binding(0)\nvar<uniform> camera", "@group(1) @binding(5)\nvar<uniform> camera");
+    std::fs::write(dir.join("shader.wgsl"), mismatched).unwrap();
+    let message = format!("{:#}", state.reload_shaders().unwrap_err());
+    assert!(message.contains("failed to create render pipeline"), "{}", message);
+    state.update(Duration::ZERO);
+    assert_eq!(common::compare(&red, &state.capture_frame().unwrap()).differing_pixels, 0);
+
+    // 修正后恢复
+    std::fs::write(dir.join("shader.wgsl"), SHADER).unwrap();
+    state.reload_shaders().unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+}
+
+// 修改 include 的文件同样触发重新加载，错误指向被包含的文件
+#[test]
+fn include_changes_trigger_reload() {
+    let dir = shader_dir("include");
+    let mut state = cube_grid_state();
+    state.enable_shader_hot_reload(&dir).unwrap();
+    let instance = dir.join("include/instance.wgsl");
+    let source = std::fs::read_to_string(&instance).unwrap();
+
+    std::thread::sleep(Duration::from_millis(20));
+    std::fs::write(&instance, source.replace("        instance.model_matrix_3\n", "        instance.model_matrix_3 +\n")).unwrap();
+    let message = format!("{:#}", state.reload_shaders().unwrap_err());
+    assert!(message.contains(&format!("{}:", instance.display())), "{}", message);
+
+    // 修正后下一次 update 自动恢复
+    std::thread::sleep(Duration::from_millis(20));
+    std::fs::write(&instance, &source).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("cube_grid", &state.capture_frame().unwrap());
+}
+
+#[test]
+fn debug_shader_reload() {
+    let dir = shader_dir("debug");
+    let mut state = cube_grid_state();
+    state.enable_shader_hot_reload(&dir).unwrap();
+    state.set_debug_view(DebugView::Normals).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("debug_normals", &state.capture_frame().unwrap());
+
+    // 缺少某个视图的入口点时整个着色器被拒绝，切换到那个视图仍然可用
+    std::fs::write(dir.join("debug.wgsl"), DEBUG_SHADER.replace("fn fs_tangents(", "fn fs_tangents_renamed(")).unwrap();
+    let message = format!("{:#}", state.reload_shaders().unwrap_err());
+    assert!(message.contains("missing entry point `fs_tangents`"), "{}", message);
+    state.set_debug_view(DebugView::Tangents).unwrap();
+    state.update(Duration::ZERO);
+    common::assert_golden("debug_tangents", &state.capture_frame().unwrap());
+}
+
+// 阴影和两个剔除着色器同样从目录中加载并热重载，出错时保留原来的管线
+#[test]
+fn pass_shaders_reload() {
+    let dir = shader_dir("passes");
+    let mut state = cube_grid_state();
+    state.enable_shader_hot_reload(&dir).unwrap();
+    state.set_gpu_culling(true);
+    state.update(Duration::ZERO);
+    let original = state.capture_frame().unwrap();
+
+    // 把所有顶点移出阴影贴图，画面中不再有阴影
+    let shadow = dir.join("shadow.wgsl");
+    let source = std::fs::read_to_string(&shadow).unwrap();
+    std::fs::write(&shadow, source.replace("return light_view_proj *", "return vec4f(2.0, 2.0, 2.0, 1.0) + 0.0 * light_view_proj *")).unwrap();
+    state.reload_shaders().unwrap();
+    state.update(Duration::ZERO);
+    assert!(common::compare(&original, &state.capture_frame().unwrap()).differing_pixels > 0);
+    std::fs::write(&shadow, &source).unwrap();
+    state.reload_shaders().unwrap();
+
+    for file in ["shadow.wgsl", "light_cull.wgsl", "instance_cull.wgsl"] {
+        let path = dir.join(file);
+        let source = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, source.replacen("fn ", "fn broken(", 1)).unwrap();
+        let message = format!("{:#}", state.reload_shaders().unwrap_err());
+        assert!(message.contains(&format!("{}:", path.display())), "{}", message);
+        state.update(Duration::ZERO);
+        assert_eq!(common::compare(&original, &state.capture_frame().unwrap()).differing_pixels, 0, "{}", file);
+        std::fs::write(&path, &source).unwrap();
+    }
+    state.reload_shaders().unwrap();
+}